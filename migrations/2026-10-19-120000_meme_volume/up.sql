@@ -0,0 +1,3 @@
+ALTER TABLE memes ADD COLUMN volume REAL NOT NULL DEFAULT 1.0;
+
+ALTER TABLE memes ADD CONSTRAINT memes_volume_nonnegative CHECK (volume >= 0);
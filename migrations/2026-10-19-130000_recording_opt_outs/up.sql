@@ -0,0 +1,4 @@
+CREATE TABLE recording_opt_outs (
+    user_id     BIGINT PRIMARY KEY,
+    created     TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
@@ -0,0 +1 @@
+ALTER TABLE audit_records ADD COLUMN description TEXT;
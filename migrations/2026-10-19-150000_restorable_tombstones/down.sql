@@ -0,0 +1,10 @@
+DROP INDEX tombstones_audio;
+DROP INDEX tombstones_image;
+DROP INDEX tombstones_title;
+DROP INDEX tombstones_deleted_at;
+
+ALTER TABLE tombstones DROP COLUMN volume;
+ALTER TABLE tombstones DROP COLUMN audio_id;
+ALTER TABLE tombstones DROP COLUMN image_id;
+ALTER TABLE tombstones DROP COLUMN content;
+ALTER TABLE tombstones DROP COLUMN title;
@@ -0,0 +1,10 @@
+ALTER TABLE tombstones ADD COLUMN title VARCHAR;
+ALTER TABLE tombstones ADD COLUMN content TEXT;
+ALTER TABLE tombstones ADD COLUMN image_id INTEGER REFERENCES images;
+ALTER TABLE tombstones ADD COLUMN audio_id INTEGER REFERENCES audio;
+ALTER TABLE tombstones ADD COLUMN volume REAL NOT NULL DEFAULT 1.0;
+
+CREATE INDEX tombstones_deleted_at ON tombstones (deleted_at);
+CREATE INDEX tombstones_title ON tombstones (title);
+CREATE INDEX tombstones_image ON tombstones (image_id);
+CREATE INDEX tombstones_audio ON tombstones (audio_id);
@@ -0,0 +1 @@
+DROP TABLE meme_tags;
@@ -0,0 +1,9 @@
+-- no foreign key on meme_id: tags outlive a soft delete so that undeleting a meme restores them.
+CREATE TABLE meme_tags (
+    meme_id INTEGER NOT NULL,
+    tag     VARCHAR NOT NULL,
+
+    PRIMARY KEY (meme_id, tag)
+);
+
+CREATE INDEX meme_tags_tag ON meme_tags (tag);
@@ -0,0 +1,7 @@
+DROP TRIGGER memes_title_namespace ON memes;
+DROP FUNCTION check_meme_title();
+
+DROP TRIGGER meme_aliases_namespace ON meme_aliases;
+DROP FUNCTION check_meme_alias();
+
+DROP TABLE meme_aliases;
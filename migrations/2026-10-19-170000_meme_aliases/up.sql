@@ -0,0 +1,42 @@
+-- as with tags, no foreign key on meme_id so aliases survive a soft delete.
+CREATE TABLE meme_aliases (
+    alias      VARCHAR PRIMARY KEY,
+    meme_id    INTEGER NOT NULL,
+    created_by BIGINT NOT NULL,
+    created    TIMESTAMP NOT NULL DEFAULT current_timestamp
+);
+
+CREATE INDEX meme_aliases_meme ON meme_aliases (meme_id);
+
+-- titles and aliases share a namespace: an alias can't shadow a meme's title, and a meme can't take
+-- a title that's already an alias of some other live meme.
+CREATE FUNCTION check_meme_alias() RETURNS trigger AS $$
+BEGIN
+    IF EXISTS (SELECT 1 FROM memes WHERE title = NEW.alias) THEN
+        RAISE unique_violation USING MESSAGE = format('"%s" is already a meme title', NEW.alias);
+    END IF;
+
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER meme_aliases_namespace BEFORE INSERT OR UPDATE ON meme_aliases
+    FOR EACH ROW EXECUTE PROCEDURE check_meme_alias();
+
+CREATE FUNCTION check_meme_title() RETURNS trigger AS $$
+BEGIN
+    IF EXISTS (
+        SELECT 1
+        FROM meme_aliases
+        INNER JOIN memes ON memes.id = meme_aliases.meme_id
+        WHERE meme_aliases.alias = NEW.title AND meme_aliases.meme_id <> NEW.id
+    ) THEN
+        RAISE unique_violation USING MESSAGE = format('"%s" is already a meme alias', NEW.title);
+    END IF;
+
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER memes_title_namespace BEFORE INSERT OR UPDATE OF title ON memes
+    FOR EACH ROW EXECUTE PROCEDURE check_meme_title();
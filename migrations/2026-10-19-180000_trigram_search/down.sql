@@ -0,0 +1,3 @@
+DROP INDEX meme_aliases_alias_trgm;
+DROP INDEX memes_content_trgm;
+DROP INDEX memes_title_trgm;
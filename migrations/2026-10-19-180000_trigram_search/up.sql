@@ -0,0 +1,5 @@
+CREATE EXTENSION IF NOT EXISTS pg_trgm;
+
+CREATE INDEX memes_title_trgm ON memes USING GIN (title gin_trgm_ops);
+CREATE INDEX memes_content_trgm ON memes USING GIN (content gin_trgm_ops);
+CREATE INDEX meme_aliases_alias_trgm ON meme_aliases USING GIN (alias gin_trgm_ops);
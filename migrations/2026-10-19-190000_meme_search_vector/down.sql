@@ -0,0 +1,6 @@
+DROP INDEX memes_search_vector_idx;
+
+DROP TRIGGER memes_search_vector ON memes;
+DROP FUNCTION memes_search_vector_update();
+
+ALTER TABLE memes DROP COLUMN search_vector;
@@ -0,0 +1,22 @@
+-- maintained by trigger rather than as a generated column so this works on postgres < 12. not
+-- exposed through diesel's schema (no tsvector type there); only raw queries use it.
+ALTER TABLE memes ADD COLUMN search_vector TSVECTOR;
+
+CREATE FUNCTION memes_search_vector_update() RETURNS trigger AS $$
+BEGIN
+    NEW.search_vector :=
+        setweight(to_tsvector('english', COALESCE(NEW.title, '')), 'A') ||
+        setweight(to_tsvector('english', COALESCE(NEW.content, '')), 'B');
+
+    RETURN NEW;
+END
+$$ LANGUAGE plpgsql;
+
+CREATE TRIGGER memes_search_vector BEFORE INSERT OR UPDATE OF title, content ON memes
+    FOR EACH ROW EXECUTE PROCEDURE memes_search_vector_update();
+
+UPDATE memes SET search_vector =
+    setweight(to_tsvector('english', COALESCE(title, '')), 'A') ||
+    setweight(to_tsvector('english', COALESCE(content, '')), 'B');
+
+CREATE INDEX memes_search_vector_idx ON memes USING GIN (search_vector);
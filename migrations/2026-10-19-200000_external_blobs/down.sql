@@ -0,0 +1,3 @@
+-- blobs have to be moved back into the database before this can succeed.
+ALTER TABLE audio ALTER COLUMN data SET NOT NULL;
+ALTER TABLE images ALTER COLUMN data SET NOT NULL;
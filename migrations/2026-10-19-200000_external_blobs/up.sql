@@ -0,0 +1,3 @@
+-- a NULL data column means the blob has moved to the blob store, keyed by data_hash.
+ALTER TABLE images ALTER COLUMN data DROP NOT NULL;
+ALTER TABLE audio ALTER COLUMN data DROP NOT NULL;
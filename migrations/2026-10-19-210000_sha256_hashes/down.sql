@@ -0,0 +1,12 @@
+-- fails if any rows were created with only a sha256 hash.
+ALTER TABLE audio   DROP CONSTRAINT audio_has_hash;
+ALTER TABLE images  DROP CONSTRAINT image_has_hash;
+
+ALTER TABLE audio   ALTER COLUMN data_hash SET NOT NULL;
+ALTER TABLE images  ALTER COLUMN data_hash SET NOT NULL;
+
+DROP INDEX audio_sha256;
+DROP INDEX image_sha256;
+
+ALTER TABLE audio   DROP COLUMN sha256;
+ALTER TABLE images  DROP COLUMN sha256;
@@ -0,0 +1,21 @@
+-- sha256 takes over from sha1 (data_hash) for dedup and blob keys. rows whose data is still in the
+-- database are backfilled here; rows already moved to the blob store are backfilled by the
+-- `checkhashes` command, since their data isn't available to sql.
+ALTER TABLE audio   ADD COLUMN sha256 bytea;
+ALTER TABLE images  ADD COLUMN sha256 bytea;
+
+UPDATE audio    SET sha256 = digest(data, 'sha256') WHERE data IS NOT NULL;
+UPDATE images   SET sha256 = digest(data, 'sha256') WHERE data IS NOT NULL;
+
+ALTER TABLE audio   ADD CONSTRAINT audio_sha256_valid CHECK (data IS NULL OR sha256 = digest(data, 'sha256'));
+ALTER TABLE images  ADD CONSTRAINT image_sha256_valid CHECK (data IS NULL OR sha256 = digest(data, 'sha256'));
+
+CREATE UNIQUE INDEX audio_sha256 ON audio     (sha256);
+CREATE UNIQUE INDEX image_sha256 ON images    (sha256);
+
+-- new rows only carry sha256
+ALTER TABLE audio   ALTER COLUMN data_hash DROP NOT NULL;
+ALTER TABLE images  ALTER COLUMN data_hash DROP NOT NULL;
+
+ALTER TABLE audio   ADD CONSTRAINT audio_has_hash CHECK (data_hash IS NOT NULL OR sha256 IS NOT NULL);
+ALTER TABLE images  ADD CONSTRAINT image_has_hash CHECK (data_hash IS NOT NULL OR sha256 IS NOT NULL);
@@ -0,0 +1,4 @@
+-- perceptual hashes for near-duplicate detection: a 64-bit dHash per image and the raw chromaprint
+-- fingerprint (from fpcalc) per audio clip. NULL until computed.
+ALTER TABLE images  ADD COLUMN phash bigint;
+ALTER TABLE audio   ADD COLUMN fingerprint integer[];
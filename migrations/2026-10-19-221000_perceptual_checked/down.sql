@@ -0,0 +1,2 @@
+ALTER TABLE images  DROP COLUMN perceptual_checked;
+ALTER TABLE audio   DROP COLUMN perceptual_checked;
@@ -0,0 +1,7 @@
+-- whether a perceptual hash has been attempted, so media that can't be hashed (videos, or audio
+-- fpcalc chokes on) isn't fetched again on every backfill.
+ALTER TABLE images  ADD COLUMN perceptual_checked BOOLEAN NOT NULL DEFAULT FALSE;
+ALTER TABLE audio   ADD COLUMN perceptual_checked BOOLEAN NOT NULL DEFAULT FALSE;
+
+UPDATE images   SET perceptual_checked = TRUE WHERE phash IS NOT NULL;
+UPDATE audio    SET perceptual_checked = TRUE WHERE fingerprint IS NOT NULL;
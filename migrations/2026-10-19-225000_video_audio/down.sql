@@ -0,0 +1,2 @@
+ALTER TABLE memes ADD CONSTRAINT text_memes_image_or_audio_null
+    CHECK (image_id IS NULL OR audio_id IS NULL);
@@ -0,0 +1,2 @@
+-- videos keep their sound as an audio track, so a meme can now have both an image and audio.
+ALTER TABLE memes DROP CONSTRAINT text_memes_image_or_audio_null;
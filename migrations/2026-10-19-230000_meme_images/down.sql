@@ -0,0 +1,16 @@
+-- only the first image of each album survives.
+ALTER TABLE memes       ADD COLUMN image_id INTEGER REFERENCES images;
+ALTER TABLE tombstones  ADD COLUMN image_id INTEGER REFERENCES images;
+
+UPDATE memes SET image_id = meme_images.image_id
+FROM meme_images
+WHERE meme_images.meme_id = memes.id AND meme_images.position = 0;
+
+UPDATE tombstones SET image_id = meme_images.image_id
+FROM meme_images
+WHERE meme_images.meme_id = tombstones.meme_id AND meme_images.position = 0;
+
+CREATE INDEX memes_image        ON memes (image_id);
+CREATE INDEX tombstones_image   ON tombstones (image_id);
+
+DROP TABLE meme_images;
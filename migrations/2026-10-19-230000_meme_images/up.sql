@@ -0,0 +1,22 @@
+-- a meme's images, in the order they're posted. like tags, rows outlive soft deletion so a meme
+-- comes back with its album; purging the tombstone removes them.
+CREATE TABLE meme_images (
+    meme_id     INTEGER NOT NULL,
+    position    INTEGER NOT NULL,
+    image_id    INTEGER NOT NULL REFERENCES images,
+    PRIMARY KEY (meme_id, position)
+);
+
+CREATE INDEX meme_images_image ON meme_images (image_id);
+
+INSERT INTO meme_images (meme_id, position, image_id)
+SELECT id, 0, image_id FROM memes WHERE image_id IS NOT NULL;
+
+INSERT INTO meme_images (meme_id, position, image_id)
+SELECT meme_id, 0, image_id FROM tombstones WHERE image_id IS NOT NULL
+ON CONFLICT DO NOTHING;
+
+-- this also drops the check that a meme has some content, which can't be expressed across tables
+-- and is enforced by the bot.
+ALTER TABLE memes       DROP COLUMN image_id;
+ALTER TABLE tombstones  DROP COLUMN image_id;
@@ -0,0 +1,93 @@
+use std::{
+    io::{
+        Read,
+        Write,
+    },
+    process::{
+        Command,
+        Stdio,
+    },
+    thread,
+};
+
+use anyhow::anyhow;
+use lazy_static::lazy_static;
+use log::error;
+
+use crate::Result;
+
+/// How much of a clip to fingerprint. Near-duplicates almost always share their opening.
+const FINGERPRINT_SECONDS: usize = 30;
+
+lazy_static! {
+    static ref FPCALC_AVAILABLE: bool = Command::new("fpcalc")
+        .arg("-version")
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()
+        .map_or(false, |status| status.success());
+}
+
+/// Whether `fpcalc` is installed, as checked the first time this is asked.
+pub fn fpcalc_available() -> bool {
+    *FPCALC_AVAILABLE
+}
+
+/// The raw chromaprint fingerprint of an audio clip (in any format ffmpeg understands), as
+/// computed by `fpcalc`. Each item covers roughly an eighth of a second.
+pub fn fingerprint(data: &[u8]) -> Result<Vec<u32>> {
+    let mut fpcalc = Command::new("fpcalc")
+        .args(&[
+            "-raw",
+            "-plain",
+            "-length", &FINGERPRINT_SECONDS.to_string(),
+            "-",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let data = data.to_vec();
+    let mut stdin = fpcalc.stdin.take().unwrap();
+
+    thread::spawn(move || {
+        if let Err(e) = stdin.write_all(&data) {
+            error!("writing audio to fpcalc: {}", e);
+        }
+    });
+
+    let mut output = String::new();
+    fpcalc.stdout.take().unwrap().read_to_string(&mut output)?;
+
+    let status = fpcalc.wait()?;
+    if !status.success() {
+        return Err(anyhow!("fpcalc exited with {}", status));
+    }
+
+    parse_raw(&output)
+}
+
+fn parse_raw(output: &str) -> Result<Vec<u32>> {
+    let output = output.trim();
+
+    if output.is_empty() {
+        return Err(anyhow!("fpcalc produced no fingerprint"));
+    }
+
+    output.split(',')
+        .map(|item| item.trim().parse::<u32>().map_err(|e| anyhow!("bad fingerprint item '{}': {}", item, e)))
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_raw() {
+        assert_eq!(vec![1, 4294967295, 3], parse_raw("1,4294967295,3\n").unwrap());
+        assert!(parse_raw("").is_err());
+        assert!(parse_raw("1,-2").is_err());
+    }
+}
@@ -0,0 +1,315 @@
+use std::{
+    io::{
+        self,
+        ErrorKind,
+        Read,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use super::{
+    BYTES_PER_SAMPLE,
+    CHANNELS,
+    SAMPLE_RATE,
+};
+
+/// Time constant of volume ramps.
+const RAMP_SECONDS: f32 = 0.08;
+
+/// Gain under which a fade to silence counts as finished.
+const SILENCE_THRESHOLD: f32 = 1e-3;
+
+/// Bytes of PCM processed per chunk (20ms, the same as an opus frame).
+const CHUNK_BYTES: usize = SAMPLE_RATE / 50 * CHANNELS * BYTES_PER_SAMPLE;
+
+const FRAME_BYTES: usize = CHANNELS * BYTES_PER_SAMPLE;
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Duck {
+    pub level: f32,
+    pub until: Instant,
+}
+
+impl Duck {
+    pub fn new(level: f32, duration: Duration) -> Self {
+        Duck {
+            level,
+            until: Instant::now() + duration,
+        }
+    }
+
+    #[inline]
+    pub fn active(&self, now: Instant) -> bool {
+        now < self.until
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+enum Fade {
+    In,
+    Pause,
+    Stop,
+}
+
+#[derive(Clone, Debug)]
+struct GainState {
+    current: f32,
+    volume: f32,
+    duck: Option<Duck>,
+    fade: Fade,
+}
+
+impl GainState {
+    fn target(&self, now: Instant) -> f32 {
+        match self.fade {
+            Fade::Pause | Fade::Stop => 0.0,
+            Fade::In => {
+                let duck = self.duck
+                    .filter(|d| d.active(now))
+                    .map_or(1.0, |d| d.level);
+
+                self.volume * duck
+            },
+        }
+    }
+
+    #[inline]
+    fn silent(&self) -> bool {
+        self.current < SILENCE_THRESHOLD
+    }
+}
+
+/// Shared handle controlling the gain applied by a [`GainReader`]. Every change is ramped rather
+/// than applied instantly, so volume changes, pauses and skips never cut mid-waveform.
+#[derive(Debug)]
+pub struct GainControl(Mutex<GainState>);
+
+impl GainControl {
+    /// Playback starts silent and fades in to `volume`.
+    pub fn new(volume: f32, duck: Option<Duck>) -> Arc<Self> {
+        Arc::new(GainControl(Mutex::new(GainState {
+            current: 0.0,
+            volume,
+            duck,
+            fade: Fade::In,
+        })))
+    }
+
+    pub fn set_volume(&self, volume: f32) {
+        self.0.lock().unwrap().volume = volume;
+    }
+
+    pub fn duck(&self, duck: Option<Duck>) {
+        self.0.lock().unwrap().duck = duck;
+    }
+
+    /// Fade to silence, then hold there until resumed.
+    pub fn pause(&self) {
+        let mut state = self.0.lock().unwrap();
+
+        if state.fade == Fade::In {
+            state.fade = Fade::Pause;
+        }
+    }
+
+    pub fn resume(&self) {
+        let mut state = self.0.lock().unwrap();
+
+        if state.fade == Fade::Pause {
+            state.fade = Fade::In;
+        }
+    }
+
+    /// Fade to silence, then end the stream.
+    pub fn stop(&self) {
+        self.0.lock().unwrap().fade = Fade::Stop;
+    }
+
+    #[inline]
+    pub fn paused(&self) -> bool {
+        self.0.lock().unwrap().fade == Fade::Pause
+    }
+
+    /// Whether a pause has finished fading out, i.e. the underlying audio can actually be paused.
+    pub fn faded_out(&self) -> bool {
+        let state = self.0.lock().unwrap();
+        state.fade == Fade::Pause && state.silent()
+    }
+
+    fn apply(&self, pcm: &mut [u8]) {
+        let mut state = self.0.lock().unwrap();
+
+        let target = state.target(Instant::now());
+        let alpha = 1.0 - (-1.0 / (RAMP_SECONDS * SAMPLE_RATE as f32)).exp();
+
+        for frame in pcm.chunks_exact_mut(FRAME_BYTES) {
+            state.current += (target - state.current) * alpha;
+
+            for sample in frame.chunks_exact_mut(BYTES_PER_SAMPLE) {
+                let value = i16::from_le_bytes([sample[0], sample[1]]) as f32 * state.current;
+                let value = value.clamp(i16::MIN as f32, i16::MAX as f32) as i16;
+
+                sample.copy_from_slice(&value.to_le_bytes());
+            }
+        }
+
+        if target == 0.0 && state.silent() {
+            state.current = 0.0;
+        }
+    }
+}
+
+/// Applies a [`GainControl`] to a stream of interleaved s16le PCM.
+pub struct GainReader<R> {
+    inner: R,
+    control: Arc<GainControl>,
+    buf: Vec<u8>,
+    pos: usize,
+}
+
+impl<R: Read> GainReader<R> {
+    pub fn new(inner: R, control: Arc<GainControl>) -> Self {
+        GainReader {
+            inner,
+            control,
+            buf: Vec::with_capacity(CHUNK_BYTES),
+            pos: 0,
+        }
+    }
+
+    /// Load and process the next chunk. Returns `false` at the end of the stream.
+    fn fill(&mut self) -> io::Result<bool> {
+        self.buf.clear();
+        self.pos = 0;
+
+        let (fade, silent) = {
+            let state = self.control.0.lock().unwrap();
+            (state.fade, state.silent())
+        };
+
+        match fade {
+            Fade::Stop if silent => return Ok(false),
+            Fade::Pause if silent => {
+                self.buf.resize(CHUNK_BYTES, 0);
+                return Ok(true);
+            },
+            _ => {},
+        }
+
+        self.buf.resize(CHUNK_BYTES, 0);
+
+        let mut filled = 0;
+        while filled < CHUNK_BYTES {
+            match self.inner.read(&mut self.buf[filled..]) {
+                Ok(0) => break,
+                Ok(n) => filled += n,
+                Err(ref e) if e.kind() == ErrorKind::Interrupted => {},
+                Err(e) => return Err(e),
+            }
+        }
+
+        self.buf.truncate(filled - filled % FRAME_BYTES);
+
+        if self.buf.is_empty() {
+            return Ok(false);
+        }
+
+        self.control.apply(&mut self.buf);
+
+        Ok(true)
+    }
+}
+
+impl<R: Read> Read for GainReader<R> {
+    fn read(&mut self, out: &mut [u8]) -> io::Result<usize> {
+        if self.pos >= self.buf.len() && !self.fill()? {
+            return Ok(0);
+        }
+
+        let n = out.len().min(self.buf.len() - self.pos);
+        out[..n].copy_from_slice(&self.buf[self.pos..self.pos + n]);
+        self.pos += n;
+
+        Ok(n)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::io::Cursor;
+
+    use super::*;
+
+    fn pcm(samples: usize, value: i16) -> Vec<u8> {
+        (0..samples).flat_map(|_| value.to_le_bytes().to_vec()).collect()
+    }
+
+    fn samples(bytes: &[u8]) -> Vec<i16> {
+        bytes.chunks_exact(2).map(|b| i16::from_le_bytes([b[0], b[1]])).collect()
+    }
+
+    #[test]
+    fn test_fade_in() {
+        let control = GainControl::new(1.0, None);
+        let mut reader = GainReader::new(Cursor::new(pcm(SAMPLE_RATE * CHANNELS, 10000)), control);
+
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+
+        let out = samples(&out);
+        assert_eq!(SAMPLE_RATE * CHANNELS, out.len());
+        assert!(out[0] < 100);
+        assert!(out[out.len() - 1] > 9900);
+        assert!(out.windows(2).all(|w| w[0] <= w[1]));
+    }
+
+    #[test]
+    fn test_stop_ends_stream() {
+        let control = GainControl::new(1.0, None);
+        let mut reader = GainReader::new(Cursor::new(pcm(SAMPLE_RATE * CHANNELS * 10, 10000)), control.clone());
+
+        let mut buf = vec![0u8; CHUNK_BYTES];
+        reader.read_exact(&mut buf).unwrap();
+
+        control.stop();
+
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+
+        assert!(out.len() < SAMPLE_RATE * CHANNELS * BYTES_PER_SAMPLE);
+        assert!(samples(&out).last().map_or(true, |s| s.abs() < 100));
+    }
+
+    #[test]
+    fn test_pause_holds_silence() {
+        let control = GainControl::new(1.0, None);
+        let mut reader = GainReader::new(Cursor::new(pcm(SAMPLE_RATE * CHANNELS * 10, 10000)), control.clone());
+
+        control.pause();
+
+        let mut buf = vec![0u8; SAMPLE_RATE * CHANNELS * BYTES_PER_SAMPLE];
+        reader.read_exact(&mut buf).unwrap();
+
+        assert!(control.faded_out());
+        assert!(samples(&buf[buf.len() - CHUNK_BYTES..]).iter().all(|s| *s == 0));
+    }
+
+    #[test]
+    fn test_duck() {
+        let control = GainControl::new(1.0, Some(Duck::new(0.5, Duration::from_secs(60))));
+        let mut reader = GainReader::new(Cursor::new(pcm(SAMPLE_RATE * CHANNELS, 10000)), control);
+
+        let mut out = Vec::new();
+        reader.read_to_end(&mut out).unwrap();
+
+        let last = *samples(&out).last().unwrap();
+        assert!(last > 4900 && last <= 5000);
+    }
+}
@@ -0,0 +1,255 @@
+use std::{
+    env,
+    fs,
+    path::{
+        Path,
+        PathBuf,
+    },
+    process::{
+        Command,
+        Stdio,
+    },
+    sync::atomic::{
+        AtomicUsize,
+        Ordering,
+    },
+};
+
+use anyhow::anyhow;
+use log::debug;
+
+use crate::Result;
+
+pub const VIDEO_EXTENSIONS: &[&str] = &["mp4", "webm", "mov", "mkv", "gif"];
+
+/// Discord voice messages are ogg.
+const AUDIO_EXTENSIONS: &[&str] = &["mp3", "ogg", "oga", "opus", "wav", "flac", "m4a", "aac"];
+
+/// Whether an attachment should be treated as a video meme, going by its filename.
+pub fn is_video(filename: &str) -> bool {
+    extension(filename).map_or(false, |ext| VIDEO_EXTENSIONS.contains(&ext.as_str()))
+}
+
+/// Whether an attachment is a sound file, going by its filename.
+pub fn is_audio(filename: &str) -> bool {
+    extension(filename).map_or(false, |ext| AUDIO_EXTENSIONS.contains(&ext.as_str()))
+}
+
+#[inline]
+pub fn is_gif(filename: &str) -> bool {
+    extension(filename).map_or(false, |ext| ext == "gif")
+}
+
+fn extension(filename: &str) -> Option<String> {
+    Path::new(filename).extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| ext.to_lowercase())
+}
+
+/// `filename` with its extension replaced by `.mp4`.
+pub fn mp4_filename(filename: &str) -> String {
+    let stem = Path::new(filename).file_stem()
+        .and_then(|s| s.to_str())
+        .filter(|s| !s.is_empty())
+        .unwrap_or("video");
+
+    format!("{}.mp4", stem)
+}
+
+/// A scratch file that's removed when dropped. ffmpeg needs to seek in most video (and some audio)
+/// containers, so they can't just be piped through.
+struct TempFile(PathBuf);
+
+impl TempFile {
+    fn new(ext: &str) -> TempFile {
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let n = COUNTER.fetch_add(1, Ordering::SeqCst);
+        TempFile(env::temp_dir().join(format!("thulani-media-{}-{}.{}", std::process::id(), n, ext)))
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
+/// Audio bitrate of transcoded videos, in bits per second.
+const VIDEO_AUDIO_BITRATE: u64 = 96_000;
+
+/// Video bitrates (in bits per second) below this aren't worth watching, so videos too long to fit
+/// at it are refused rather than transcoded.
+const MIN_VIDEO_BITRATE: u64 = 100_000;
+
+/// How much of the size cap a transcode aims for. x264 overshoots its target bitrate a little, and
+/// the container takes some room too.
+const VIDEO_SIZE_TARGET: f64 = 0.9;
+
+/// The length of a media file in seconds, according to ffprobe.
+fn probe_duration(path: &Path) -> Result<f64> {
+    let out = Command::new("ffprobe")
+        .args(&[
+            "-v", "error",
+            "-show_entries", "format=duration",
+            "-of", "default=noprint_wrappers=1:nokey=1",
+        ])
+        .arg(path)
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+
+    if !out.status.success() {
+        return Err(anyhow!("ffprobe exited with {} finding the duration", out.status));
+    }
+
+    let output = String::from_utf8_lossy(&out.stdout);
+
+    match output.trim().parse::<f64>() {
+        Ok(duration) if duration > 0. => Ok(duration),
+        _ => Err(anyhow!("ffprobe gave a bad duration: '{}'", output.trim())),
+    }
+}
+
+/// The video bitrate that fits `duration` seconds of video, along with its audio, into `max_bytes`.
+/// `None` if that would leave too little for the picture.
+fn video_bitrate(max_bytes: usize, duration: f64) -> Option<u64> {
+    let total = max_bytes as f64 * 8. * VIDEO_SIZE_TARGET / duration;
+    let video = total - VIDEO_AUDIO_BITRATE as f64;
+
+    if video < MIN_VIDEO_BITRATE as f64 {
+        return None;
+    }
+
+    Some(video as u64)
+}
+
+/// Re-encode a video as h264/aac mp4, scaled down to at most 640px wide, at a bitrate that fits
+/// `max_bytes`. `None` if it's too long to fit, or came out too big anyway.
+pub fn transcode_video(data: &[u8], max_bytes: usize) -> Result<Option<Vec<u8>>> {
+    let input = TempFile::new("in");
+    let output = TempFile::new("mp4");
+
+    fs::write(&input.0, data)?;
+
+    let duration = probe_duration(&input.0)?;
+
+    let bitrate = match video_bitrate(max_bytes, duration) {
+        Some(bitrate) => bitrate,
+        None => {
+            debug!("{:.1}s of video won't fit in {} bytes", duration, max_bytes);
+            return Ok(None);
+        },
+    };
+
+    let status = Command::new("ffmpeg")
+        .arg("-y")
+        .arg("-i")
+        .arg(&input.0)
+        .args(&[
+            "-c:v", "libx264",
+            "-preset", "veryfast",
+            "-b:v", &bitrate.to_string(),
+            "-maxrate", &bitrate.to_string(),
+            "-bufsize", &(2 * bitrate).to_string(),
+            "-vf", "scale='min(640,iw)':-2",
+            "-pix_fmt", "yuv420p",
+            "-c:a", "aac",
+            "-b:a", &VIDEO_AUDIO_BITRATE.to_string(),
+            "-movflags", "+faststart",
+            // only a safety net: hitting it cuts the video short, which is caught below
+            "-fs", &max_bytes.to_string(),
+        ])
+        .arg(&output.0)
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .stderr(Stdio::null())
+        .status()?;
+
+    if !status.success() {
+        return Err(anyhow!("ffmpeg exited with {} transcoding video", status));
+    }
+
+    let data = fs::read(&output.0)?;
+    let transcoded_duration = probe_duration(&output.0)?;
+
+    debug!("transcoded {:.1}s of video to {:.1}s in {} bytes at {}bps", duration, transcoded_duration, data.len(), bitrate);
+
+    if data.len() > max_bytes || transcoded_duration < duration - 1. {
+        return Ok(None);
+    }
+
+    Ok(Some(data))
+}
+
+/// The audio track of a sound or video file, encoded as opus like any other audio meme and cut
+/// down by `time_args` (see [`ffmpeg_time_args`](super::ffmpeg_time_args)), and cut off at
+/// `max_size` (in ffmpeg's notation, e.g. `5M`) if given. `None` if there's no sound.
+pub fn extract_audio(data: &[u8], time_args: &[String], max_size: Option<&str>) -> Result<Option<Vec<u8>>> {
+    let input = TempFile::new("in");
+    fs::write(&input.0, data)?;
+
+    let out = Command::new("ffmpeg")
+        .arg("-i")
+        .arg(&input.0)
+        .args(time_args)
+        .args(&[
+            "-vn",
+            "-ac", "2",
+            "-ar", "48000",
+            "-f", "opus",
+            "-acodec", "libopus",
+            "-b:a", "96k",
+        ])
+        .args(max_size.map(|size| vec!["-fs", size]).unwrap_or_default())
+        .arg("-")
+        .stdin(Stdio::null())
+        .stderr(Stdio::null())
+        .output()?;
+
+    // ffmpeg fails outright when there's no audio stream to map, or the range is past the end
+    if out.stdout.is_empty() {
+        return Ok(None);
+    }
+
+    Ok(Some(out.stdout))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_is_video() {
+        assert!(is_video("clip.mp4"));
+        assert!(is_video("CLIP.WEBM"));
+        assert!(is_video("dance.gif"));
+        assert!(!is_video("meme.png"));
+        assert!(!is_video("mp4"));
+
+        assert!(is_gif("dance.Gif"));
+        assert!(!is_gif("clip.mp4"));
+
+        assert!(is_audio("voice-message.ogg"));
+        assert!(is_audio("song.MP3"));
+        assert!(!is_audio("clip.mp4"));
+    }
+
+    #[test]
+    fn test_mp4_filename() {
+        assert_eq!("clip.mp4", mp4_filename("clip.webm"));
+        assert_eq!("clip.mp4", mp4_filename("clip.mp4"));
+        assert_eq!("my.clip.mp4", mp4_filename("my.clip.mov"));
+        assert_eq!("video.mp4", mp4_filename(""));
+    }
+
+    #[test]
+    fn test_video_bitrate() {
+        // 8MB over a minute, less the audio
+        assert_eq!(Some(910_632), video_bitrate(8 << 20, 60.));
+        assert_eq!(Some(20_036_659), video_bitrate(8 << 20, 3.));
+
+        // ten minutes would be a slideshow
+        assert_eq!(None, video_bitrate(8 << 20, 600.));
+    }
+}
@@ -14,14 +14,55 @@ use serenity::{
 };
 use typemap::Key;
 
+pub use self::fingerprint::{
+    fingerprint,
+    fpcalc_available,
+};
+pub use self::gain::{
+    Duck,
+    GainControl,
+    GainReader,
+};
+pub use self::media::{
+    extract_audio,
+    is_audio,
+    is_gif,
+    is_video,
+    mp4_filename,
+    transcode_video,
+    VIDEO_EXTENSIONS,
+};
 pub use self::play_queue::PlayQueue;
-pub use self::timeutil::parse_times;
+pub use self::recorder::{
+    encode_opus,
+    MAX_CLIP_SECONDS,
+    Recorder,
+    VoiceBuffer,
+};
+pub use self::timeutil::{
+    ffmpeg_time_args,
+    parse_times,
+    parse_url_times,
+    resolve_times,
+    TimeParseError,
+    url_start,
+};
+pub use self::tts::synthesize;
 pub use self::ytdl::*;
 
+mod fingerprint;
+mod gain;
+mod media;
+mod recorder;
 mod timeutil;
+mod tts;
 mod ytdl;
 mod play_queue;
 
+pub(crate) const SAMPLE_RATE: usize = 48000;
+pub(crate) const CHANNELS: usize = 2;
+pub(crate) const BYTES_PER_SAMPLE: usize = 2;
+
 pub struct VoiceManager;
 
 impl Key for VoiceManager {
@@ -42,10 +83,25 @@ pub struct PlayArgs {
     pub sender_channel: ChannelId,
     pub start: Option<Duration>,
     pub end: Option<Duration>,
+
+    /// Volume for this item alone, as a fraction of the global volume.
+    pub volume: f32,
+
+    /// Stored default volume of the meme this item was queued from (1.0 otherwise).
+    pub meme_volume: f32,
+}
+
+impl PlayArgs {
+    /// Combined item and meme scaling, to be multiplied by the global volume.
+    #[inline]
+    pub fn volume_scale(&self) -> f32 {
+        self.volume * self.meme_volume
+    }
 }
 
 #[derive(Clone)]
 pub struct CurrentItem {
     pub init_args: PlayArgs,
     pub audio: LockedAudio,
+    pub gain: Arc<GainControl>,
 }
@@ -23,9 +23,19 @@ use typemap::Key;
 
 use crate::{
     audio::{
+        BYTES_PER_SAMPLE,
+        CHANNELS,
         CurrentItem,
+        Duck,
+        GainControl,
+        GainReader,
         PlayArgs,
-        ytdl_url,
+        Recorder,
+        SAMPLE_RATE,
+        VoiceBuffer,
+        ffmpeg_time_args,
+        resolve_times,
+        ytdl_info,
     },
     commands::{
         sound_levels::DEFAULT_VOLUME,
@@ -36,9 +46,6 @@ use crate::{
 
 const SECONDS_LEAD_TIME: f32 = 0.75;
 const SECONDS_TRAIL_TIME: f32 = 0.1;
-const SAMPLE_RATE: usize = 48000;
-const CHANNELS: usize = 2;
-const BYTES_PER_SAMPLE: usize = 2;
 const PRE_SILENCE_BYTES: usize = (SECONDS_LEAD_TIME * (SAMPLE_RATE * CHANNELS * BYTES_PER_SAMPLE) as f32) as usize;
 const POST_SILENCE_BYTES: usize = (SECONDS_TRAIL_TIME * (SAMPLE_RATE * CHANNELS * BYTES_PER_SAMPLE) as f32) as usize;
 
@@ -48,6 +55,7 @@ pub struct PlayQueue {
     pub meme_queue: VecDeque<PlayArgs>,
     pub playing: Option<CurrentItem>,
     pub volume: f32,
+    pub duck: Option<Duck>,
 }
 
 impl Key for PlayQueue {
@@ -61,24 +69,27 @@ impl PlayQueue {
             meme_queue: VecDeque::new(),
             playing: None,
             volume: DEFAULT_VOLUME,
+            duck: None,
         }
     }
 
+    /// Must be called after [`Recorder::register`].
     pub fn register(c: &mut Client) {
         let voice_manager = Arc::clone(&c.voice_manager);
 
         let queue = Arc::new(RwLock::new(PlayQueue::new()));
 
-        {
+        let recorder = {
             let mut data = c.data.write();
             data.insert::<PlayQueue>(Arc::clone(&queue));
-        }
+            data.get::<Recorder>().cloned().expect("recorder not registered")
+        };
 
 
         let cache_http = c.cache_and_http.clone();
         thread::spawn(move || {
             loop {
-                if let Err(e) = Self::update(&cache_http, &queue, &voice_manager) {
+                if let Err(e) = Self::update(&cache_http, &queue, &voice_manager, &recorder) {
                     error!("updating playqueue: {}", e);
                 }
 
@@ -88,10 +99,26 @@ impl PlayQueue {
 
     }
 
-    fn update(cache_http: &CacheAndHttp, queue_lck: &Arc<RwLock<Self>>, voice_manager: &Arc<Mutex<ClientVoiceManager>>) -> Result<()> {
+    fn update(
+        cache_http: &CacheAndHttp,
+        queue_lck: &Arc<RwLock<Self>>,
+        voice_manager: &Arc<Mutex<ClientVoiceManager>>,
+        recorder: &Arc<std::sync::Mutex<VoiceBuffer>>,
+    ) -> Result<()> {
         let (queue_is_empty, queue_has_playing) = {
             let queue = queue_lck.read().unwrap();
 
+            if let Some(ref current) = queue.playing {
+                if current.gain.faded_out() {
+                    let mut audio = current.audio.lock();
+
+                    if audio.playing {
+                        audio.pause();
+                        debug!("pause faded out");
+                    }
+                }
+            }
+
             let allow_continue = queue.playing.clone().map_or(false, |x| !x.audio.lock().finished);
 
             if allow_continue {
@@ -129,26 +156,24 @@ impl PlayQueue {
             queue.general_queue.pop_front().unwrap()
         };
 
+        let gain = GainControl::new(queue.volume * item.volume_scale(), queue.duck);
+
         let src = match &mut item.data {
             Left(ref url) => {
-                let youtube_url = ytdl_url(url.as_str())?;
-
-                let duration_opts = if let Some(e) = item.end {
-                    vec! [
-                        "-ss".to_owned(), item.start.map_or_else(
-                            || "00:00:00".to_owned(),
-                            |s| format!("{:02}:{:02}:{:02}", s.num_hours(), s.num_minutes() % 60, s.num_seconds() % 60)
-                        ),
-
-                        "-to".to_owned(), format!("{:02}:{:02}:{:02}", e.num_hours(), e.num_minutes() % 60, e.num_seconds() % 60),
-                    ]
-                } else {
-                    vec! []
+                let info = ytdl_info(url.as_str())?;
+
+                let duration_opts = match resolve_times(item.start, item.end, info.duration) {
+                    Ok((start, end)) => ffmpeg_time_args(start, end),
+                    Err(e) => {
+                        error!("resolving times for {}: {}", url, e);
+                        item.sender_channel.say(&cache_http.http, format!("can't play that: {}", e))?;
+                        return Ok(());
+                    },
                 };
 
                 let ffmpeg_command = process::Command::new("ffmpeg")
                     .arg("-i")
-                    .arg(youtube_url)
+                    .arg(info.url)
                     .args(duration_opts)
                     .args(&[
                         "-ac", "2",
@@ -169,7 +194,7 @@ impl PlayQueue {
 
                 let reader = Cursor::new(pre_silence).chain(audio_reader).chain(Cursor::new(post_silence));
 
-                voice::pcm(true, reader)
+                voice::pcm(true, GainReader::new(reader, gain.clone()))
             },
             Right(ref vec) => {
                 let transcoder = process::Command::new("ffmpeg")
@@ -222,7 +247,7 @@ impl PlayQueue {
                     .chain(stdout.unwrap())
                     .chain(Cursor::new(post_silence));
 
-                voice::pcm(true, reader)
+                voice::pcm(true, GainReader::new(reader, gain.clone()))
             }
         };
 
@@ -231,14 +256,13 @@ impl PlayQueue {
 
         match handler {
             Some(handler) => {
+                handler.listen(Some(Recorder::receiver(recorder)));
                 let audio = handler.play_only(src);
-                {
-                    audio.lock().volume(queue.volume);
-                }
 
                 queue.playing = Some(CurrentItem {
                     init_args: item,
                     audio,
+                    gain,
                 });
 
                 debug!("playing new song");
@@ -0,0 +1,239 @@
+use std::{
+    collections::VecDeque,
+    io::{
+        Read,
+        Write,
+    },
+    process::{
+        Command,
+        Stdio,
+    },
+    sync::{
+        Arc,
+        Mutex,
+    },
+    thread,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use anyhow::anyhow;
+use fnv::{
+    FnvHashMap,
+    FnvHashSet,
+};
+use log::error;
+use serenity::{
+    client::Client,
+    voice::AudioReceiver,
+};
+use typemap::Key;
+
+use crate::Result;
+
+use super::{
+    CHANNELS,
+    SAMPLE_RATE,
+};
+
+/// How much received voice audio is kept around for clipping.
+pub const MAX_CLIP_SECONDS: u64 = 30;
+
+#[derive(Clone, Debug)]
+struct Packet {
+    ssrc: u32,
+    received: Instant,
+    timestamp: u32,
+    samples: Vec<i16>,
+}
+
+/// Rolling buffer of the voice audio the bot has received. Audio only ever lives in memory, and
+/// packets from excluded (opted-out) users are dropped on arrival. Until the excluded users are
+/// known, every packet is dropped.
+#[derive(Debug, Default)]
+pub struct VoiceBuffer {
+    users: FnvHashMap<u32, u64>,
+    excluded: Option<FnvHashSet<u64>>,
+    packets: VecDeque<Packet>,
+}
+
+impl VoiceBuffer {
+    pub fn set_excluded(&mut self, excluded: FnvHashSet<u64>) {
+        self.excluded = Some(excluded);
+
+        let packets = std::mem::take(&mut self.packets);
+        self.packets = packets.into_iter().filter(|p| self.allowed(p.ssrc)).collect();
+    }
+
+    /// Whether audio from `ssrc` may be kept: it has to belong to a known user who hasn't opted
+    /// out.
+    fn allowed(&self, ssrc: u32) -> bool {
+        match (&self.excluded, self.users.get(&ssrc)) {
+            (Some(excluded), Some(user)) => !excluded.contains(user),
+            _ => false,
+        }
+    }
+
+    fn push(&mut self, packet: Packet) {
+        let cutoff = packet.received - Duration::from_secs(MAX_CLIP_SECONDS);
+
+        while self.packets.front().map_or(false, |p| p.received < cutoff) {
+            self.packets.pop_front();
+        }
+
+        self.packets.push_back(packet);
+    }
+
+    /// Mix down the last `duration` of audio from every (known, non-excluded) speaker into
+    /// interleaved stereo PCM. Each speaker's packets are laid out by their RTP timestamps,
+    /// anchored at the arrival time of their first packet in the window.
+    pub fn clip(&self, duration: Duration) -> Vec<i16> {
+        let end = Instant::now();
+        let start = end - duration.min(Duration::from_secs(MAX_CLIP_SECONDS));
+        let len = frames(end - start) * CHANNELS;
+
+        let mut out = vec![0i32; len];
+        let mut anchors: FnvHashMap<u32, (usize, u32)> = FnvHashMap::default();
+
+        let packets = self.packets.iter()
+            .filter(|p| p.received >= start)
+            .filter(|p| self.allowed(p.ssrc));
+
+        for packet in packets {
+            let (anchor_frame, anchor_ts) = *anchors.entry(packet.ssrc)
+                .or_insert_with(|| {
+                    let packet_frames = packet.samples.len() / CHANNELS;
+                    (frames(packet.received - start).saturating_sub(packet_frames), packet.timestamp)
+                });
+
+            let offset = (anchor_frame + packet.timestamp.wrapping_sub(anchor_ts) as usize) * CHANNELS;
+
+            out.iter_mut()
+                .skip(offset)
+                .zip(packet.samples.iter())
+                .for_each(|(o, s)| *o += *s as i32);
+        }
+
+        out.into_iter()
+            .map(|s| s.max(i16::MIN as i32).min(i16::MAX as i32) as i16)
+            .collect()
+    }
+}
+
+#[inline]
+fn frames(d: Duration) -> usize {
+    (d.as_secs_f64() * SAMPLE_RATE as f64) as usize
+}
+
+pub struct Recorder;
+
+impl Key for Recorder {
+    type Value = Arc<Mutex<VoiceBuffer>>;
+}
+
+impl Recorder {
+    /// Registers an empty buffer, seeded with the stored opt-outs. If they can't be loaded, nobody
+    /// is recorded until they are.
+    pub fn register(c: &mut Client) {
+        let mut buffer = VoiceBuffer::default();
+
+        #[cfg(feature = "db")]
+        {
+            use crate::db::{
+                connection,
+                RecordingOptOut,
+            };
+
+            match connection().and_then(|conn| RecordingOptOut::all(&conn)) {
+                Ok(excluded) => buffer.set_excluded(excluded),
+                Err(e) => error!("loading recording opt-outs, recording nobody for now: {}", e),
+            }
+        }
+
+        let mut data = c.data.write();
+        data.insert::<Recorder>(Arc::new(Mutex::new(buffer)));
+    }
+
+    pub fn receiver(buffer: &Arc<Mutex<VoiceBuffer>>) -> Box<dyn AudioReceiver> {
+        Box::new(Receiver(Arc::clone(buffer)))
+    }
+}
+
+struct Receiver(Arc<Mutex<VoiceBuffer>>);
+
+impl AudioReceiver for Receiver {
+    fn speaking_update(&mut self, ssrc: u32, user_id: u64, _speaking: bool) {
+        self.0.lock().unwrap().users.insert(ssrc, user_id);
+    }
+
+    fn voice_packet(&mut self, ssrc: u32, _sequence: u16, timestamp: u32, stereo: bool, data: &[i16], _compressed_size: usize) {
+        let mut buffer = self.0.lock().unwrap();
+
+        if !buffer.allowed(ssrc) {
+            return;
+        }
+
+        let samples = if stereo {
+            data.to_vec()
+        } else {
+            data.iter().flat_map(|s| vec![*s; CHANNELS]).collect()
+        };
+
+        buffer.push(Packet {
+            ssrc,
+            received: Instant::now(),
+            timestamp,
+            samples,
+        });
+    }
+
+    fn client_disconnect(&mut self, user_id: u64) {
+        self.0.lock().unwrap().users.retain(|_, u| *u != user_id);
+    }
+}
+
+/// Encode interleaved 48kHz stereo PCM as opus.
+pub fn encode_opus(pcm: Vec<i16>) -> Result<Vec<u8>> {
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(&[
+            "-f", "s16le",
+            "-ar", "48000",
+            "-ac", "2",
+            "-i", "pipe:0",
+            "-f", "opus",
+            "-acodec", "libopus",
+            "-b:a", "96k",
+            "-",
+        ])
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let mut stdin = ffmpeg.stdin.take().unwrap();
+    let writer = thread::spawn(move || {
+        let bytes = pcm.into_iter().flat_map(|s| s.to_le_bytes().to_vec()).collect::<Vec<_>>();
+        stdin.write_all(&bytes)
+    });
+
+    let mut data = Vec::new();
+    ffmpeg.stdout.take().unwrap().read_to_end(&mut data)?;
+
+    // join the writer and reap ffmpeg before bailing on either
+    let written = writer.join().map_err(|_| anyhow!("clip writer thread panicked"));
+    let status = ffmpeg.wait()?;
+
+    written?.map_err(|e| anyhow!("writing clip to ffmpeg: {}", e))?;
+
+    if !status.success() {
+        return Err(anyhow!("ffmpeg exited with {} encoding a clip", status));
+    }
+
+    if data.is_empty() {
+        return Err(anyhow!("ffmpeg produced no audio"));
+    }
+
+    Ok(data)
+}
@@ -1,50 +1,231 @@
 use chrono::Duration;
 use regex::{
-    Match,
+    Captures,
     Regex,
 };
+use thiserror::Error;
+use url::Url;
 
 use lazy_static::lazy_static;
 
+/// A time value: clock-style (`1:23`, `01:02:03.5`) or unit-style (`1h2m3.5s`, `90`), optionally
+/// negative to count back from the end of the media.
+const TIME_PATTERN: &str = concat!(
+    r"(?P<negative>-)?(?:",
+    r"(?:(?P<clock_hours>\d+):)?(?P<clock_minutes>\d+):(?P<clock_seconds>\d+(?:\.\d+)?)",
+    r"|",
+    r"(?:(?P<hours>\d+)h\s?)?(?:(?P<minutes>\d+)m\s?)?(?:(?P<seconds>\d+(?:\.\d+)?)s?)?",
+    r")",
+);
+
 lazy_static! {
-    static ref START_REGEX: Regex =
-        Regex::new(r"(?:start|begin(?:ning)?)\s*=?\s*(?:(?P<hours>\d+)h\s?)?(?:(?P<minutes>\d+)m\s?)?(?:(?P<seconds>\d+)s?)?").unwrap();
+    static ref START_REGEX: Regex = keyword_regex(r"start|begin(?:ning)?");
+    static ref DUR_REGEX: Regex = keyword_regex(r"dur(?:ation)?");
+    static ref END_REGEX: Regex = keyword_regex(r"end|term(?:inate|ination)?");
+
+    static ref VALUE_REGEX: Regex = Regex::new(&format!("^{}$", TIME_PATTERN)).unwrap();
+}
+
+fn keyword_regex(keywords: &str) -> Regex {
+    Regex::new(&format!(r"(?i)\b(?:{})\b\s*=?\s*{}", keywords, TIME_PATTERN)).unwrap()
+}
 
-    static ref DUR_REGEX: Regex =
-        Regex::new(r"dur(?:ation)?\s*=?\s*(?:(?P<hours>\d+)h\s?)?(?:(?P<minutes>\d+)m\s?)?(?:(?P<seconds>\d+)s?)?").unwrap();
+const URL_START_PARAMS: &[&str] = &["t", "start", "time_continue"];
 
-    static ref END_REGEX: Regex =
-        Regex::new(r"(?:end|term(?:inate|ination)?)\s*=?\s*(?:(?P<hours>\d+)h\s?)?(?:(?P<minutes>\d+)m\s?)?(?:(?P<seconds>\d+)s?)?").unwrap();
+#[derive(Clone, Debug, Error, PartialEq, Eq, Hash)]
+pub enum TimeParseError {
+    #[error("couldn't understand time '{0}'")]
+    Malformed(String),
+
+    #[error("{0} was given more than once")]
+    Repeated(&'static str),
+
+    #[error("{0} can't be negative")]
+    Negative(&'static str),
+
+    #[error("can't count back from the end without knowing how long it is")]
+    UnknownLength,
 }
 
-pub fn parse_times<A: AsRef<str>>(s: A) -> (Option<Duration>, Option<Duration>) {
-    fn parse_match(m: Option<Match>) -> u64 {
-        m.and_then(|s| s.as_str().parse::<u64>().ok()).unwrap_or(0)
+type TimeResult<T> = std::result::Result<T, TimeParseError>;
+
+/// Parse start and end times out of a command. Negative results are offsets from the end of the
+/// media, to be resolved with [`resolve_times`].
+pub fn parse_times<A: AsRef<str>>(s: A) -> TimeResult<(Option<Duration>, Option<Duration>)> {
+    let s = s.as_ref();
+
+    let start_time = parse_keyword(&START_REGEX, "start", s)?;
+    let dur = parse_keyword(&DUR_REGEX, "duration", s)?;
+    let end_time = parse_keyword(&END_REGEX, "end", s)?;
+
+    if dur.map_or(false, |d| d < Duration::zero()) {
+        return Err(TimeParseError::Negative("duration"));
     }
 
-    fn parse_captures<B: AsRef<str>>(r: &Regex, s: B) -> Option<Duration> {
-        r.captures(s.as_ref())
-            .map(|capt| {
-                let hours = parse_match(capt.name("hours"));
-                let minutes = parse_match(capt.name("minutes"));
-                let seconds = parse_match(capt.name("seconds"));
+    let end_time = end_time.or_else(|| {
+        match (start_time, dur) {
+            // counting back from the end and running past it: play to the end
+            (Some(start), Some(d)) if start < Duration::zero() && start + d >= Duration::zero() => None,
+            (Some(start), Some(d)) => Some(start + d),
+            _ => None,
+        }
+    });
 
-                let result = Duration::hours(hours as i64) +
-                    Duration::minutes(minutes as i64) +
-                    Duration::seconds(seconds as i64);
+    Ok((start_time, end_time))
+}
 
-                assert!(result >= Duration::zero());
+/// [`parse_times`] for a command about `url`, taking the start time from the URL if the command
+/// doesn't give one.
+pub fn parse_url_times<A: AsRef<str>>(url: &Url, s: A) -> TimeResult<(Option<Duration>, Option<Duration>)> {
+    let (start, end) = parse_times(s)?;
 
-                result
-            })
+    match start {
+        Some(_) => Ok((start, end)),
+        None => url_start(url).map(|start| (start, end)),
+    }
+}
+
+/// Start time encoded in a media URL, e.g. youtube's `?t=1m30s`, `&start=90` or `#t=90`.
+pub fn url_start(url: &Url) -> TimeResult<Option<Duration>> {
+    let fragment = url.fragment()
+        .map(|f| url::form_urlencoded::parse(f.as_bytes()).into_owned().collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    let value = url.query_pairs()
+        .into_owned()
+        .chain(fragment.into_iter())
+        .find(|(k, _)| URL_START_PARAMS.contains(&k.as_str()))
+        .map(|(_, v)| v);
+
+    let value = match value {
+        Some(v) => v,
+        None => return Ok(None),
+    };
+
+    let start = VALUE_REGEX.captures(&value)
+        .ok_or_else(|| TimeParseError::Malformed(value.clone()))
+        .and_then(|capt| duration_from_captures(&capt, &value))?;
+
+    match start {
+        Some(d) if d < Duration::zero() => Err(TimeParseError::Negative("start")),
+        Some(d) => Ok(Some(d)),
+        None => Err(TimeParseError::Malformed(value)),
+    }
+}
+
+/// Resolve times counting back from the end (negative durations) against the media's length.
+pub fn resolve_times(start: Option<Duration>, end: Option<Duration>, length: Option<Duration>) -> TimeResult<(Option<Duration>, Option<Duration>)> {
+    let resolve = |t: Option<Duration>| match t {
+        Some(t) if t < Duration::zero() => length
+            .map(|l| Some((l + t).max(Duration::zero())))
+            .ok_or(TimeParseError::UnknownLength),
+        t => Ok(t),
+    };
+
+    Ok((resolve(start)?, resolve(end)?))
+}
+
+/// ffmpeg output options selecting the given (resolved) range.
+pub fn ffmpeg_time_args(start: Option<Duration>, end: Option<Duration>) -> Vec<String> {
+    let mut args = vec![];
+
+    if let Some(s) = start {
+        args.push("-ss".to_owned());
+        args.push(format_timestamp(s));
+    }
+
+    if let Some(e) = end {
+        args.push("-to".to_owned());
+        args.push(format_timestamp(e));
+    }
+
+    args
+}
+
+fn format_timestamp(d: Duration) -> String {
+    format!(
+        "{:02}:{:02}:{:02}.{:03}",
+        d.num_hours(),
+        d.num_minutes() % 60,
+        d.num_seconds() % 60,
+        d.num_milliseconds() % 1000,
+    )
+}
+
+fn parse_keyword(r: &Regex, name: &'static str, s: &str) -> TimeResult<Option<Duration>> {
+    let mut all_captures = r.captures_iter(s);
+
+    let capt = match all_captures.next() {
+        Some(capt) => capt,
+        None => return Ok(None),
+    };
+
+    if all_captures.next().is_some() {
+        return Err(TimeParseError::Repeated(name));
+    }
+
+    let whole = capt.get(0).unwrap();
+    let rest = &s[whole.end()..];
+
+    let terminated = rest.is_empty() ||
+        rest.starts_with(char::is_whitespace) ||
+        whole.as_str().ends_with(char::is_whitespace);
+
+    let token = || {
+        let tail = rest.find(char::is_whitespace).unwrap_or(rest.len());
+        s[whole.start()..whole.end() + tail].trim().to_owned()
+    };
+
+    if !terminated {
+        return Err(TimeParseError::Malformed(token()));
+    }
+
+    duration_from_captures(&capt, whole.as_str())?
+        .map(Some)
+        .ok_or_else(|| TimeParseError::Malformed(token()))
+}
+
+/// `Ok(None)` if the captures didn't contain a time value at all.
+fn duration_from_captures(capt: &Captures, source: &str) -> TimeResult<Option<Duration>> {
+    let malformed = || TimeParseError::Malformed(source.trim().to_owned());
+
+    let clock = capt.name("clock_seconds").is_some();
+
+    let (hours, minutes, seconds) = if clock {
+        (capt.name("clock_hours"), capt.name("clock_minutes"), capt.name("clock_seconds"))
+    } else {
+        (capt.name("hours"), capt.name("minutes"), capt.name("seconds"))
+    };
+
+    if hours.is_none() && minutes.is_none() && seconds.is_none() {
+        return Ok(None);
+    }
+
+    let parse_int = |m: Option<regex::Match>| {
+        m.map_or(Ok(0), |m| m.as_str().parse::<i64>().map_err(|_| malformed()))
+    };
+
+    let hours_given = hours.is_some();
+    let hours = parse_int(hours)?;
+    let minutes = parse_int(minutes)?;
+    let seconds = seconds.map_or(Ok(0.), |m| m.as_str().parse::<f64>().map_err(|_| malformed()))?;
+
+    if clock && (seconds >= 60. || (hours_given && minutes >= 60)) {
+        return Err(malformed());
     }
 
-    let start_time = parse_captures(&START_REGEX, &s);
-    let dur = parse_captures(&DUR_REGEX, &s);
-    let end_time = parse_captures(&END_REGEX, s)
-        .or_else(|| start_time.and_then(|start| dur.map(|d| start + d)));
+    let millis = hours.checked_mul(3_600_000)
+        .and_then(|h| minutes.checked_mul(60_000).and_then(|m| h.checked_add(m)))
+        .and_then(|hm| hm.checked_add((seconds * 1000.).round() as i64))
+        .ok_or_else(malformed)?;
 
-    (start_time, end_time)
+    let result = Duration::milliseconds(millis);
+
+    if capt.name("negative").is_some() {
+        Ok(Some(-result))
+    } else {
+        Ok(Some(result))
+    }
 }
 
 #[cfg(test)]
@@ -105,6 +286,11 @@ mod test {
 
             let elems = vec![true, false];
 
+            let mut clock_forms = vec![format!("{}:{:02}:{:02}", hours, minutes, seconds)];
+            if hours == 0 {
+                clock_forms.push(format!("{}:{:02}", minutes, seconds));
+            }
+
             #[inline]
             fn format_maybe_zero<S: AsRef<str>>(v: i64, unit: S, always: bool) -> String {
                 if always || v != 0 {
@@ -126,6 +312,7 @@ mod test {
 
                     Some(format!("{}{}{}", hr_string, mn_string, sec_string))
                 })
+                .chain(clock_forms.into_iter())
         }
 
         let start_times = vec![None, Some(Duration::seconds(0)), Some(Duration::seconds(32))];
@@ -176,7 +363,7 @@ mod test {
 
                 println!("testing {}", s);
 
-                let (parse_start, parse_end) = parse_times(s);
+                let (parse_start, parse_end) = parse_times(s).unwrap();
 
                 match start {
                     Some((dur, _)) => assert_eq!(*dur, parse_start.unwrap()),
@@ -199,4 +386,80 @@ mod test {
                 }
             });
     }
+
+    #[test]
+    fn test_clock_and_fractional() {
+        assert_eq!((Some(Duration::seconds(83)), None), parse_times("start 1:23").unwrap());
+        assert_eq!((Some(Duration::milliseconds(3_723_500)), None), parse_times("start=01:02:03.5").unwrap());
+        assert_eq!((None, Some(Duration::milliseconds(1500))), parse_times("end 1.5s").unwrap());
+        assert_eq!((Some(Duration::seconds(10)), Some(Duration::milliseconds(12_250))), parse_times("start 0:10 dur 2.25").unwrap());
+        assert_eq!((Some(Duration::seconds(90)), None), parse_times("START 1m 30s").unwrap());
+    }
+
+    #[test]
+    fn test_negative() {
+        assert_eq!((None, Some(Duration::seconds(-10))), parse_times("end=-10s").unwrap());
+        assert_eq!((Some(Duration::seconds(-30)), Some(Duration::seconds(-20))), parse_times("start -0:30 dur 10").unwrap());
+        assert_eq!((Some(Duration::seconds(-30)), None), parse_times("start -30 dur 45").unwrap());
+
+        assert_eq!(Err(TimeParseError::Negative("duration")), parse_times("dur -5"));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(Err(TimeParseError::Malformed("start=abc".to_owned())), parse_times("start=abc"));
+        assert_eq!(Err(TimeParseError::Malformed("end 5x".to_owned())), parse_times("end 5x"));
+        assert_eq!(Err(TimeParseError::Malformed("start 1:75".to_owned())), parse_times("start 1:75"));
+        assert_eq!(Err(TimeParseError::Malformed("start 1:2:3:4".to_owned())), parse_times("start 1:2:3:4"));
+        assert_eq!(Err(TimeParseError::Repeated("start")), parse_times("start 5 begin 10"));
+
+        assert_eq!(Ok((None, None)), parse_times("restart the endless tape"));
+        assert_eq!(Ok((None, None)), parse_times(""));
+    }
+
+    #[test]
+    fn test_url_start() {
+        let parse = |s: &str| url_start(&Url::parse(s).unwrap());
+
+        assert_eq!(Ok(Some(Duration::seconds(90))), parse("https://www.youtube.com/watch?v=abc&t=90"));
+        assert_eq!(Ok(Some(Duration::seconds(90))), parse("https://youtu.be/abc?t=1m30s"));
+        assert_eq!(Ok(Some(Duration::seconds(42))), parse("https://www.youtube.com/watch?v=abc&start=42"));
+        assert_eq!(Ok(Some(Duration::seconds(75))), parse("https://www.youtube.com/watch?v=abc#t=1:15"));
+        assert_eq!(Ok(None), parse("https://www.youtube.com/watch?v=abc"));
+
+        assert_eq!(Err(TimeParseError::Malformed("soon".to_owned())), parse("https://youtu.be/abc?t=soon"));
+        assert_eq!(Err(TimeParseError::Negative("start")), parse("https://youtu.be/abc?t=-5"));
+    }
+
+    #[test]
+    fn test_resolve() {
+        let length = Some(Duration::seconds(100));
+
+        assert_eq!(
+            Ok((Some(Duration::seconds(70)), Some(Duration::seconds(90)))),
+            resolve_times(Some(Duration::seconds(-30)), Some(Duration::seconds(-10)), length),
+        );
+
+        assert_eq!(
+            Ok((Some(Duration::zero()), None)),
+            resolve_times(Some(Duration::seconds(-300)), None, length),
+        );
+
+        assert_eq!(
+            Ok((Some(Duration::seconds(5)), Some(Duration::seconds(10)))),
+            resolve_times(Some(Duration::seconds(5)), Some(Duration::seconds(10)), None),
+        );
+
+        assert_eq!(Err(TimeParseError::UnknownLength), resolve_times(None, Some(Duration::seconds(-10)), None));
+    }
+
+    #[test]
+    fn test_ffmpeg_args() {
+        assert!(ffmpeg_time_args(None, None).is_empty());
+
+        assert_eq!(
+            vec!["-ss", "00:01:02.500", "-to", "01:00:00.000"],
+            ffmpeg_time_args(Some(Duration::milliseconds(62_500)), Some(Duration::hours(1))),
+        );
+    }
 }
@@ -0,0 +1,83 @@
+use std::{
+    io::{
+        Read,
+        Write,
+    },
+    process::{
+        Command,
+        Stdio,
+    },
+    thread,
+};
+
+use anyhow::anyhow;
+use log::{
+    debug,
+    error,
+};
+
+use crate::{
+    CONFIG,
+    Result,
+};
+
+/// Synthesize `text` with the configured TTS command and encode it as opus, ready to be queued
+/// like any other audio meme.
+pub fn synthesize<S: AsRef<str>>(text: S) -> Result<Vec<u8>> {
+    let mut command = CONFIG.tts_command.split_whitespace();
+    let program = command.next().ok_or(anyhow!("no tts command configured"))?;
+
+    let mut tts = Command::new(program)
+        .args(command)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let text = text.as_ref().to_owned();
+    let mut tts_stdin = tts.stdin.take().unwrap();
+
+    thread::spawn(move || {
+        if let Err(e) = tts_stdin.write_all(text.as_bytes()) {
+            error!("writing text to tts: {}", e);
+        }
+    });
+
+    let mut ffmpeg = Command::new("ffmpeg")
+        .args(&[
+            "-i", "pipe:0",
+            "-ac", "2",
+            "-ar", "48000",
+            "-f", "opus",
+            "-acodec", "libopus",
+            "-b:a", "96k",
+            "-",
+        ])
+        .stdin(tts.stdout.take().unwrap())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::null())
+        .spawn()?;
+
+    let mut data = Vec::new();
+    ffmpeg.stdout.take().unwrap().read_to_end(&mut data)?;
+
+    // reap both before bailing on either
+    let tts_status = tts.wait()?;
+    let ffmpeg_status = ffmpeg.wait()?;
+
+    if !tts_status.success() {
+        return Err(anyhow!("tts command exited with {}", tts_status));
+    }
+
+    if !ffmpeg_status.success() {
+        return Err(anyhow!("ffmpeg exited with {} encoding speech", ffmpeg_status));
+    }
+
+    if data.is_empty() {
+        return Err(anyhow!("tts produced no audio"));
+    }
+
+    debug!("synthesized {} bytes of speech", data.len());
+
+    Ok(data)
+}
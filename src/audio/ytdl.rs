@@ -7,6 +7,7 @@ use std::{
     },
 };
 
+use chrono::Duration;
 use serde_json::Value;
 use serenity::{
     voice::{
@@ -16,7 +17,13 @@ use serenity::{
 
 use crate::Result;
 
-pub fn ytdl_url(uri: &str) -> Result<String> {
+#[derive(Clone, Debug)]
+pub struct YtdlInfo {
+    pub url: String,
+    pub duration: Option<Duration>,
+}
+
+pub fn ytdl_info(uri: &str) -> Result<YtdlInfo> {
     let args = [
         "-f",
         "webm[abr>0]/bestaudio/best",
@@ -41,11 +48,20 @@ pub fn ytdl_url(uri: &str) -> Result<String> {
         other => return Err(VoiceError::YouTubeDLProcessing(other).into()),
     };
 
-    match obj.remove("url") {
+    let duration = obj.get("duration")
+        .and_then(Value::as_f64)
+        .map(|secs| Duration::milliseconds((secs * 1000.) as i64));
+
+    let url = match obj.remove("url") {
         Some(v) => match v {
-            Value::String(uri) => Ok(uri),
-            other => Err(VoiceError::YouTubeDLUrl(other).into()),
+            Value::String(uri) => uri,
+            other => return Err(VoiceError::YouTubeDLUrl(other).into()),
         },
-        None => Err(VoiceError::YouTubeDLUrl(Value::Object(obj)).into()),
-    }
+        None => return Err(VoiceError::YouTubeDLUrl(Value::Object(obj)).into()),
+    };
+
+    Ok(YtdlInfo {
+        url,
+        duration,
+    })
 }
@@ -0,0 +1,312 @@
+//! Meme database maintenance without the bot: `thulani-admin <command> [args]`. Run with no
+//! arguments for the list of commands.
+
+use std::{
+    fmt::Display,
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        BufReader,
+        BufWriter,
+    },
+    path::Path,
+};
+
+use anyhow::anyhow;
+use dotenv::dotenv;
+use itertools::Itertools;
+
+use thulani::{
+    CONFIG,
+    Result,
+    audio::{
+        extract_audio,
+        is_audio,
+        is_video,
+    },
+    db::{
+        self,
+        Audio,
+        check_hashes,
+        connection,
+        delete_meme,
+        export_memes,
+        Image,
+        import_memes,
+        MemeQuery,
+        NewMeme,
+        query_meme,
+        search_memes,
+        TimeRange,
+        Tombstone,
+        undelete_meme,
+    },
+};
+
+const USAGE: &str = r#"usage: thulani-admin <command> [args]
+
+    list [--tag <tag>]                 every meme, oldest first
+    search <text>                      best matches for some text
+    add <title> [--text <text>] [--audio <file>] [--by <user id>] [<image>...]
+                                       add a meme from local files
+    delete <title>                     delete a meme (it can be restored until purged)
+    graveyard                          recently deleted memes
+    restore <title>                    restore a deleted meme
+    stats                              overall meme stats
+    checkhashes                        check every blob against its hash, backfilling sha256
+    export <archive.tar>               write every meme to an archive
+    import <archive.tar>               load memes from an archive"#;
+
+const SEARCH_RESULTS: usize = 10;
+const GRAVEYARD_SIZE: usize = 20;
+
+fn main() {
+    dotenv().ok();
+
+    let args = ::std::env::args().skip(1).collect::<Vec<_>>();
+
+    if let Err(e) = run(&args) {
+        eprintln!("{}", e);
+        ::std::process::exit(1);
+    }
+}
+
+fn run(args: &[String]) -> Result<()> {
+    let (cmd, args) = match args.split_first() {
+        Some((cmd, args)) => (cmd.as_str(), args),
+        None => return Err(anyhow!(USAGE)),
+    };
+
+    let one_arg = || match args {
+        [arg] => Ok(arg.as_str()),
+        _ => Err(anyhow!(USAGE)),
+    };
+
+    match cmd {
+        "list" => list(args),
+        "search" if !args.is_empty() => search(&args.join(" ")),
+        "add" => add(args),
+        "delete" => {
+            let title = one_arg()?;
+            delete_meme(&connection()?, title, CONFIG.discord.owner().0)?;
+            println!("deleted {}", title);
+            Ok(())
+        },
+        "graveyard" => graveyard(),
+        "restore" => {
+            let meme = undelete_meme(&connection()?, one_arg()?, CONFIG.discord.owner().0)?;
+            println!("restored {}", meme.title);
+            Ok(())
+        },
+        "stats" => stats(),
+        "checkhashes" => checkhashes(),
+        "export" => {
+            let path = one_arg()?;
+            let summary = export_memes(&connection()?, BufWriter::new(File::create(path)?))?;
+            println!("exported {} memes ({} files) to {}", summary.memes, summary.files, path);
+            Ok(())
+        },
+        "import" => {
+            let path = one_arg()?;
+            let summary = import_memes(&connection()?, BufReader::new(File::open(path)?))?;
+            println!("imported {} memes ({} files) from {}; skipped {} that already exist",
+                     summary.imported, summary.files, path, summary.skipped);
+            Ok(())
+        },
+        _ => Err(anyhow!(USAGE)),
+    }
+}
+
+fn list(args: &[String]) -> Result<()> {
+    let tag = match args {
+        [] => None,
+        [flag, tag] if flag == "--tag" => Some(tag.trim_start_matches('#').to_lowercase()),
+        _ => return Err(anyhow!(USAGE)),
+    };
+
+    let query = MemeQuery {
+        tag,
+        ..Default::default()
+    };
+
+    let page = query_meme(&query, 0, i64::max_value() as usize)?;
+
+    for (meme, metadata, images) in &page.memes {
+        println!(
+            "{}\t{}\tby {} on {}\timages: {}\taudio: {}",
+            meme.id,
+            meme.title,
+            metadata.created_by,
+            metadata.created.date(),
+            images,
+            if meme.audio_id.is_some() { "yes" } else { "no" },
+        );
+    }
+
+    println!("{} memes", page.total);
+
+    Ok(())
+}
+
+fn search(text: &str) -> Result<()> {
+    let results = search_memes(text, SEARCH_RESULTS)?;
+
+    if results.is_empty() {
+        println!("no match");
+    }
+
+    for (meme, score) in results {
+        println!("{:.3}\t{}", score, meme.title);
+    }
+
+    Ok(())
+}
+
+fn add(args: &[String]) -> Result<()> {
+    let (title, mut rest) = match args.split_first() {
+        Some((title, rest)) => (title.clone(), rest),
+        None => return Err(anyhow!(USAGE)),
+    };
+
+    let mut text = None;
+    let mut audio_path = None;
+    let mut by_user = CONFIG.discord.owner().0;
+    let mut image_paths = Vec::new();
+
+    while let Some((arg, next)) = rest.split_first() {
+        let value = || next.first().ok_or_else(|| anyhow!("{} needs a value", arg));
+
+        match arg.as_str() {
+            "--text" => text = Some(value()?.clone()),
+            "--audio" => audio_path = Some(value()?.clone()),
+            "--by" => by_user = value()?.parse()?,
+            _ => {
+                image_paths.push(arg.clone());
+                rest = next;
+                continue;
+            },
+        }
+
+        rest = &next[1..];
+    }
+
+    if text.is_none() && audio_path.is_none() && image_paths.is_empty() {
+        return Err(anyhow!("a meme needs text, audio or images"));
+    }
+
+    let conn = connection()?;
+
+    let image_ids = image_paths.iter()
+        .map(|path| {
+            let filename = Path::new(path)
+                .file_name()
+                .map(|f| f.to_string_lossy().into_owned())
+                .ok_or_else(|| anyhow!("{} isn't a file", path))?;
+
+            Image::create(&conn, &filename, fs::read(path)?, by_user)
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    let audio_id = match audio_path {
+        Some(path) => {
+            if !is_audio(&path) && !is_video(&path) {
+                return Err(anyhow!("{} doesn't look like a sound or video file", path));
+            }
+
+            // transcoded like any other audio meme
+            let data = extract_audio(&fs::read(&path)?, &[], None)?
+                .ok_or_else(|| anyhow!("no sound in {}", path))?;
+
+            Some(Audio::create(&conn, data, by_user)?)
+        },
+        None => None,
+    };
+
+    let meme = NewMeme {
+        title,
+        content: text,
+        audio_id,
+        metadata_id: 0,
+    }.save(&conn, &image_ids, by_user)?;
+
+    println!("added {} ({} images, audio: {})", meme.title, image_ids.len(), if audio_id.is_some() { "yes" } else { "no" });
+
+    Ok(())
+}
+
+fn graveyard() -> Result<()> {
+    let tombstones = Tombstone::recent(&connection()?, GRAVEYARD_SIZE)?;
+
+    if tombstones.is_empty() {
+        println!("nothing to restore");
+    }
+
+    for tombstone in tombstones {
+        println!(
+            "{}\tdeleted by {} on {}",
+            tombstone.title.unwrap_or_default(),
+            tombstone.deleted_by,
+            tombstone.deleted_at.date(),
+        );
+    }
+
+    Ok(())
+}
+
+fn stats() -> Result<()> {
+    let stats = db::stats(&connection()?, &TimeRange::default())?;
+
+    println!("{} memes ({} with audio, {} with images)", stats.memes_overall, stats.audio_memes, stats.image_memes);
+
+    let started = match stats.started_recording {
+        Some(started) => started,
+        None => {
+            println!("no invocations recorded");
+            return Ok(());
+        },
+    };
+
+    println!("{} invocations since {} ({} random, {} audio)",
+             stats.total_meme_invocations, started.date(),
+             stats.random_meme_invocations, stats.audio_meme_invocations);
+
+    fn record<T: Display>(label: &str, record: Option<(T, usize)>) {
+        if let Some((what, count)) = record {
+            println!("{}: {} ({})", label, what, count);
+        }
+    }
+
+    record("most active day", stats.most_active_day);
+    record("loudest day", stats.most_audio_active_day);
+    record("most random memes by user", stats.most_random_meme_user);
+    record("most memes by name by user", stats.most_directly_named_meme_user);
+    record("most requested", stats.most_popular_named_meme);
+    record("most random", stats.most_popular_random_meme);
+    record("most memed", stats.most_popular_meme_overall);
+
+    Ok(())
+}
+
+fn checkhashes() -> Result<()> {
+    let report = check_hashes(&connection()?)?;
+
+    let list = |rows: &[(&str, i32)]| rows.iter().map(|(kind, id)| format!("{} {}", kind, id)).join(", ");
+
+    println!("checked {} blobs, backfilled {} sha256 hashes", report.checked, report.backfilled);
+
+    if !report.missing.is_empty() {
+        println!("missing: {}", list(&report.missing));
+    }
+
+    if !report.mismatched.is_empty() {
+        println!("hash mismatch: {}", list(&report.mismatched));
+    }
+
+    if report.missing.is_empty() && report.mismatched.is_empty() {
+        Ok(())
+    } else {
+        Err(anyhow!("{} blobs failed the check", report.missing.len() + report.mismatched.len()))
+    }
+}
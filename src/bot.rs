@@ -80,6 +80,17 @@ impl EventHandler for Handler {
             .remove(&deleted_message_id);
     }
 
+    #[cfg(feature = "db")]
+    fn reaction_add(&self, ctx: Context, reaction: serenity::model::channel::Reaction) {
+        if let Err(e) = crate::commands::handle_query_reaction(&ctx, &reaction) {
+            error!("handling reaction: {}", e);
+        }
+
+        if let Err(e) = crate::commands::handle_draft_reaction(&ctx, &reaction) {
+            error!("handling draft reaction: {}", e);
+        }
+    }
+
     fn unknown(&self, _ctx: Context, name: String, _raw: Value) {
         info!("unknown event: {:?}", name);
     }
@@ -200,12 +211,19 @@ fn after_handle(ctx: &mut Context, msg: &Message, cmd: &str, err: StdResult<(),
 }
 
 pub fn run() -> Result<()> {
+    #[cfg(feature = "db")]
+    crate::db::check_repeats()?;
+
     let token = &CONFIG.discord.auth.token;
     let mut client = Client::new(token, Handler)?;
 
     audio::VoiceManager::register(&mut client);
+    audio::Recorder::register(&mut client);
     audio::PlayQueue::register(&mut client);
 
+    #[cfg(feature = "db")]
+    crate::db::spawn_tombstone_purge();
+
     client.with_framework(framework());
 
     let shard_manager = client.shard_manager.clone();
@@ -0,0 +1,88 @@
+use diesel::{
+    NotFound,
+    result::{
+        DatabaseErrorKind,
+        Error as DieselError,
+    },
+};
+use log::info;
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{
+    Result,
+    db::{
+        connection,
+        Meme,
+        MemeAlias,
+    },
+    util::CtxExt,
+};
+
+#[command]
+#[aliases("aka")]
+pub fn alias(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let title = args.single_quoted::<String>()?;
+    let alias = args.single_quoted::<String>()?;
+
+    if alias.trim().is_empty() {
+        return ctx.send(msg.channel_id, "alias it to what", msg.tts);
+    }
+
+    let conn = connection()?;
+
+    let meme = match Meme::by_title(&conn, &title) {
+        Ok(meme) => meme,
+        Err(e) => {
+            if let Some(NotFound) = e.downcast_ref::<DieselError>() {
+                info!("attempted to alias nonexistent meme: '{}'", title);
+                return ctx.send(msg.channel_id, "never heard of it", msg.tts);
+            }
+
+            return Err(e);
+        },
+    };
+
+    match meme.add_alias(&conn, &alias, msg.author.id.0) {
+        Ok(_) => {
+            info!("'{}' aliased to '{}'", alias, meme.title);
+            msg.react(ctx, "👌")
+        },
+        Err(e) => {
+            if let Some(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) = e.downcast_ref::<DieselError>() {
+                info!("alias '{}' is already taken", alias);
+                return ctx.send(msg.channel_id, "that name's taken", msg.tts);
+            }
+
+            Err(e)
+        },
+    }
+}
+
+#[command]
+pub fn unalias(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let alias = args.single_quoted::<String>()?;
+
+    let conn = connection()?;
+
+    match MemeAlias::remove(&conn, &alias, msg.author.id.0) {
+        Ok(removed) => {
+            info!("alias '{}' (meme {}) removed", removed.alias, removed.meme_id);
+            msg.react(ctx, "👌")
+        },
+        Err(e) => {
+            if let Some(NotFound) = e.downcast_ref::<DieselError>() {
+                info!("attempted to remove nonexistent alias: '{}'", alias);
+                return ctx.send(msg.channel_id, "that's not an alias", msg.tts);
+            }
+
+            Err(e)
+        },
+    }
+}
@@ -0,0 +1,102 @@
+use std::{
+    fs::{
+        self,
+        File,
+    },
+    io::BufWriter,
+    path::Path,
+};
+
+use chrono::Utc;
+use itertools::Itertools;
+use log::info;
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{
+    CONFIG,
+    Result,
+    db::{
+        check_hashes,
+        connection,
+        export_memes,
+        migrate_blobs,
+    },
+    util::CtxExt,
+};
+
+#[command]
+#[owners_only]
+#[aliases("migrateblob")]
+pub fn migrateblobs(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+    let conn = connection()?;
+
+    ctx.send(msg.channel_id, "moving blobs out of the database...", msg.tts)?;
+
+    let (images, audio) = migrate_blobs(&conn)?;
+    info!("moved {} images and {} audio clips to the blob store", images, audio);
+
+    ctx.send(msg.channel_id, &format!("moved {} images and {} audio clips", images, audio), msg.tts)
+}
+
+#[command]
+#[owners_only]
+#[aliases("checkhash", "fsck")]
+pub fn checkhashes(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+    let conn = connection()?;
+
+    ctx.send(msg.channel_id, "checking hashes...", msg.tts)?;
+
+    let report = check_hashes(&conn)?;
+    info!("hash check: {:?}", report);
+
+    let list = |rows: &[(&str, i32)]| rows.iter().map(|(kind, id)| format!("{} {}", kind, id)).join(", ");
+
+    let mut resp = format!("checked {} blobs, backfilled {} sha256 hashes", report.checked, report.backfilled);
+
+    if !report.missing.is_empty() {
+        resp += &format!("\nmissing: {}", list(&report.missing));
+    }
+
+    if !report.mismatched.is_empty() {
+        resp += &format!("\nhash mismatch: {}", list(&report.mismatched));
+    }
+
+    ctx.send(msg.channel_id, &resp, msg.tts)
+}
+
+#[command]
+#[owners_only]
+#[aliases("exportmeme", "backup")]
+pub fn exportmemes(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+    let conn = connection()?;
+
+    ctx.send(msg.channel_id, "exporting memes...", msg.tts)?;
+
+    let dir = Path::new(&CONFIG.export_dir);
+    fs::create_dir_all(dir)?;
+
+    let path = dir.join(format!("memes-{}.tar", Utc::now().format("%Y%m%d-%H%M%S")));
+    let partial = path.with_extension("tar.partial");
+
+    // written aside and renamed, so a half-finished export never looks like a real one
+    let summary = match export_memes(&conn, BufWriter::new(File::create(&partial)?)) {
+        Ok(summary) => summary,
+        Err(e) => {
+            let _ = fs::remove_file(&partial);
+            return Err(e);
+        },
+    };
+
+    fs::rename(&partial, &path)?;
+
+    info!("exported {} memes ({} files) to {}", summary.memes, summary.files, path.display());
+
+    ctx.send(msg.channel_id, &format!("exported {} memes ({} files) to `{}`", summary.memes, summary.files, path.display()), msg.tts)
+}
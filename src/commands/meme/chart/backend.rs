@@ -0,0 +1,162 @@
+use lazy_static::lazy_static;
+use plotters_backend::{
+    BackendColor,
+    BackendCoord,
+    BackendStyle,
+    BackendTextStyle,
+    DrawingBackend,
+    DrawingErrorKind,
+    text_anchor::{
+        HPos,
+        VPos,
+    },
+};
+use plotters_bitmap::{
+    BitMapBackend,
+    bitmap_pixel::RGBPixel,
+};
+use rusttype::{
+    Font,
+    point,
+    PositionedGlyph,
+    Scale,
+};
+
+lazy_static! {
+    /// Charts carry their own font so they render the same without any installed.
+    static ref FONT: Font<'static> = Font::try_from_bytes(include_bytes!("../../../../assets/DejaVuSans.ttf"))
+        .expect("bundled chart font doesn't parse");
+}
+
+type Error = DrawingErrorKind<<BitMapBackend<'static, RGBPixel> as DrawingBackend>::ErrorType>;
+
+/// An RGB bitmap that draws text with the bundled font rather than asking plotters to find a
+/// system one.
+pub(super) struct GlyphBackend<'a>(BitMapBackend<'a>);
+
+impl<'a> GlyphBackend<'a> {
+    pub(super) fn with_buffer(buf: &'a mut [u8], size: (u32, u32)) -> Self {
+        GlyphBackend(BitMapBackend::with_buffer(buf, size))
+    }
+}
+
+/// `text` laid out from the origin at `size` pixels, along with its width and height.
+fn layout(text: &str, size: f64) -> (Vec<PositionedGlyph<'static>>, i32, i32) {
+    let scale = Scale::uniform(size as f32);
+    let metrics = FONT.v_metrics(scale);
+
+    let glyphs = FONT.layout(text, scale, point(0., metrics.ascent)).collect::<Vec<_>>();
+
+    let width = glyphs.last()
+        .map(|g| g.position().x + g.unpositioned().h_metrics().advance_width)
+        .unwrap_or(0.);
+
+    (glyphs, width.ceil() as i32, (metrics.ascent - metrics.descent).ceil() as i32)
+}
+
+impl<'a> DrawingBackend for GlyphBackend<'a> {
+    type ErrorType = <BitMapBackend<'a> as DrawingBackend>::ErrorType;
+
+    fn get_size(&self) -> (u32, u32) {
+        self.0.get_size()
+    }
+
+    fn ensure_prepared(&mut self) -> Result<(), Error> {
+        self.0.ensure_prepared()
+    }
+
+    fn present(&mut self) -> Result<(), Error> {
+        self.0.present()
+    }
+
+    fn draw_pixel(&mut self, point: BackendCoord, color: BackendColor) -> Result<(), Error> {
+        self.0.draw_pixel(point, color)
+    }
+
+    fn draw_line<S: BackendStyle>(&mut self, from: BackendCoord, to: BackendCoord, style: &S) -> Result<(), Error> {
+        self.0.draw_line(from, to, style)
+    }
+
+    fn draw_rect<S: BackendStyle>(
+        &mut self,
+        upper_left: BackendCoord,
+        bottom_right: BackendCoord,
+        style: &S,
+        fill: bool,
+    ) -> Result<(), Error> {
+        self.0.draw_rect(upper_left, bottom_right, style, fill)
+    }
+
+    fn draw_path<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(&mut self, path: I, style: &S) -> Result<(), Error> {
+        self.0.draw_path(path, style)
+    }
+
+    fn draw_circle<S: BackendStyle>(&mut self, center: BackendCoord, radius: u32, style: &S, fill: bool) -> Result<(), Error> {
+        self.0.draw_circle(center, radius, style, fill)
+    }
+
+    fn fill_polygon<S: BackendStyle, I: IntoIterator<Item = BackendCoord>>(&mut self, vert: I, style: &S) -> Result<(), Error> {
+        self.0.fill_polygon(vert, style)
+    }
+
+    fn blit_bitmap(&mut self, pos: BackendCoord, size: (u32, u32), src: &[u8]) -> Result<(), Error> {
+        self.0.blit_bitmap(pos, size, src)
+    }
+
+    fn draw_text<S: BackendTextStyle>(&mut self, text: &str, style: &S, pos: BackendCoord) -> Result<(), Error> {
+        let color = style.color();
+
+        if color.alpha == 0. {
+            return Ok(());
+        }
+
+        let (glyphs, width, height) = layout(text, style.size());
+
+        let dx = match style.anchor().h_pos {
+            HPos::Left => 0,
+            HPos::Right => -width,
+            HPos::Center => -width / 2,
+        };
+
+        let dy = match style.anchor().v_pos {
+            VPos::Top => 0,
+            VPos::Center => -height / 2,
+            VPos::Bottom => -height,
+        };
+
+        let transform = style.transform();
+        let (w, h) = self.get_size();
+
+        let mut result = Ok(());
+
+        for glyph in &glyphs {
+            let bounds = match glyph.pixel_bounding_box() {
+                Some(bounds) => bounds,
+                None => continue,
+            };
+
+            glyph.draw(|gx, gy, coverage| {
+                if result.is_err() || coverage <= 0. {
+                    return;
+                }
+
+                let (x, y) = transform.transform(bounds.min.x + gx as i32 + dx, bounds.min.y + gy as i32 + dy);
+                let (x, y) = (pos.0 + x, pos.1 + y);
+
+                if x >= 0 && x < w as i32 && y >= 0 && y < h as i32 {
+                    result = self.0.draw_pixel((x, y), BackendColor {
+                        alpha: color.alpha * coverage as f64,
+                        rgb: color.rgb,
+                    });
+                }
+            });
+        }
+
+        result
+    }
+
+    fn estimate_text_size<S: BackendTextStyle>(&self, text: &str, style: &S) -> Result<(u32, u32), Error> {
+        let (_, width, height) = layout(text, style.size());
+        Ok((width.max(0) as u32, height.max(0) as u32))
+    }
+}
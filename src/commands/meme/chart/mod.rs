@@ -0,0 +1,265 @@
+use anyhow::anyhow;
+use chrono::{
+    Date,
+    NaiveDateTime,
+    Utc,
+};
+use image::{
+    DynamicImage,
+    ImageOutputFormat,
+    RgbImage,
+};
+use log::info;
+use plotters::{
+    coord::{
+        Shift,
+        types::{
+            RangedCoordu32,
+            RangedDate,
+        },
+    },
+    prelude::*,
+};
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    http::AttachmentType,
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{
+    db::{
+        Bucket,
+        invocation_counts,
+        TimeRange,
+        user_invocations,
+    },
+    Result,
+    util::CtxExt,
+};
+
+use self::backend::GlyphBackend;
+
+use super::history::{
+    describe_range,
+    range_arg,
+    username,
+};
+
+mod backend;
+
+const WIDTH: u32 = 960;
+const HEIGHT: u32 = 480;
+
+/// Only the size counts: [`GlyphBackend`] draws everything in its bundled font.
+const CAPTION_FONT: (&str, u32) = ("sans-serif", 24);
+
+/// How many users the activity chart has room for.
+const CHART_USERS: usize = 12;
+
+/// Past this many days of history, the share chart counts by week instead.
+const MAX_DAILY_SHARE: i64 = 120;
+
+type Area<'a> = DrawingArea<GlyphBackend<'a>, Shift>;
+type DateChart<'a, 'b> = ChartContext<'a, GlyphBackend<'b>, Cartesian2d<RangedDate<Date<Utc>>, RangedCoordu32>>;
+
+/// Draw a chart onto a blank canvas and encode it as a PNG.
+fn render<F>(draw: F) -> Result<Vec<u8>>
+    where F: FnOnce(&Area) -> Result<()>
+{
+    let mut pixels = vec![0u8; (WIDTH * HEIGHT * 3) as usize];
+
+    {
+        let root = GlyphBackend::with_buffer(&mut pixels, (WIDTH, HEIGHT)).into_drawing_area();
+        root.fill(&WHITE)?;
+        draw(&root)?;
+        root.present()?;
+    }
+
+    let img = RgbImage::from_raw(WIDTH, HEIGHT, pixels)
+        .ok_or_else(|| anyhow!("chart buffer doesn't match its dimensions"))?;
+
+    let mut png = Vec::new();
+    DynamicImage::ImageRgb8(img).write_to(&mut png, ImageOutputFormat::Png)?;
+
+    Ok(png)
+}
+
+fn day(time: NaiveDateTime) -> Date<Utc> {
+    Date::from_utc(time.date(), Utc)
+}
+
+/// Captioned axes spanning the days in `counts` and up past the busiest one, mesh drawn.
+fn date_axes<'a, 'b>(
+    root: &'a Area<'b>,
+    caption: &str,
+    counts: &[(NaiveDateTime, usize, usize)],
+) -> Result<DateChart<'a, 'b>> {
+    let from = day(counts[0].0);
+    let to = day(counts[counts.len() - 1].0).succ();
+    let max = counts.iter().map(|&(_, total, _)| total).max().unwrap_or(0).max(1) as u32;
+
+    let mut chart = ChartBuilder::on(root)
+        .caption(caption, CAPTION_FONT)
+        .margin(12)
+        .x_label_area_size(30)
+        .y_label_area_size(40)
+        .build_cartesian_2d(from..to, 0..max + max / 10 + 1)?;
+
+    chart.configure_mesh()
+        .disable_x_mesh()
+        .x_labels(10)
+        .x_label_formatter(&|d| d.format("%Y-%m-%d").to_string())
+        .y_desc("memes")
+        .draw()?;
+
+    Ok(chart)
+}
+
+/// Invocations per day as a filled line.
+fn daily_chart(caption: &str, counts: &[(NaiveDateTime, usize, usize)]) -> Result<Vec<u8>> {
+    render(|root| {
+        let mut chart = date_axes(root, caption, counts)?;
+
+        chart.draw_series(
+            AreaSeries::new(counts.iter().map(|&(t, total, _)| (day(t), total as u32)), 0, &BLUE.mix(0.3))
+                .border_style(&BLUE)
+        )?;
+
+        Ok(())
+    })
+}
+
+/// Audio invocations stacked against all invocations per bucket, so the share is what's blue.
+fn share_chart(caption: &str, counts: &[(NaiveDateTime, usize, usize)]) -> Result<Vec<u8>> {
+    render(|root| {
+        let mut chart = date_axes(root, caption, counts)?;
+
+        chart.draw_series(
+            AreaSeries::new(counts.iter().map(|&(t, total, _)| (day(t), total as u32)), 0, &BLACK.mix(0.15))
+                .border_style(&BLACK.mix(0.5))
+        )?
+            .label("silent")
+            .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], BLACK.mix(0.15).filled()));
+
+        chart.draw_series(
+            AreaSeries::new(counts.iter().map(|&(t, _, audio)| (day(t), audio as u32)), 0, &BLUE.mix(0.4))
+                .border_style(&BLUE)
+        )?
+            .label("audio")
+            .legend(|(x, y)| Rectangle::new([(x, y - 5), (x + 10, y + 5)], BLUE.mix(0.4).filled()));
+
+        chart.configure_series_labels()
+            .background_style(&WHITE.mix(0.8))
+            .border_style(&BLACK)
+            .draw()?;
+
+        Ok(())
+    })
+}
+
+/// One bar per user, busiest first.
+fn users_chart(caption: &str, users: &[(String, usize)]) -> Result<Vec<u8>> {
+    render(|root| {
+        let max = users.iter().map(|&(_, n)| n).max().unwrap_or(0).max(1) as u32;
+
+        let mut chart = ChartBuilder::on(root)
+            .caption(caption, CAPTION_FONT)
+            .margin(12)
+            .x_label_area_size(40)
+            .y_label_area_size(40)
+            .build_cartesian_2d((0..users.len() as u32 - 1).into_segmented(), 0..max + max / 10 + 1)?;
+
+        chart.configure_mesh()
+            .disable_x_mesh()
+            .x_labels(users.len())
+            .x_label_formatter(&|v| match v {
+                SegmentValue::CenterOf(i) => users.get(*i as usize).map(|(name, _)| name.clone()).unwrap_or_default(),
+                _ => String::new(),
+            })
+            .y_desc("memes")
+            .draw()?;
+
+        chart.draw_series(
+            Histogram::vertical(&chart)
+                .style(BLUE.mix(0.6).filled())
+                .margin(8)
+                .data(users.iter().enumerate().map(|(i, &(_, n))| (i as u32, n as u32)))
+        )?;
+
+        Ok(())
+    })
+}
+
+fn daily(range: &TimeRange) -> Result<Option<Vec<u8>>> {
+    let counts = invocation_counts(range, Bucket::Day)?;
+
+    if counts.is_empty() {
+        return Ok(None);
+    }
+
+    daily_chart(&format!("memes per day {}", describe_range(range)), &counts).map(Some)
+}
+
+fn share(range: &TimeRange) -> Result<Option<Vec<u8>>> {
+    let mut counts = invocation_counts(range, Bucket::Day)?;
+
+    let (first, last) = match (counts.first(), counts.last()) {
+        (Some(first), Some(last)) => (first.0, last.0),
+        _ => return Ok(None),
+    };
+
+    // days get too thin to see over long stretches
+    let bucket = if (last - first).num_days() > MAX_DAILY_SHARE {
+        counts = invocation_counts(range, Bucket::Week)?;
+        "week"
+    } else {
+        "day"
+    };
+
+    share_chart(&format!("audio and silent memes per {} {}", bucket, describe_range(range)), &counts).map(Some)
+}
+
+fn users(ctx: &Context, range: &TimeRange) -> Result<Option<Vec<u8>>> {
+    let users = user_invocations(range, CHART_USERS)?
+        .into_iter()
+        .map(|(user_id, n)| Ok((username(ctx, user_id)?, n)))
+        .collect::<Result<Vec<_>>>()?;
+
+    if users.is_empty() {
+        return Ok(None);
+    }
+
+    users_chart(&format!("memes by user {}", describe_range(range)), &users).map(Some)
+}
+
+#[command]
+#[aliases("graph", "plot")]
+pub fn chart(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let kind = args.single::<String>().unwrap_or_default().to_lowercase();
+
+    let range = match range_arg(ctx, msg, &args)? {
+        Some(range) => range,
+        None => return Ok(()),
+    };
+
+    info!("charting {} {:?}", kind, range);
+
+    let png = match kind.as_str() {
+        "daily" | "day" | "days" => daily(&range)?,
+        "audio" | "share" => share(&range)?,
+        "users" | "memers" => users(ctx, &range)?,
+        _ => return ctx.send(msg.channel_id, "chart what? daily, audio or users, optionally with a time window", msg.tts),
+    };
+
+    match png {
+        Some(png) => {
+            msg.channel_id.send_files(ctx, vec![AttachmentType::Bytes((png.as_slice(), "chart.png"))], |m| m)?;
+            Ok(())
+        },
+        None => ctx.send(msg.channel_id, &format!("no memes {}", describe_range(&range)), msg.tts),
+    }
+}
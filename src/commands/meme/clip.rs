@@ -0,0 +1,134 @@
+use std::time::Duration;
+
+use log::{
+    error,
+    info,
+};
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{
+    Result,
+    CONFIG,
+    audio::{
+        encode_opus,
+        MAX_CLIP_SECONDS,
+        Recorder,
+        VoiceManager,
+    },
+    db::{
+        Audio,
+        connection,
+        NewMeme,
+        RecordingOptOut,
+    },
+    util::CtxExt,
+};
+
+use super::{
+    create::report_save,
+    dupes::warn_near_duplicates,
+};
+
+#[command]
+#[aliases("join")]
+pub fn listen(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+    let (mgr_lock, recorder) = {
+        let data = ctx.data.read();
+        (data.get::<VoiceManager>().cloned().unwrap(), data.get::<Recorder>().cloned().unwrap())
+    };
+
+    let conn = connection()?;
+    recorder.lock().unwrap().set_excluded(RecordingOptOut::all(&conn)?);
+
+    let mut manager = mgr_lock.lock();
+
+    match manager.join(CONFIG.discord.guild(), CONFIG.discord.voice_channel()) {
+        Some(handler) => {
+            handler.listen(Some(Recorder::receiver(&recorder)));
+            info!("joined voice channel to listen");
+            msg.react(&ctx, "👂")
+        },
+        None => {
+            error!("couldn't join channel to listen");
+            ctx.send(msg.channel_id, "i can't get in", msg.tts)
+        },
+    }
+}
+
+#[command]
+pub fn clip(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let seconds = match args.single::<f32>() {
+        Ok(secs) if secs.is_finite() && secs > 0.0 => secs.min(MAX_CLIP_SECONDS as f32),
+        _ => return ctx.send(msg.channel_id, "how many seconds", msg.tts),
+    };
+
+    let title = match args.single_quoted::<String>() {
+        Ok(title) => title,
+        Err(_) => return ctx.send(msg.channel_id, "what do i call it", msg.tts),
+    };
+
+    let text = args.rest().to_owned();
+    let text = if text.is_empty() { None } else { Some(text) };
+
+    let conn = connection()?;
+    let recorder = ctx.data.read().get::<Recorder>().cloned().unwrap();
+
+    let pcm = {
+        let mut buffer = recorder.lock().unwrap();
+        buffer.set_excluded(RecordingOptOut::all(&conn)?);
+        buffer.clip(Duration::from_secs_f32(seconds))
+    };
+
+    if pcm.iter().all(|s| *s == 0) {
+        info!("clip requested with nothing recorded");
+        return ctx.send(msg.channel_id, "i didn't hear anything", msg.tts);
+    }
+
+    let audio_id = Audio::create(&conn, encode_opus(pcm)?, msg.author.id.0)?;
+
+    let save_result = NewMeme {
+        title,
+        content: text,
+        audio_id: Some(audio_id),
+        metadata_id: 0,
+    }.save(&conn, &[], msg.author.id.0);
+
+    if let Ok(ref meme) = save_result {
+        warn_near_duplicates(ctx, msg, &conn, meme);
+    }
+
+    report_save(ctx, msg, save_result.map(|_| {}))
+}
+
+#[command]
+#[aliases("dontrecordme", "optout")]
+pub fn norecord(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+    let conn = connection()?;
+    RecordingOptOut::add(&conn, msg.author.id.0)?;
+
+    let recorder = ctx.data.read().get::<Recorder>().cloned().unwrap();
+    recorder.lock().unwrap().set_excluded(RecordingOptOut::all(&conn)?);
+
+    info!("user {} opted out of recording", msg.author.id);
+    msg.react(&ctx, "🙉")
+}
+
+#[command]
+#[aliases("recordme", "optin")]
+pub fn record(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+    let conn = connection()?;
+    RecordingOptOut::remove(&conn, msg.author.id.0)?;
+
+    let recorder = ctx.data.read().get::<Recorder>().cloned().unwrap();
+    recorder.lock().unwrap().set_excluded(RecordingOptOut::all(&conn)?);
+
+    info!("user {} opted in to recording", msg.author.id);
+    msg.react(&ctx, "👂")
+}
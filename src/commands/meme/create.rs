@@ -6,7 +6,11 @@ use std::{
     },
 };
 
-use diesel::result::Error as DieselError;
+use chrono::Duration;
+use diesel::{
+    PgConnection,
+    result::Error as DieselError,
+};
 use log::{
     debug,
     error,
@@ -18,7 +22,10 @@ use serenity::{
         Delimiter,
         macros::command,
     },
-    model::channel::Message,
+    model::channel::{
+        Attachment,
+        Message,
+    },
     prelude::*,
 };
 use url::Url;
@@ -27,10 +34,21 @@ use anyhow::anyhow;
 use lazy_static::lazy_static;
 
 use crate::{
+    CONFIG,
     Result,
     audio::{
+        extract_audio,
+        ffmpeg_time_args,
+        is_audio,
+        is_gif,
+        is_video,
+        mp4_filename,
         parse_times,
-        ytdl_url,
+        parse_url_times,
+        resolve_times,
+        TimeParseError,
+        transcode_video,
+        ytdl_info,
     },
     db::{
         Audio,
@@ -41,6 +59,17 @@ use crate::{
     util::CtxExt,
 };
 
+use super::{
+    draft::{
+        Draft,
+        post_preview,
+    },
+    dupes::warn_near_duplicates,
+};
+
+/// Largest audio meme, in ffmpeg's notation. Anything longer is cut off.
+const AUDIO_MAX_SIZE: &str = "5M";
+
 lazy_static! {
     static ref DELIMS: Vec<Delimiter> = vec![' '.into(), '\n'.into(), '\t'.into()];
 }
@@ -56,39 +85,32 @@ pub fn addmeme(ctx: &mut Context, msg: &Message, args: Args) -> Result<()> {
 
     let conn = connection()?;
 
-    let image = msg.attachments.first();
-
-    if image.is_none() && text.is_none() {
+    if msg.attachments.is_empty() && text.is_none() {
         warn!("tried to create non-audio meme with no image or text");
         return ctx.send(msg.channel_id, "hahAA it's empty xdddd", msg.tts);
     }
 
-    let image_id = image.map(|att| {
-        let data = att.download()?;
-        Image::create(&conn, &att.filename, data, msg.author.id.0)
-    }).transpose()?;
+    let (image_ids, track) = match save_attachments(ctx, msg, &conn, &msg.attachments)? {
+        Some(saved) => saved,
+        None => return Ok(()),
+    };
+
+    let audio_id = track
+        .map(|data| Audio::create(&conn, data, msg.author.id.0))
+        .transpose()?;
 
     let save_result = NewMeme {
         title,
         content: text,
-        image_id,
-        audio_id: None,
+        audio_id,
         metadata_id: 0,
-    }.save(&conn, msg.author.id.0).map(|_| {});
+    }.save(&conn, &image_ids, msg.author.id.0);
 
-    use diesel::result::DatabaseErrorKind;
-    match save_result {
-        Ok(_) => msg.react(&ctx, "👌"),
-        Err(e) => {
-            if let Some(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) = e.downcast_ref::<DieselError>() {
-                error!("tried to create meme that already exists");
-                msg.react(&ctx, "❌")?;
-                return ctx.send(msg.channel_id, "that meme already exists", msg.tts);
-            }
-
-            return Err(e);
-        }
+    if let Ok(ref meme) = save_result {
+        warn_near_duplicates(ctx, msg, &conn, meme);
     }
+
+    report_save(ctx, msg, save_result.map(|_| {}))
 }
 
 #[command]
@@ -96,96 +118,303 @@ pub fn addaudiomeme(ctx: &mut Context, msg: &Message, args: Args) -> Result<()>
     let mut args = Args::new(args.rest(), DELIMS.as_ref());
 
     let title = args.single_quoted::<String>()?;
-    let audio_str = args.single_quoted::<String>()?;
 
-    let elems = audio_str.split_whitespace().collect::<Vec<_>>();
+    let from_link = args.quoted().current()
+        .and_then(|s| s.split_whitespace().next())
+        .map_or(false, |s| Url::parse(s).is_ok());
 
-    if elems.len() == 0 {
-        ctx.send(msg.channel_id, "are you stupid", msg.tts)?;
-        return Err(anyhow!("no audio link was provided"))
+    // without a link, the first sound or video attachment is the audio and the rest are images
+    let source_index = if from_link {
+        None
+    } else {
+        let has_sound = |att: &Attachment| is_audio(&att.filename) || (is_video(&att.filename) && !is_gif(&att.filename));
+
+        match msg.attachments.iter().position(has_sound) {
+            Some(i) => Some(i),
+            None => return ctx.send(msg.channel_id, "link it or attach it", msg.tts),
+        }
+    };
+
+    let (source, times) = match source_index {
+        Some(i) => {
+            // optional times, e.g. "start=0:05 end=0:10". text that merely starts with a keyword
+            // ("end of days") is left as text; only a `key=value` that doesn't parse is an error.
+            let token = args.quoted().current().map(str::to_owned);
+
+            let times = match token.as_ref().map(|token| (token, parse_times(token))) {
+                None | Some((_, Ok((None, None)))) => (None, None),
+                Some((_, Ok(times))) => {
+                    args.advance();
+                    times
+                },
+                Some((token, Err(e))) if token.contains('=') => {
+                    warn!("bad times for audio meme: {}", e);
+                    return ctx.send(msg.channel_id, &format!("bad time: {}", e), msg.tts);
+                },
+                Some((_, Err(_))) => (None, None),
+            };
+
+            (AudioSource::File(msg.attachments[i].download()?), times)
+        },
+        None => {
+            let audio_str = args.single_quoted::<String>()?;
+
+            match link_source(ctx, msg, &audio_str)? {
+                Some(source) => source,
+                None => return Ok(()),
+            }
+        },
+    };
+
+    let audio = match render_audio(ctx, msg, &source, times)? {
+        Some(data) => data,
+        None => return Ok(()),
+    };
+
+    let text = args.rest().to_owned();
+    let text = if text.is_empty() { None } else { Some(text) };
+
+    // the chosen audio wins over any video's own track. the images are fetched now, while the
+    // attachments are sure to be around, but only stored once the draft is confirmed.
+    let attachments = msg.attachments.iter()
+        .enumerate()
+        .filter(|&(i, _)| Some(i) != source_index)
+        .map(|(_, att)| att);
+
+    let images = match prepare_attachments(ctx, msg, attachments)? {
+        Some(images) => images,
+        None => return Ok(()),
+    };
+
+    // nothing is stored until the creator confirms the preview
+    post_preview(ctx, msg, Draft::new(msg, title, text, source, times, audio, images))
+}
+
+/// Store attachments as images for a meme's album, in order. Returns the image ids and the audio
+/// track of the first video with one, or `None` if an attachment was rejected, in which case the
+/// user has already been told.
+pub(super) fn save_attachments<'a, I: IntoIterator<Item = &'a Attachment>>(
+    ctx: &Context,
+    msg: &Message,
+    conn: &PgConnection,
+    attachments: I,
+) -> Result<Option<(Vec<i32>, Option<Vec<u8>>)>> {
+    match prepare_attachments(ctx, msg, attachments)? {
+        Some(images) => store_images(conn, images, msg.author.id.0).map(Some),
+        None => Ok(None),
     }
+}
 
-    let audio_link = Url::parse(elems[0])?;
-    let opts = elems[1..].join(" ");
-    let (start, end) = parse_times(opts);
+/// An attachment that's been downloaded, checked and (if need be) transcoded, ready to be stored
+/// as an image.
+#[derive(Clone, Debug)]
+pub(super) struct PreparedImage {
+    filename: String,
+    data: Vec<u8>,
+    /// A video's audio track, if it has one.
+    track: Option<Vec<u8>>,
+}
 
-    let youtube_url = ytdl_url(audio_link.as_str())?;
+/// Download and check attachments for a meme's album, in order, without storing anything. `None`
+/// if an attachment was rejected, in which case the user has already been told.
+pub(super) fn prepare_attachments<'a, I: IntoIterator<Item = &'a Attachment>>(
+    ctx: &Context,
+    msg: &Message,
+    attachments: I,
+) -> Result<Option<Vec<PreparedImage>>> {
+    let mut images = Vec::new();
+
+    for att in attachments {
+        match prepare_attachment(ctx, msg, att)? {
+            Some(image) => images.push(image),
+            None => return Ok(None),
+        }
+    }
+
+    Ok(Some(images))
+}
+
+/// Store prepared images, in order. Returns the image ids and the audio track of the first video
+/// with one.
+pub(super) fn store_images(conn: &PgConnection, images: Vec<PreparedImage>, by_user: u64) -> Result<(Vec<i32>, Option<Vec<u8>>)> {
+    let mut image_ids = Vec::with_capacity(images.len());
+    let mut track = None;
+
+    for image in images {
+        image_ids.push(Image::create(conn, &image.filename, image.data, by_user)?);
+        track = track.or(image.track);
+    }
+
+    Ok((image_ids, track))
+}
 
-    let duration_opts = if let Some(e) = end {
-        vec! [
-            "-ss".to_owned(), start.map_or_else(
-                || "00:00:00".to_owned(),
-                |s| format!("{:02}:{:02}:{:02}", s.num_hours(), s.num_minutes() % 60, s.num_seconds() % 60)
-            ),
+/// Download an attachment to be stored as an image. Videos (and GIFs) are posted the same way, but
+/// are size-checked and optionally transcoded, and their audio track (if any) is extracted so it
+/// can be played alongside.
+fn prepare_attachment(ctx: &Context, msg: &Message, att: &Attachment) -> Result<Option<PreparedImage>> {
+    if !is_video(&att.filename) {
+        return Ok(Some(PreparedImage {
+            filename: att.filename.clone(),
+            data: att.download()?,
+            track: None,
+        }));
+    }
+
+    let transcode = CONFIG.transcode_videos && !is_gif(&att.filename);
+    if att.size as usize > CONFIG.video_max_bytes && !transcode {
+        warn!("rejected {} byte video '{}'", att.size, att.filename);
+        return ctx.send(msg.channel_id, "that video is way too big", msg.tts).map(|_| None);
+    }
 
-            "-to".to_owned(), format!("{:02}:{:02}:{:02}", e.num_hours(), e.num_minutes() % 60, e.num_seconds() % 60),
-        ]
+    let data = att.download()?;
+
+    let (filename, data) = if transcode {
+        match transcode_video(&data, CONFIG.video_max_bytes)? {
+            Some(data) => (mp4_filename(&att.filename), data),
+            None => {
+                warn!("couldn't fit {} byte video '{}' in {} bytes", att.size, att.filename, CONFIG.video_max_bytes);
+                return ctx.send(msg.channel_id, "that video is too long to shrink down", msg.tts).map(|_| None);
+            },
+        }
     } else {
-        vec! []
+        (att.filename.clone(), data)
     };
 
-    let ffmpeg_command = Command::new("ffmpeg")
-        .arg("-i")
-        .arg(youtube_url)
-        .args(duration_opts)
-        .args(&[
-            "-ac", "2",
-            "-ar", "48000",
-            "-f", "opus",
-            "-acodec", "libopus",
-            "-b:a", "96k",
-            "-fs", "5M",
-            "-",
-        ])
-        .stdout(Stdio::piped())
-        .stderr(Stdio::null())
-        .stdin(Stdio::null())
-        .spawn()?;
-
-    let mut audio_reader = ffmpeg_command.stdout.unwrap();
+    let track = if is_gif(&filename) { None } else { extract_audio(&data, &[], None)? };
 
-    let text = args.rest().to_owned();
-    let text = if text.is_empty() { None } else { Some(text) };
+    Ok(Some(PreparedImage { filename, data, track }))
+}
 
-    let conn = connection()?;
+/// Start and end times as parsed, so possibly counting back from the end of the media.
+pub(super) type Times = (Option<Duration>, Option<Duration>);
+
+/// Where an audio meme's sound comes from, kept so that drafts can be rendered again with
+/// different times.
+#[derive(Clone, Debug)]
+pub(super) enum AudioSource {
+    /// Media resolved by youtube-dl, and its length, if known.
+    Link {
+        url: String,
+        length: Option<Duration>,
+    },
 
-    let image = msg.attachments.first()
-        .ok_or(anyhow!("no attachment"))
-        .and_then(|att| {
-            let data = att.download()?;
-            Image::create(&conn, &att.filename, data, msg.author.id.0)
-        })
-        .ok();
+    /// An attached sound or video. Its length isn't known up front, so times can't count back from
+    /// the end.
+    File(Vec<u8>),
+}
 
-    let mut audio_data = Vec::new();
-    let bytes = audio_reader.read_to_end(&mut audio_data)?;
+impl AudioSource {
+    /// Transcode the audio between `times` to opus. Fails with a [`TimeParseError`] if the times
+    /// don't fit this source, and returns `None` if there was no sound.
+    pub(super) fn render(&self, (start, end): Times) -> Result<Option<Vec<u8>>> {
+        let length = match self {
+            AudioSource::Link { length, .. } => *length,
+            AudioSource::File(_) => None,
+        };
+
+        let (start, end) = resolve_times(start, end, length)?;
+        let time_args = ffmpeg_time_args(start, end);
+
+        let url = match self {
+            AudioSource::File(data) => return extract_audio(data, &time_args, Some(AUDIO_MAX_SIZE)),
+            AudioSource::Link { url, .. } => url,
+        };
+
+        let ffmpeg_command = Command::new("ffmpeg")
+            .arg("-i")
+            .arg(url)
+            .args(time_args)
+            .args(&[
+                "-ac", "2",
+                "-ar", "48000",
+                "-f", "opus",
+                "-acodec", "libopus",
+                "-b:a", "96k",
+                "-fs", AUDIO_MAX_SIZE,
+                "-",
+            ])
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .stdin(Stdio::null())
+            .spawn()?;
+
+        let mut audio_reader = ffmpeg_command.stdout.unwrap();
+
+        let mut audio_data = Vec::new();
+        let bytes = audio_reader.read_to_end(&mut audio_data)?;
+
+        if bytes == 0 {
+            debug!("read 0 bytes from audio reader");
+            return Ok(None);
+        }
 
-    if bytes == 0 {
-        debug!("read 0 bytes from audio reader");
-        return ctx.send(msg.channel_id, "🔇🔇🔇🔕🔕🔕🔕🔕🔇🔕🔕🔇🔕🔕📣📢📣📢📣", msg.tts);
+        Ok(Some(audio_data))
     }
+}
 
-    let audio_id = Audio::create(&conn, audio_data, msg.author.id.0)?;
+/// Render `source` between `times`. Returns `None` if that didn't work out, in which case the user
+/// has already been told.
+pub(super) fn render_audio(ctx: &Context, msg: &Message, source: &AudioSource, times: Times) -> Result<Option<Vec<u8>>> {
+    match source.render(times) {
+        Ok(Some(data)) => Ok(Some(data)),
+        Ok(None) => ctx.send(msg.channel_id, "🔇🔇🔇🔕🔕🔕🔕🔕🔇🔕🔕🔇🔕🔕📣📢📣📢📣", msg.tts).map(|_| None),
+        Err(e) => match e.downcast_ref::<TimeParseError>() {
+            Some(e) => {
+                warn!("bad times for audio meme: {}", e);
+                ctx.send(msg.channel_id, &format!("bad time: {}", e), msg.tts).map(|_| None)
+            },
+            None => Err(e),
+        },
+    }
+}
 
-    let save_result = NewMeme {
-        title,
-        content: text,
-        image_id: image,
-        audio_id: Some(audio_id),
-        metadata_id: 0,
-    }.save(&conn, msg.author.id.0).map(|_| {});
+/// Resolve `audio_str` (a link, optionally followed by times) with youtube-dl. Returns `None` if
+/// the request was bad, in which case the user has already been told.
+fn link_source(ctx: &Context, msg: &Message, audio_str: &str) -> Result<Option<(AudioSource, Times)>> {
+    let elems = audio_str.split_whitespace().collect::<Vec<_>>();
+
+    if elems.len() == 0 {
+        ctx.send(msg.channel_id, "are you stupid", msg.tts)?;
+        return Err(anyhow!("no audio link was provided"))
+    }
+
+    let audio_link = Url::parse(elems[0])?;
+    let opts = elems[1..].join(" ");
+    let times = match parse_url_times(&audio_link, opts) {
+        Ok(times) => times,
+        Err(e) => {
+            warn!("bad times for audio meme: {}", e);
+            return ctx.send(msg.channel_id, &format!("bad time: {}", e), msg.tts).map(|_| None);
+        },
+    };
+
+    let info = ytdl_info(audio_link.as_str())?;
+
+    Ok(Some((AudioSource::Link { url: info.url, length: info.duration }, times)))
+}
+
+/// Download and transcode the audio described by `audio_str` (a link, optionally followed by
+/// times). Returns `None` if the request was bad, in which case the user has already been told.
+pub(super) fn fetch_audio(ctx: &Context, msg: &Message, audio_str: &str) -> Result<Option<Vec<u8>>> {
+    match link_source(ctx, msg, audio_str)? {
+        Some((source, times)) => render_audio(ctx, msg, &source, times),
+        None => Ok(None),
+    }
+}
 
+/// React to the outcome of saving a new meme, translating title collisions into a message.
+pub(super) fn report_save(ctx: &Context, msg: &Message, save_result: Result<()>) -> Result<()> {
     use diesel::result::DatabaseErrorKind;
     match save_result {
-        Ok(_) => msg.react(&ctx, "👌"),
+        Ok(_) => msg.react(ctx, "👌"),
         Err(e) => {
             if let Some(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) = e.downcast_ref::<DieselError>() {
                 error!("tried to create meme that already exists");
-                msg.react(&ctx, "❌")?;
+                msg.react(ctx, "❌")?;
                 return ctx.send(msg.channel_id, "that meme already exists", msg.tts);
             }
 
-            return Err(e);
+            Err(e)
         }
     }
 }
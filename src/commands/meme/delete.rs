@@ -1,6 +1,9 @@
 use diesel::{
     NotFound,
-    result::Error as DieselError,
+    result::{
+        DatabaseErrorKind,
+        Error as DieselError,
+    },
 };
 use log::info;
 use serenity::{
@@ -13,14 +16,21 @@ use serenity::{
 };
 
 use crate::{
+    CONFIG,
     Result,
     db::{
         connection,
         delete_meme,
+        Tombstone,
+        undelete_meme,
     },
     util::CtxExt,
 };
 
+use super::history::TIME_FORMATTER;
+
+const GRAVEYARD_SIZE: usize = 10;
+
 #[command]
 #[aliases("delmem")]
 pub fn delmeme(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
@@ -43,3 +53,61 @@ pub fn delmeme(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
         }
     }
 }
+
+#[command]
+#[aliases("undelmeme", "undelmem", "resurrect")]
+pub fn undelete(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let title = args.single_quoted::<String>()?;
+
+    let conn = connection()?;
+
+    match undelete_meme(&conn, &title, msg.author.id.0) {
+        Ok(meme) => {
+            info!("meme '{}' restored by {}", meme.title, msg.author.id);
+            msg.react(ctx, "🧟")
+        },
+        Err(e) => match e.downcast_ref::<DieselError>() {
+            Some(NotFound) => {
+                info!("attempted to undelete meme with no tombstone: '{}'", title);
+                ctx.send(msg.channel_id, "it's gone. it's gone forever", msg.tts)
+            },
+            Some(DieselError::DatabaseError(DatabaseErrorKind::UniqueViolation, _)) => {
+                info!("attempted to undelete meme whose title is taken: '{}'", title);
+                ctx.send(msg.channel_id, "something else lives there now", msg.tts)
+            },
+            _ => Err(e),
+        },
+    }
+}
+
+#[command]
+#[aliases("deleted", "morgue")]
+pub fn graveyard(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+    use itertools::Itertools;
+
+    let conn = connection()?;
+
+    let tombstones = Tombstone::recent(&conn, GRAVEYARD_SIZE)?;
+
+    if tombstones.is_empty() {
+        return ctx.send(msg.channel_id, "no one has died", msg.tts);
+    }
+
+    let now = chrono::Utc::now().naive_utc();
+    let retention = chrono::Duration::days(CONFIG.tombstone_retention_days);
+
+    let resp = tombstones.into_iter()
+        .map(|t| {
+            let ago = TIME_FORMATTER.convert((now - t.deleted_at).to_std().unwrap_or_default());
+            let left = TIME_FORMATTER.convert((t.deleted_at + retention - now).to_std().unwrap_or_default());
+
+            let deleter = CONFIG.discord.guild().member(&ctx, t.deleted_by as u64)
+                .map(|m| m.display_name().into_owned())
+                .unwrap_or("???".to_owned());
+
+            format!("\"{}\" killed by {} {}. gone for good in {}.", t.title.unwrap_or_default(), deleter, ago, left)
+        })
+        .join("\n");
+
+    ctx.send(msg.channel_id, &resp, msg.tts)
+}
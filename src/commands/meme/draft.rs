@@ -0,0 +1,325 @@
+use std::{
+    sync::Mutex,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use fnv::FnvHashMap;
+use lazy_static::lazy_static;
+use log::{
+    debug,
+    error,
+    info,
+};
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    http::AttachmentType,
+    model::{
+        channel::{
+            Message,
+            Reaction,
+            ReactionType,
+        },
+        id::{
+            ChannelId,
+            MessageId,
+            UserId,
+        },
+    },
+    prelude::*,
+};
+
+use crate::{
+    Error,
+    Result,
+    audio::parse_times,
+    db::{
+        Audio,
+        connection,
+        NewMeme,
+    },
+    util::CtxExt,
+};
+
+use super::{
+    create::{
+        AudioSource,
+        PreparedImage,
+        render_audio,
+        report_save,
+        store_images,
+        Times,
+    },
+    dupes::warn_near_duplicates,
+    queue_meme_audio,
+};
+
+const CONFIRM: &str = "✅";
+const SCRAP: &str = "❌";
+
+/// How long an unconfirmed audio meme hangs around before it's forgotten.
+const DRAFT_TTL: Duration = Duration::from_secs(10 * 60);
+
+const PREVIEW_FILENAME: &str = "draft.ogg";
+
+/// What a preview is changed to say once its draft is gone.
+const EXPIRED: &str = "this draft expired. `addaudiomeme` again to start over";
+const RETRIMMED: &str = "this draft was retrimmed, see below";
+
+/// An audio meme waiting on its creator's go-ahead. Nothing is stored until it's confirmed, but
+/// everything it needs is held onto, so confirming doesn't depend on the command message or its
+/// attachments still being around.
+pub(super) struct Draft {
+    title: String,
+    text: Option<String>,
+    author: UserId,
+    channel: ChannelId,
+    source: AudioSource,
+    times: Times,
+    audio: Vec<u8>,
+    images: Vec<PreparedImage>,
+    created: Instant,
+}
+
+impl Draft {
+    pub(super) fn new(
+        msg: &Message,
+        title: String,
+        text: Option<String>,
+        source: AudioSource,
+        times: Times,
+        audio: Vec<u8>,
+        images: Vec<PreparedImage>,
+    ) -> Self {
+        Draft {
+            title,
+            text,
+            author: msg.author.id,
+            channel: msg.channel_id,
+            source,
+            times,
+            audio,
+            images,
+            created: Instant::now(),
+        }
+    }
+}
+
+lazy_static! {
+    /// Drafts by the id of their preview message.
+    static ref DRAFTS: Mutex<FnvHashMap<MessageId, Draft>> = Mutex::new(FnvHashMap::default());
+}
+
+/// Change a preview to say why it's no longer any good, and take its reactions away.
+fn retire_preview(ctx: &Context, channel: ChannelId, preview: MessageId, note: &str) {
+    if let Err(e) = channel.edit_message(ctx, preview, |m| m.content(note)) {
+        error!("retiring draft preview: {}", e);
+    }
+
+    if let Err(e) = channel.delete_reactions(ctx, preview) {
+        debug!("clearing draft reactions: {}", e);
+    }
+}
+
+/// Forget drafts that have waited too long, saying so on their previews. Returns those previews.
+fn prune_drafts(ctx: &Context) -> Vec<MessageId> {
+    let expired = {
+        let mut drafts = DRAFTS.lock().unwrap();
+
+        let expired = drafts.iter()
+            .filter(|(_, d)| d.created.elapsed() >= DRAFT_TTL)
+            .map(|(&id, d)| (id, d.channel))
+            .collect::<Vec<_>>();
+
+        for (id, _) in &expired {
+            drafts.remove(id);
+        }
+
+        expired
+    };
+
+    for &(id, channel) in &expired {
+        retire_preview(ctx, channel, id, EXPIRED);
+    }
+
+    expired.into_iter().map(|(id, _)| id).collect()
+}
+
+/// Post a draft's clip for its creator to check, playing it too if anyone's listening.
+pub(super) fn post_preview(ctx: &Context, msg: &Message, draft: Draft) -> Result<()> {
+    let text = format!(
+        "here's *{}*. {} to save it, {} to scrap it, or `retrim start=... end=...` to have another go",
+        draft.title, CONFIRM, SCRAP,
+    );
+
+    let preview = draft.channel.send_files(ctx, vec![AttachmentType::Bytes((draft.audio.as_slice(), PREVIEW_FILENAME))], |m| m.content(text))?;
+
+    if ctx.users_listening().unwrap_or(false) {
+        queue_meme_audio(ctx, msg, draft.audio.clone(), 1.0);
+    }
+
+    prune_drafts(ctx);
+    DRAFTS.lock().unwrap().insert(preview.id, draft);
+
+    preview.react(ctx, CONFIRM)?;
+    preview.react(ctx, SCRAP)?;
+
+    Ok(())
+}
+
+/// Save or scrap a draft when its creator reacts to the preview.
+pub fn handle_draft_reaction(ctx: &Context, reaction: &Reaction) -> Result<()> {
+    let confirmed = match reaction.emoji {
+        ReactionType::Unicode(ref s) if s == CONFIRM => true,
+        ReactionType::Unicode(ref s) if s == SCRAP => false,
+        _ => return Ok(()),
+    };
+
+    if reaction.user_id == ctx.cache.read().user.id {
+        return Ok(());
+    }
+
+    if prune_drafts(ctx).contains(&reaction.message_id) {
+        return Ok(());
+    }
+
+    let author = DRAFTS.lock().unwrap().get(&reaction.message_id).map(|d| d.author);
+
+    match author {
+        Some(author) if author == reaction.user_id => {},
+        Some(_) => return Ok(()),
+        None => return note_lost_draft(ctx, reaction),
+    }
+
+    let draft = match DRAFTS.lock().unwrap().remove(&reaction.message_id) {
+        Some(draft) => draft,
+        // another reaction got to it first
+        None => return Ok(()),
+    };
+
+    if let Err(e) = reaction.channel_id.delete_reactions(ctx, reaction.message_id) {
+        debug!("clearing draft reactions: {}", e);
+    }
+
+    if !confirmed {
+        info!("scrapped draft of '{}'", draft.title);
+        return reaction.channel_id.create_reaction(ctx, reaction.message_id, "🗑").map_err(Error::from);
+    }
+
+    let result = reaction.message(ctx)
+        .map_err(Error::from)
+        .and_then(|preview| save_draft(ctx, &preview, &draft));
+
+    if let Err(e) = result {
+        // keep it for another go
+        error!("saving draft of '{}': {}", draft.title, e);
+        DRAFTS.lock().unwrap().insert(reaction.message_id, draft);
+
+        for &emoji in &[CONFIRM, SCRAP] {
+            if let Err(e) = reaction.channel_id.create_reaction(ctx, reaction.message_id, emoji) {
+                debug!("restoring draft reactions: {}", e);
+            }
+        }
+
+        return ctx.send(reaction.channel_id, &format!("couldn't save that. {} to try again", CONFIRM), false);
+    }
+
+    Ok(())
+}
+
+/// Store a confirmed draft, reporting back on its preview.
+fn save_draft(ctx: &Context, preview: &Message, draft: &Draft) -> Result<()> {
+    let conn = connection()?;
+
+    let (image_ids, _) = store_images(&conn, draft.images.clone(), draft.author.0)?;
+    let audio_id = Audio::create(&conn, draft.audio.clone(), draft.author.0)?;
+
+    let save_result = NewMeme {
+        title: draft.title.clone(),
+        content: draft.text.clone(),
+        audio_id: Some(audio_id),
+        metadata_id: 0,
+    }.save(&conn, &image_ids, draft.author.0);
+
+    if let Ok(ref meme) = save_result {
+        warn_near_duplicates(ctx, preview, &conn, meme);
+    }
+
+    report_save(ctx, preview, save_result.map(|_| {}))
+}
+
+/// Someone reacted to what might be the preview of a draft that's gone, say since a restart. If it
+/// is one, say it's expired rather than leave them wondering.
+fn note_lost_draft(ctx: &Context, reaction: &Reaction) -> Result<()> {
+    let msg = reaction.message(ctx)?;
+
+    let is_preview = msg.author.id == ctx.cache.read().user.id
+        && msg.content != EXPIRED
+        && msg.attachments.iter().any(|att| att.filename == PREVIEW_FILENAME);
+
+    if is_preview {
+        retire_preview(ctx, reaction.channel_id, reaction.message_id, EXPIRED);
+    }
+
+    Ok(())
+}
+
+/// Take the newest live draft `user` has going in `channel` out of the pile, along with the id of
+/// its preview.
+fn take_draft(ctx: &Context, user: UserId, channel: ChannelId) -> Option<(MessageId, Draft)> {
+    prune_drafts(ctx);
+
+    let mut drafts = DRAFTS.lock().unwrap();
+
+    let id = drafts.iter()
+        .filter(|(_, d)| d.author == user && d.channel == channel)
+        .max_by_key(|(_, d)| d.created)
+        .map(|(&id, _)| id)?;
+
+    drafts.remove(&id).map(|draft| (id, draft))
+}
+
+#[command]
+#[aliases("trim")]
+pub fn retrim(ctx: &mut Context, msg: &Message, args: Args) -> Result<()> {
+    let times = match parse_times(args.rest()) {
+        Ok((None, None)) => return ctx.send(msg.channel_id, "trim it to what", msg.tts),
+        Ok(times) => times,
+        Err(e) => return ctx.send(msg.channel_id, &format!("bad time: {}", e), msg.tts),
+    };
+
+    let (preview_id, mut draft) = match take_draft(ctx, msg.author.id, msg.channel_id) {
+        Some(taken) => taken,
+        None => return ctx.send(msg.channel_id, "you don't have a draft going", msg.tts),
+    };
+
+    // whichever of start and end aren't given stay where they were
+    let times = (times.0.or(draft.times.0), times.1.or(draft.times.1));
+
+    match render_audio(ctx, msg, &draft.source, times) {
+        Ok(Some(audio)) => {
+            draft.audio = audio;
+            draft.times = times;
+            draft.created = Instant::now();
+
+            post_preview(ctx, msg, draft)?;
+            retire_preview(ctx, msg.channel_id, preview_id, RETRIMMED);
+
+            Ok(())
+        },
+        result => {
+            // the old cut still stands
+            if let Err(ref e) = result {
+                error!("retrimming '{}': {}", draft.title, e);
+            }
+
+            DRAFTS.lock().unwrap().insert(preview_id, draft);
+            result.map(|_| ())
+        },
+    }
+}
@@ -0,0 +1,80 @@
+use diesel::PgConnection;
+use itertools::Itertools;
+use log::{
+    error,
+    info,
+};
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{
+    Result,
+    db::{
+        backfill_perceptual,
+        connection,
+        duplicate_clusters,
+        Meme,
+        near_duplicates,
+    },
+    util::CtxExt,
+};
+
+/// Point out existing memes that look or sound like a freshly saved one. Failing to check is only
+/// logged; the meme has been saved either way.
+pub(super) fn warn_near_duplicates(ctx: &Context, msg: &Message, conn: &PgConnection, meme: &Meme) {
+    let result = near_duplicates(conn, meme).and_then(|dupes| {
+        if dupes.is_empty() {
+            return Ok(());
+        }
+
+        info!("meme '{}' is a near-duplicate of {} others", meme.title, dupes.len());
+
+        let titles = dupes.iter().map(|m| format!("\"{}\"", m.title)).join(", ");
+        ctx.send(msg.channel_id, &format!("heads up, this is a lot like {}", titles), msg.tts)
+    });
+
+    if let Err(e) = result {
+        error!("checking for near-duplicates of '{}': {}", meme.title, e);
+    }
+}
+
+#[command]
+#[aliases("dupe", "duplicates")]
+pub fn dupes(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+    let conn = connection()?;
+
+    let hashed = backfill_perceptual(&conn)?;
+    if hashed > 0 {
+        info!("computed {} missing perceptual hashes", hashed);
+    }
+
+    let clusters = duplicate_clusters(&conn)?;
+
+    if clusters.is_empty() {
+        return ctx.send(msg.channel_id, "no dupes. we're all original here", msg.tts);
+    }
+
+    let footer = format!("{} clusters of likely duplicates", clusters.len());
+
+    let body = clusters.iter()
+        .enumerate()
+        .map(|(i, cluster)| format!("{}. {}", i + 1, cluster.iter().map(|m| format!("*{}*", m.title)).join(", ")))
+        .scan(footer.len() + 1, |state, line| {
+            *state += line.len() + 1;
+
+            if *state < 2000 {
+                Some(line)
+            } else {
+                None
+            }
+        })
+        .join("\n");
+
+    ctx.send(msg.channel_id, &format!("{}\n{}", body, footer), msg.tts)
+}
@@ -0,0 +1,232 @@
+use diesel::{
+    NotFound,
+    result::Error as DieselError,
+};
+use lazy_static::lazy_static;
+use log::{
+    info,
+    warn,
+};
+use regex::Regex;
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{
+    Result,
+    db::{
+        Audio,
+        connection,
+        Meme,
+        MemeChanges,
+    },
+    util::CtxExt,
+};
+
+use super::{
+    create::{
+        fetch_audio,
+        report_save,
+        save_attachments,
+    },
+    dupes::warn_near_duplicates,
+};
+
+lazy_static! {
+    static ref FIELD_REGEX: Regex = Regex::new(r"(?i)(?:^|\s)(title|text|audio|image)\s*=").unwrap();
+}
+
+/// Value that clears an optional field.
+const NONE: &str = "none";
+
+/// The fields requested by an `editmeme` invocation, as given by the user.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+struct EditArgs {
+    title: Option<String>,
+    text: Option<String>,
+    audio: Option<String>,
+    image: Option<String>,
+}
+
+/// Split `title=.. text=.. audio=.. image=..` into its fields. Each value runs until the next
+/// field (or the end of the input), so text can contain spaces without quoting.
+fn parse_edits(s: &str) -> std::result::Result<EditArgs, String> {
+    let fields = FIELD_REGEX.captures_iter(s)
+        .map(|c| (c.get(1).unwrap().as_str().to_lowercase(), c.get(0).unwrap().start(), c.get(0).unwrap().end()))
+        .collect::<Vec<_>>();
+
+    let leading = &s[..fields.first().map_or(s.len(), |f| f.1)];
+    if !leading.trim().is_empty() {
+        return Err(format!("don't know what to do with {:?}", leading.trim()));
+    }
+
+    let mut result = EditArgs::default();
+
+    for (i, (field, _, value_start)) in fields.iter().enumerate() {
+        let value_end = fields.get(i + 1).map_or(s.len(), |f| f.1);
+        let value = unquote(s[*value_start..value_end].trim()).to_owned();
+
+        let slot = match field.as_str() {
+            "title" => &mut result.title,
+            "text" => &mut result.text,
+            "audio" => &mut result.audio,
+            "image" => &mut result.image,
+            _ => unreachable!(),
+        };
+
+        if slot.is_some() {
+            return Err(format!("{} given twice", field));
+        }
+
+        *slot = Some(value);
+    }
+
+    Ok(result)
+}
+
+fn unquote(s: &str) -> &str {
+    if s.len() >= 2 && s.starts_with('"') && s.ends_with('"') {
+        &s[1..s.len() - 1]
+    } else {
+        s
+    }
+}
+
+#[command]
+#[aliases("editmem", "memedit")]
+pub fn editmeme(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let title = args.single_quoted::<String>()?;
+
+    let edits = match parse_edits(args.rest()) {
+        Ok(edits) => edits,
+        Err(e) => {
+            warn!("bad editmeme arguments: {}", e);
+            return ctx.send(msg.channel_id, &e, msg.tts);
+        },
+    };
+
+    let conn = connection()?;
+
+    let meme = match Meme::by_title(&conn, &title) {
+        Ok(meme) => meme,
+        Err(e) => {
+            if let Some(NotFound) = e.downcast_ref::<DieselError>() {
+                info!("attempted to edit nonexistent meme: '{}'", title);
+                return ctx.send(msg.channel_id, "never heard of it", msg.tts);
+            }
+
+            return Err(e);
+        },
+    };
+
+    let mut changes = MemeChanges::default();
+
+    match edits.title {
+        Some(ref t) if t.is_empty() => return ctx.send(msg.channel_id, "it needs a name", msg.tts),
+        Some(t) => changes.title = Some(t),
+        None => {},
+    }
+
+    if let Some(text) = edits.text {
+        changes.content = Some(if text.is_empty() || text.eq_ignore_ascii_case(NONE) { None } else { Some(text) });
+    }
+
+    if !msg.attachments.is_empty() {
+        let (image_ids, track) = match save_attachments(ctx, msg, &conn, &msg.attachments)? {
+            Some(saved) => saved,
+            None => return Ok(()),
+        };
+
+        changes.images = Some(image_ids);
+
+        // a video brings its own audio, unless told otherwise
+        if let (Some(track), None) = (track, &edits.audio) {
+            changes.audio_id = Some(Some(Audio::create(&conn, track, msg.author.id.0)?));
+        }
+    } else if let Some(image) = edits.image {
+        if !image.eq_ignore_ascii_case(NONE) {
+            return ctx.send(msg.channel_id, "attach the new images, or say image=none to remove them", msg.tts);
+        }
+
+        changes.images = Some(Vec::new());
+    }
+
+    if let Some(audio) = edits.audio {
+        if audio.eq_ignore_ascii_case(NONE) {
+            changes.audio_id = Some(None);
+        } else {
+            let data = match fetch_audio(ctx, msg, &audio)? {
+                Some(data) => data,
+                None => return Ok(()),
+            };
+
+            changes.audio_id = Some(Some(Audio::create(&conn, data, msg.author.id.0)?));
+        }
+    }
+
+    if changes.is_empty() {
+        return ctx.send(msg.channel_id, "edit what", msg.tts);
+    }
+
+    let image_ids = meme.image_ids(&conn)?;
+
+    let has_content = changes.content.as_ref().unwrap_or(&meme.content).is_some() ||
+        !changes.images.as_ref().unwrap_or(&image_ids).is_empty() ||
+        changes.audio_id.unwrap_or(meme.audio_id).is_some();
+
+    if !has_content {
+        warn!("tried to edit meme '{}' down to nothing", meme.title);
+        return ctx.send(msg.channel_id, "hahAA it's empty xdddd", msg.tts);
+    }
+
+    let result = meme.update(&conn, &changes, msg.author.id.0);
+    if let Ok(ref updated) = result {
+        info!("meme '{}' edited by {}: {:?}", updated.title, msg.author.id, changes.describe(&meme, &image_ids));
+
+        if changes.images.is_some() || changes.audio_id.is_some() {
+            warn_near_duplicates(ctx, msg, &conn, updated);
+        }
+    }
+
+    report_save(ctx, msg, result.map(|_| {}))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_edits() {
+        assert_eq!(Ok(EditArgs::default()), parse_edits(""));
+
+        assert_eq!(Ok(EditArgs {
+            title: Some("new title".to_owned()),
+            ..Default::default()
+        }), parse_edits(r#"title="new title""#));
+
+        assert_eq!(Ok(EditArgs {
+            title: Some("better".to_owned()),
+            text: Some("some text with spaces\nand lines".to_owned()),
+            ..Default::default()
+        }), parse_edits("title=better text=some text with spaces\nand lines"));
+
+        assert_eq!(Ok(EditArgs {
+            audio: Some("https://youtu.be/xyz start=0:10 dur=5".to_owned()),
+            image: Some("none".to_owned()),
+            ..Default::default()
+        }), parse_edits("audio=https://youtu.be/xyz start=0:10 dur=5 IMAGE = none"));
+
+        assert_eq!(Ok(EditArgs {
+            text: Some("".to_owned()),
+            ..Default::default()
+        }), parse_edits("text="));
+
+        assert!(parse_edits("title=a title=b").is_err());
+        assert!(parse_edits("huh title=a").is_err());
+    }
+}
@@ -1,7 +1,14 @@
+use chrono::{
+    Duration,
+    NaiveDate,
+    NaiveDateTime,
+    Utc,
+};
 use diesel::{
     NotFound,
     result::Error as DieselError,
 };
+use itertools::Itertools;
 use log::{
     debug,
     error,
@@ -12,7 +19,10 @@ use serenity::{
         Args,
         macros::command,
     },
-    model::channel::Message,
+    model::{
+        channel::Message,
+        id::UserId,
+    },
     prelude::*,
 };
 use timeago::{
@@ -20,24 +30,29 @@ use timeago::{
     TimeUnit,
 };
 
-use anyhow::anyhow;
 use lazy_static::lazy_static;
 
 use crate::{
     db::{
         self,
+        Ambiguous,
         connection,
+        find_meme,
         InvocationRecord,
         Meme,
+        meme_stats,
         Metadata,
+        TimeRange,
     },
     CONFIG,
     Result,
     util::CtxExt,
 };
 
+use super::search::did_you_mean;
+
 lazy_static! {
-    static ref TIME_FORMATTER: Formatter = {
+    pub(super) static ref TIME_FORMATTER: Formatter = {
         let mut f = Formatter::new();
         f.min_unit(TimeUnit::Minutes);
         f.num_items(2);
@@ -46,7 +61,27 @@ lazy_static! {
     };
 }
 
-static CLEAN_DATE_FORMAT: &'static str = "%b %-e %Y";
+pub(super) static CLEAN_DATE_FORMAT: &'static str = "%b %-e %Y";
+
+const RANGE_DATE_FORMAT: &str = "%Y-%m-%d";
+
+const TOP_INVOKERS: usize = 3;
+
+/// The most months `memestats` will draw, most recent last.
+const SPARKLINE_MONTHS: usize = 36;
+
+const SPARKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+
+/// One bar per value, scaled to the largest. Only zero gets the lowest bar.
+pub(super) fn sparkline(values: &[usize]) -> String {
+    let max = values.iter().copied().max().unwrap_or(0);
+    let top = SPARKS.len() - 1;
+
+    values.iter()
+        .map(|&v| if max == 0 { 0 } else { (v * top + max - 1) / max })
+        .map(|i| SPARKS[i])
+        .collect()
+}
 
 #[command]
 #[aliases("what")]
@@ -93,8 +128,6 @@ pub fn wat(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
 
 #[command]
 pub fn history(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
-    use itertools::Itertools;
-
     let conn = connection()?;
 
     let n = args.single_quoted::<usize>().unwrap_or(CONFIG.default_hist);
@@ -119,8 +152,8 @@ pub fn history(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
         .enumerate()
         .rev()
         .map(|(i, rec)| {
-            let dt = chrono::DateTime::from_utc(rec.time, chrono::Utc{});
-            let ago = TIME_FORMATTER.convert((chrono::Utc::now() - dt).to_std().unwrap());
+            let dt = chrono::DateTime::from_utc(rec.time, Utc{});
+            let ago = TIME_FORMATTER.convert((Utc::now() - dt).to_std().unwrap());
 
             let rand = if rec.random { "R, " } else { "" };
             Meme::find(&conn, rec.meme_id)
@@ -148,86 +181,174 @@ pub fn history(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
     ctx.send(msg.channel_id, &resp, false)
 }
 
+/// Parse a stats window: `today`, `week`, `month` or `year` (counting back from `now`), and/or
+/// `since=YYYY-MM-DD` and `until=YYYY-MM-DD` (inclusive). Fails with the word it didn't understand.
+pub(super) fn parse_range(s: &str, now: NaiveDateTime) -> std::result::Result<TimeRange, String> {
+    let mut range = TimeRange::default();
+
+    let date = |word: &str, value: &str| NaiveDate::parse_from_str(value, RANGE_DATE_FORMAT)
+        .map(|d| d.and_hms(0, 0, 0))
+        .map_err(|_| word.to_owned());
+
+    for word in s.split_whitespace() {
+        let lower = word.to_lowercase();
+
+        let since = match lower.as_str() {
+            "today" => Some(now.date().and_hms(0, 0, 0)),
+            "week" => Some(now - Duration::days(7)),
+            "month" => Some(now - Duration::days(30)),
+            "year" => Some(now - Duration::days(365)),
+            _ => None,
+        };
+
+        if since.is_some() {
+            range.since = since;
+        } else if lower.starts_with("since=") {
+            range.since = Some(date(word, &word["since=".len()..])?);
+        } else if lower.starts_with("until=") {
+            range.until = Some(date(word, &word["until=".len()..])? + Duration::days(1));
+        } else {
+            return Err(word.to_owned());
+        }
+    }
+
+    Ok(range)
+}
+
+/// "this week"-style description of a range, for headers.
+pub(super) fn describe_range(range: &TimeRange) -> String {
+    let day = |t: NaiveDateTime| t.date().format(CLEAN_DATE_FORMAT);
+
+    match (range.since, range.until) {
+        (None, None) => "of all time".to_owned(),
+        (Some(since), None) => format!("since {}", day(since)),
+        (None, Some(until)) => format!("through {}", day(until - Duration::days(1))),
+        (Some(since), Some(until)) => format!("from {} through {}", day(since), day(until - Duration::days(1))),
+    }
+}
+
+/// The window given in a command's arguments, or `None` if it didn't parse, in which case the user
+/// has already been told.
+pub(super) fn range_arg(ctx: &Context, msg: &Message, args: &Args) -> Result<Option<TimeRange>> {
+    match parse_range(args.rest(), Utc::now().naive_utc()) {
+        Ok(range) => Ok(Some(range)),
+        Err(word) => {
+            info!("bad stats range: {:?}", word);
+            ctx.send(msg.channel_id, &format!("what's \"{}\"? try week, month, year, since=YYYY-MM-DD or until=YYYY-MM-DD", word), msg.tts)?;
+            Ok(None)
+        },
+    }
+}
+
+pub(super) fn username(ctx: &Context, user_id: u64) -> Result<String> {
+    let user = UserId(user_id).to_user(ctx)?;
+    Ok(user.nick_in(ctx, CONFIG.discord.guild()).unwrap_or(user.name))
+}
+
 #[command]
 #[aliases("stat")]
-pub fn stats(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
-    use db;
-    use serenity::model::{
-        id::UserId,
-        user::User,
+pub fn stats(ctx: &mut Context, msg: &Message, args: Args) -> Result<()> {
+    let range = match range_arg(ctx, msg, &args)? {
+        Some(range) => range,
+        None => return Ok(()),
     };
 
     let conn = connection()?;
-    let stats = db::stats(&conn)?;
-
-    debug!("reporting stats");
-
-    let rand_user: User = UserId(stats.most_random_meme_user).to_user(&ctx)?;
-    let direct_user: User = UserId(stats.most_directly_named_meme_user).to_user(&ctx)?;
-
-    let rand_user = rand_user.nick_in(&ctx, CONFIG.discord.guild()).unwrap_or(rand_user.name);
-    let direct_user = direct_user.nick_in(&ctx, CONFIG.discord.guild()).unwrap_or(direct_user.name);
-
-    let s = format!(
-        r#"
-**{}** memes stored
-**{}** memes with audio ({:0.1}%)
-**{}** memes with images ({:0.1}%)
-
-started recording meme invocations on *{}* ({})
-**{}** total meme invocations recorded
-**{}** of which were random ({:0.1}%)
-and **{}** were audio ({:0.1}%)
-
-the most active day was *{}* with **{}** memes
-and the loudest day was *{}* with **{}** audio memes
-
-**{}** has invoked the most random memes ({})
-**{}** has invoked the most memes by name ({})
-
-*{}* was the meme specifically requested the most ({})
-*{}* was the meme randomly invoked the most ({})
-and *{}* was the most-memed overall ({})"#,
-        stats.memes_overall,
-        stats.audio_memes,
-        (stats.audio_memes as f64) / (stats.memes_overall as f64) * 100.,
-        stats.image_memes,
-        (stats.image_memes as f64) / (stats.memes_overall as f64) * 100.,
-        stats.started_recording.date().format(CLEAN_DATE_FORMAT),
-        TIME_FORMATTER.convert((chrono::Utc::now() - stats.started_recording).to_std().unwrap()),
-        stats.total_meme_invocations,
-        stats.random_meme_invocations,
-        (stats.random_meme_invocations as f64) / (stats.total_meme_invocations as f64) * 100.,
-        stats.audio_meme_invocations,
-        (stats.audio_meme_invocations as f64) / (stats.total_meme_invocations as f64) * 100.,
-        stats.most_active_day.format(CLEAN_DATE_FORMAT), stats.most_active_day_count,
-        stats.most_audio_active_day.format(CLEAN_DATE_FORMAT), stats.most_audio_active_count,
-        rand_user, stats.most_random_meme_user_count,
-        direct_user, stats.most_directly_named_meme_count,
-        stats.most_popular_named_meme, stats.most_popular_named_meme_count,
-        stats.most_popular_random_meme, stats.most_popular_random_meme_count,
-        stats.most_popular_meme_overall, stats.most_popular_meme_overall_count,
-    );
+    let stats = db::stats(&conn, &range)?;
+
+    debug!("reporting stats {:?}", range);
+
+    let pct = |n: usize, total: usize| (n as f64) / (total as f64) * 100.;
+
+    let mut sections = vec![vec![
+        format!("**{}** memes stored", stats.memes_overall),
+        format!("**{}** memes with audio ({:0.1}%)", stats.audio_memes, pct(stats.audio_memes, stats.memes_overall)),
+        format!("**{}** memes with images ({:0.1}%)", stats.image_memes, pct(stats.image_memes, stats.memes_overall)),
+    ]];
+
+    if !range.is_all_time() {
+        sections[0].push(format!("**{}** new {}", stats.new_memes, describe_range(&range)));
+    }
+
+    let started_recording = match stats.started_recording {
+        Some(started) => started,
+        None => {
+            sections.push(vec![format!("nobody memed {}", describe_range(&range))]);
+            return ctx.send(msg.channel_id, &sections.iter().map(|s| s.join("\n")).join("\n\n"), msg.tts);
+        },
+    };
+
+    sections.push(vec![
+        if range.is_all_time() {
+            format!(
+                "started recording meme invocations on *{}* ({})",
+                started_recording.date().format(CLEAN_DATE_FORMAT),
+                TIME_FORMATTER.convert((Utc::now() - started_recording).to_std().unwrap()),
+            )
+        } else {
+            format!("memes {}:", describe_range(&range))
+        },
+        format!("**{}** total meme invocations recorded", stats.total_meme_invocations),
+        format!("**{}** of which were random ({:0.1}%)", stats.random_meme_invocations, pct(stats.random_meme_invocations, stats.total_meme_invocations)),
+        format!("and **{}** were audio ({:0.1}%)", stats.audio_meme_invocations, pct(stats.audio_meme_invocations, stats.total_meme_invocations)),
+    ]);
+
+    let mut days = Vec::new();
+    if let Some((day, count)) = stats.most_active_day {
+        days.push(format!("the most active day was *{}* with **{}** memes", day.format(CLEAN_DATE_FORMAT), count));
+    }
+    if let Some((day, count)) = stats.most_audio_active_day {
+        days.push(format!("and the loudest day was *{}* with **{}** audio memes", day.format(CLEAN_DATE_FORMAT), count));
+    }
+
+    let mut users = Vec::new();
+    if let Some((user_id, count)) = stats.most_random_meme_user {
+        users.push(format!("**{}** has invoked the most random memes ({})", username(ctx, user_id)?, count));
+    }
+    if let Some((user_id, count)) = stats.most_directly_named_meme_user {
+        users.push(format!("**{}** has invoked the most memes by name ({})", username(ctx, user_id)?, count));
+    }
+
+    let mut memes = Vec::new();
+    if let Some((ref title, count)) = stats.most_popular_named_meme {
+        memes.push(format!("*{}* was the meme specifically requested the most ({})", title, count));
+    }
+    if let Some((ref title, count)) = stats.most_popular_random_meme {
+        memes.push(format!("*{}* was the meme randomly invoked the most ({})", title, count));
+    }
+    if let Some((ref title, count)) = stats.most_popular_meme_overall {
+        memes.push(format!("and *{}* was the most-memed overall ({})", title, count));
+    }
+
+    sections.extend(vec![days, users, memes]);
+
+    let s = sections.into_iter()
+        .filter(|section| !section.is_empty())
+        .map(|section| section.join("\n"))
+        .join("\n\n");
+
     ctx.send(msg.channel_id, s, msg.tts)
 }
 
 #[command]
-pub fn memers(ctx: &mut Context, msg: &Message, _args: Args) -> Result<()> {
-    use db;
-    use itertools::Itertools;
-    use serenity::model::{
-        id::UserId,
+pub fn memers(ctx: &mut Context, msg: &Message, args: Args) -> Result<()> {
+    let range = match range_arg(ctx, msg, &args)? {
+        Some(range) => range,
+        None => return Ok(()),
     };
 
-    let s = db::memers()?
+    let memers = db::memers(&range)?;
+
+    if memers.is_empty() {
+        return ctx.send(msg.channel_id, &format!("nobody memed {}", describe_range(&range)), msg.tts);
+    }
+
+    let s = memers
         .into_iter()
         .map(|info| {
-            let user = UserId(info.user_id).to_user(&ctx)?;
-            let username = user.nick_in(&ctx, CONFIG.discord.guild()).unwrap_or(user.name);
-
             let res = format!(
                 "**{}**: {} total, {} random, {} specific. favorite meme: *{}* ({})",
-                username,
+                username(ctx, info.user_id)?,
                 info.random_memes + info.specific_memes,
                 info.random_memes,
                 info.specific_memes,
@@ -245,92 +366,157 @@ pub fn memers(ctx: &mut Context, msg: &Message, _args: Args) -> Result<()> {
 }
 
 #[command]
-pub fn query(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
-    use std::borrow::Borrow;
+#[aliases("leaderboard", "makers")]
+pub fn creators(ctx: &mut Context, msg: &Message, args: Args) -> Result<()> {
+    let range = match range_arg(ctx, msg, &args)? {
+        Some(range) => range,
+        None => return Ok(()),
+    };
+
+    let creators = db::creators(&range)?;
+
+    if creators.is_empty() {
+        return ctx.send(msg.channel_id, &format!("nobody made a meme {}", describe_range(&range)), msg.tts);
+    }
+
+    let s = creators
+        .into_iter()
+        .enumerate()
+        .map(|(i, (user_id, count))| Ok(format!("{}. **{}**: {} memes", i + 1, username(ctx, user_id)?, count)))
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .join("\n");
 
-    use itertools::Itertools;
-    use regex::Regex;
-    use serenity::model::id::UserId;
+    ctx.send(msg.channel_id, &s, msg.tts)
+}
 
-    use crate::{
-        game::get_user_id,
-        db,
-        CONFIG,
+#[command]
+#[aliases("memeoftheweek", "motw")]
+pub fn meme_of_the_week(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+    let range = TimeRange {
+        since: Some(Utc::now().naive_utc() - Duration::days(7)),
+        until: None,
     };
 
-    lazy_static! {
-        static ref CREATOR_REGEX: Regex = Regex::new(r"(?i)(?:by|creator)=(.*)").unwrap();
-        static ref AGE_REGEX: Regex = Regex::new(r"(?i)(?:age|order)=(.*)").unwrap();
+    match db::top_meme(&range)? {
+        Some((title, count)) => ctx.send(msg.channel_id, &format!("the meme of the week is *{}*, memed **{}** times", title, count), msg.tts),
+        None => ctx.send(msg.channel_id, "no memes this week. sad", msg.tts),
     }
+}
 
-    let guild = msg.channel_id.to_channel(&ctx)?
-        .guild()
-        .ok_or(anyhow!("couldn't find guild"))?;
+#[command]
+#[aliases("memestat", "statsfor")]
+pub fn memestats(ctx: &mut Context, msg: &Message, args: Args) -> Result<()> {
+    let search = args.rest().trim();
 
-    let guild = guild.read()
-        .guild(&ctx)
-        .ok_or(anyhow!("couldn't find guild"))?;
+    if search.is_empty() {
+        return ctx.send(msg.channel_id, "stats for what", msg.tts);
+    }
 
-    let guild = guild
-        .read();
+    let conn = connection()?;
 
-    let creator: Option<u64> = {
-        let creator = args.quoted().current().map(|s| CREATOR_REGEX.is_match(s)).unwrap_or(false);
-        if creator {
-            args.single_quoted::<String>()
-                .ok()
-                .and_then(|s| CREATOR_REGEX.captures(&s).and_then(|c| c.get(1)).map(|x| x.as_str().to_owned()))
-                .and_then(|s| get_user_id(guild.borrow(), s).ok().map(|s| s.0))
-        } else {
-            None
-        }
+    let meme = match find_meme(&conn, search) {
+        Ok(meme) => meme,
+        Err(e) => {
+            return if let Some(NotFound) = e.downcast_ref::<DieselError>() {
+                info!("requested stats for nonexistent meme '{}'", search);
+                ctx.send(msg.channel_id, "never heard of it", msg.tts)
+            } else if let Some(Ambiguous(titles)) = e.downcast_ref::<Ambiguous>() {
+                ctx.send(msg.channel_id, &did_you_mean(titles), msg.tts)
+            } else {
+                Err(e)
+            };
+        },
     };
 
-    let order = {
-        let order = args.quoted().current().map(|s| AGE_REGEX.is_match(s)).unwrap_or(false);
+    let metadata = Metadata::find(&conn, meme.metadata_id)?;
+    let stats = meme_stats(&meme, TOP_INVOKERS)?;
 
-        if order {
-            args.single_quoted::<String>().ok()
-                .and_then(|s| AGE_REGEX.captures(&s).and_then(|c| c.get(1)).map(|x| x.as_str().to_owned()))
-                .map(|s: String| s.contains("new"))
-                .unwrap_or(true)
-        } else {
-            true
-        }
+    let name = |user_id: u64| CONFIG.discord.guild().member(&ctx, user_id)
+        .map(|m| m.display_name().into_owned())
+        .unwrap_or("???".to_owned());
+
+    let mut resp = format!(
+        "*{}* by **{}** ({})",
+        meme.title,
+        name(metadata.created_by as u64),
+        metadata.created.date().format(CLEAN_DATE_FORMAT),
+    );
+
+    let (first, last) = match (stats.first, stats.last) {
+        (Some(first), Some(last)) => (first, last),
+        _ => {
+            resp += "\nnobody has ever memed it";
+            return ctx.send(msg.channel_id, &resp, msg.tts);
+        },
     };
 
-    let result = db::query_meme(args.rest(), creator, order)?
-        .into_iter()
-        .map(|(meme, metadata)| {
-            let user = UserId(metadata.created_by as u64).to_user(&ctx)?;
-            let username = user.nick_in(&ctx, CONFIG.discord.guild()).unwrap_or(user.name);
-
-            Ok(format!("*{}* by **{}** ({}). text length: **{}**, image: **{}**, audio: **{}**",
-                       meme.title,
-                       username,
-                       metadata.created.date().format(CLEAN_DATE_FORMAT),
-                       meme.content.map_or(0, |s| s.len()),
-                       meme.image_id.map_or("NO", |_s| "YES"),
-                       meme.audio_id.map_or("NO", |_s| "YES"),
-            ))
-        })
-        .collect::<Result<Vec<_>>>()?
-        .into_iter()
-        .scan(0, |state, line| {
-            *state = *state + line.len() + 1;
+    let ago = |time| TIME_FORMATTER.convert((Utc::now().naive_utc() - time).to_std().unwrap_or_default());
 
-            if *state < 2000 {
-                Some(line)
-            } else {
-                None
-            }
-        })
-        .join("\n");
+    resp += &format!(
+        "\n**{}** invocations: **{}** random, **{}** by name\nfirst memed *{}* ({} ago), last memed *{}* ({} ago)",
+        stats.total,
+        stats.random,
+        stats.named,
+        first.date().format(CLEAN_DATE_FORMAT), ago(first),
+        last.date().format(CLEAN_DATE_FORMAT), ago(last),
+    );
+
+    let top = stats.top_invokers.iter()
+        .map(|&(user_id, count)| format!("**{}** ({})", name(user_id), count))
+        .join(", ");
+
+    resp += &format!("\ntop memers: {}", top);
+
+    let months = &stats.monthly[stats.monthly.len().saturating_sub(SPARKLINE_MONTHS)..];
 
-    if result.len() == 0 {
-        info!("no memes matched query");
-        return ctx.send(msg.channel_id, "no match".to_owned(), msg.tts);
+    if let Some((since, _)) = months.first() {
+        let counts = months.iter().map(|&(_, count)| count).collect::<Vec<_>>();
+        resp += &format!("\nper month since {}: `{}`", since.format("%b %Y"), sparkline(&counts));
     }
 
-    ctx.send(msg.channel_id, &result, msg.tts)
-}
\ No newline at end of file
+    ctx.send(msg.channel_id, &resp, msg.tts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_range() {
+        let now = NaiveDate::from_ymd(2024, 3, 15).and_hms(12, 30, 0);
+
+        assert_eq!(Ok(TimeRange::default()), parse_range("", now));
+        assert_eq!(Ok(Some(NaiveDate::from_ymd(2024, 3, 8).and_hms(12, 30, 0))), parse_range("week", now).map(|r| r.since));
+        assert_eq!(Ok(Some(NaiveDate::from_ymd(2024, 3, 15).and_hms(0, 0, 0))), parse_range("Today", now).map(|r| r.since));
+
+        assert_eq!(
+            Ok(TimeRange {
+                since: Some(NaiveDate::from_ymd(2024, 1, 1).and_hms(0, 0, 0)),
+                until: Some(NaiveDate::from_ymd(2024, 2, 1).and_hms(0, 0, 0)),
+            }),
+            parse_range("since=2024-01-01 until=2024-01-31", now),
+        );
+
+        assert_eq!(Err("since=yesterday".to_owned()), parse_range("since=yesterday", now));
+        assert_eq!(Err("fortnight".to_owned()), parse_range("week fortnight", now));
+    }
+
+    #[test]
+    fn test_describe_range() {
+        let day = |d| NaiveDate::from_ymd(2024, 1, d).and_hms(0, 0, 0);
+
+        assert_eq!("of all time", describe_range(&TimeRange::default()));
+        assert_eq!("since Jan 1 2024", describe_range(&TimeRange { since: Some(day(1)), until: None }));
+        assert_eq!("from Jan 1 2024 through Jan 9 2024", describe_range(&TimeRange { since: Some(day(1)), until: Some(day(10)) }));
+    }
+
+    #[test]
+    fn test_sparkline() {
+        assert_eq!("", sparkline(&[]));
+        assert_eq!("▁▁", sparkline(&[0, 0]));
+        assert_eq!("█", sparkline(&[5]));
+        assert_eq!("▁▂▅█", sparkline(&[0, 1, 4, 8]));
+        assert_eq!("▂█", sparkline(&[1, 100]));
+    }
+}
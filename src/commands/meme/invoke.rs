@@ -14,13 +14,20 @@ use serenity::{
 };
 
 use crate::{
-    commands::meme::send_meme,
+    commands::meme::{
+        search::did_you_mean,
+        send_meme,
+        tags::normalize_tag,
+    },
     Result,
     db::{
         self,
+        Ambiguous,
         connection,
         find_meme,
         InvocationRecord,
+        Rarity,
+        UnknownRarity,
     },
     util::CtxExt,
 };
@@ -51,8 +58,13 @@ enum AudioPlayback {
 }
 
 fn _meme(ctx: &mut Context, msg: &Message, args: Args, audio_playback: AudioPlayback) -> Result<()> {
-    if args.len() == 0 || audio_playback != AudioPlayback::Optional {
-        return rand_meme(ctx, msg, audio_playback);
+    let tag = match args.raw().collect::<Vec<_>>().as_slice() {
+        [tag] if tag.starts_with('#') => normalize_tag(tag),
+        _ => None,
+    };
+
+    if args.len() == 0 || tag.is_some() || audio_playback != AudioPlayback::Optional {
+        return rand_meme(ctx, msg, audio_playback, tag.as_ref().map(String::as_str));
     }
 
     let search = args.raw().join(" ");
@@ -68,6 +80,9 @@ fn _meme(ctx: &mut Context, msg: &Message, args: Args, audio_playback: AudioPlay
             return if let Some(NotFound) = e.downcast_ref::<DieselError>() {
                 info!("requested meme not found in database");
                 ctx.send(msg.channel_id, "c'mon baby, guesstimate", msg.tts)
+            } else if let Some(Ambiguous(titles)) = e.downcast_ref::<Ambiguous>() {
+                info!("requested meme was ambiguous: {:?}", titles);
+                ctx.send(msg.channel_id, &did_you_mean(titles), msg.tts)
             } else {
                 ctx.send(msg.channel_id, "what in ryan's name", msg.tts)?;
                 Err(e)
@@ -78,15 +93,15 @@ fn _meme(ctx: &mut Context, msg: &Message, args: Args, audio_playback: AudioPlay
     send_meme(ctx, &mem, &conn, msg)
 }
 
-fn rand_meme(ctx: &Context, message: &Message, audio_playback: AudioPlayback) -> Result<()> {
+fn rand_meme(ctx: &Context, message: &Message, audio_playback: AudioPlayback, tag: Option<&str>) -> Result<()> {
     let conn = connection()?;
 
     let should_audio = ctx.users_listening()?;
 
     let mem = match audio_playback {
-        AudioPlayback::Required => db::rand_audio_meme(&conn),
-        AudioPlayback::Optional => db::rand_meme(&conn, should_audio),
-        AudioPlayback::Prohibited => db::rand_silent_meme(&conn),
+        AudioPlayback::Required => db::rand_audio_meme(&conn, tag),
+        AudioPlayback::Optional => db::rand_meme(&conn, should_audio, tag),
+        AudioPlayback::Prohibited => db::rand_silent_meme(&conn, tag),
     };
 
     match mem {
@@ -101,6 +116,10 @@ fn rand_meme(ctx: &Context, message: &Message, audio_playback: AudioPlayback) ->
                     info!("random meme not found");
                     return ctx.send(message.channel_id, "i don't know any :(", message.tts)
                 },
+                None if tag.is_some() => {
+                    info!("no random meme with tag {:?}", tag);
+                    return ctx.send(message.channel_id, "nothing's tagged like that", message.tts)
+                },
                 _ => {},
             }
 
@@ -112,16 +131,47 @@ fn rand_meme(ctx: &Context, message: &Message, audio_playback: AudioPlayback) ->
 
 #[command]
 #[aliases("rarememe", "raremem")]
-pub fn rare_meme(ctx: &mut Context, msg: &Message, _args: Args) -> Result<()> {
+pub fn rare_meme(ctx: &mut Context, msg: &Message, args: Args) -> Result<()> {
+    let mut rarity = Rarity::default();
+    let mut explain = false;
+
+    for word in args.raw() {
+        if word.eq_ignore_ascii_case("explain") {
+            explain = true;
+            continue;
+        }
+
+        match word.parse() {
+            Ok(r) => rarity = r,
+            Err(UnknownRarity(word)) => {
+                info!("unknown rarity {:?}", word);
+                return ctx.send(
+                    msg.channel_id,
+                    "rare how? least-played, least-recent, inverse-frequency or age-decayed, and optionally explain",
+                    msg.tts,
+                );
+            },
+        }
+    }
+
     let should_audio = ctx.users_listening()?;
 
     let conn = connection()?;
-    let meme = db::rare_meme(&conn, should_audio);
+    let rare = db::rare_meme(&conn, should_audio, rarity);
+
+    match rare {
+        Ok(rare) => {
+            InvocationRecord::create(&conn, msg.author.id.0, msg.id.0, rare.meme.id, true)?;
+            send_meme(ctx, &rare.meme, &conn, msg)?;
+
+            if !explain {
+                return Ok(());
+            }
 
-    match meme {
-        Ok(meme) => {
-            InvocationRecord::create(&conn, msg.author.id.0, msg.id.0, meme.id, true)?;
-            send_meme(ctx, &meme, &conn, msg)
+            ctx.send(msg.channel_id, &format!(
+                "*{}* weighs {:.3} by {}: rank {} of {}, a {:.2}% chance",
+                rare.meme.title, rare.weight, rarity, rare.rank, rare.candidates, rare.chance * 100.,
+            ), msg.tts)
         },
         Err(e) => {
             match e.downcast_ref::<DieselError>() {
@@ -1,5 +1,8 @@
 use diesel::PgConnection;
-use log::debug;
+use log::{
+    debug,
+    error,
+};
 use rand::{Rng, thread_rng};
 use serenity::{
     framework::standard::macros::group,
@@ -10,24 +13,49 @@ use serenity::{
 
 use crate::{
     audio::{
+        synthesize,
         PlayArgs,
         PlayQueue,
     },
     db::Meme,
     Result,
+    CONFIG,
+    util::CtxExt,
 };
 
 pub use self::{
+    aliases::*,
+    blobs::*,
+    chart::*,
+    clip::*,
     create::*,
     delete::*,
+    draft::*,
+    dupes::*,
+    edit::*,
     history::*,
     invoke::*,
+    query::*,
+    search::*,
+    tags::*,
+    volume::*,
 };
 
+mod aliases;
+mod blobs;
+mod chart;
 mod history;
+mod clip;
 mod create;
 mod invoke;
+mod query;
+mod search;
+mod tags;
 mod delete;
+mod draft;
+mod dupes;
+mod edit;
+mod volume;
 
 group!({
     name: "memes",
@@ -40,49 +68,84 @@ group!({
         silent_Meme,
         addmeme,
         addaudiomeme,
+        retrim,
         delmeme,
+        undelete,
+        graveyard,
+        editmeme,
         wat,
         stats,
+        memestats,
         history,
         rare_meme,
         memers,
+        creators,
+        meme_of_the_week,
+        chart,
         query,
+        meme_search,
+        dupes,
+        tag,
+        untag,
+        tags,
+        alias,
+        unalias,
+        memevolume,
+        listen,
+        clip,
+        norecord,
+        record,
+        migrateblobs,
+        checkhashes,
+        exportmemes,
     ],
 });
 
 fn send_meme(ctx: &Context, t: &Meme, conn: &PgConnection, msg: &Message) -> Result<()> {
-    let should_tts = t.content.as_ref().map(|t| t.len() > 0).unwrap_or(false) &&
+    let has_text = t.content.as_ref().map(|t| t.len() > 0).unwrap_or(false);
+
+    let should_speak = has_text &&
+        t.audio_id.is_none() &&
+        CONFIG.speak_text_memes &&
+        ctx.users_listening().unwrap_or(false);
+
+    let should_tts = has_text && !should_speak &&
         thread_rng().gen::<u32>() % 25 == 0;
 
-    debug!("sending meme (tts: {}): {:?}", should_tts, t);
+    debug!("sending meme (tts: {}, speak: {}): {:?}", should_tts, should_speak, t);
 
-    let image = t.image(conn);
+    let images = t.images(conn)?;
     let audio = t.audio(conn);
 
-    match image {
-        Some(image) => {
-            let image = image?;
-            msg.channel_id.send_files(ctx, vec!(AttachmentType::Bytes((&image.data, &image.filename))), |m| {
+    match images.len() {
+        0 => match t.content {
+            Some(_) => { msg.channel_id.send_message(ctx, |m| {
                 let ret = m.tts(should_tts);
 
                 match t.content {
                     Some(ref text) if text.len() > 0 => ret.content(text),
                     _ => ret,
                 }
-            })?;
+            })?; },
+            None => {},
         },
 
-        None => match t.content {
-            Some(_) => { msg.channel_id.send_message(ctx, |m| {
+        _ => {
+            let files = images.iter()
+                .map(|image| Ok((image.contents()?, image.filename.as_str())))
+                .collect::<Result<Vec<_>>>()?;
+
+            let attachments = files.iter()
+                .map(|(data, filename)| AttachmentType::Bytes((data.as_slice(), filename)));
+
+            msg.channel_id.send_files(ctx, attachments, |m| {
                 let ret = m.tts(should_tts);
 
                 match t.content {
                     Some(ref text) if text.len() > 0 => ret.content(text),
                     _ => ret,
                 }
-            })?; },
-            None => {},
-
+            })?;
         },
     };
 
@@ -92,21 +155,32 @@ fn send_meme(ctx: &Context, t: &Meme, conn: &PgConnection, msg: &Message) -> Res
     if let Some(audio) = audio {
         let audio = audio?;
 
-        {
-            let queue_lock = ctx.data.write().get::<PlayQueue>().cloned().unwrap();
-            let mut play_queue = queue_lock.write().unwrap();
-
-            play_queue.meme_queue.push_back(PlayArgs{
-                initiator: msg.author.name.clone(),
-                data: ::either::Right(audio.data.clone()),
-                sender_channel: msg.channel_id,
-                start: None,
-                end: None,
-            });
-        }
-
+        queue_meme_audio(ctx, msg, audio.contents()?, t.volume);
         msg.react(ctx, "📣")?;
+    } else if should_speak {
+        match synthesize(t.content.as_ref().unwrap()) {
+            Ok(data) => {
+                queue_meme_audio(ctx, msg, data, t.volume);
+                msg.react(ctx, "🗣")?;
+            },
+            Err(e) => error!("speaking text meme: {}", e),
+        }
     }
 
     Ok(())
 }
+
+fn queue_meme_audio(ctx: &Context, msg: &Message, data: Vec<u8>, meme_volume: f32) {
+    let queue_lock = ctx.data.write().get::<PlayQueue>().cloned().unwrap();
+    let mut play_queue = queue_lock.write().unwrap();
+
+    play_queue.meme_queue.push_back(PlayArgs{
+        initiator: msg.author.name.clone(),
+        data: ::either::Right(data),
+        sender_channel: msg.channel_id,
+        start: None,
+        end: None,
+        volume: 1.0,
+        meme_volume,
+    });
+}
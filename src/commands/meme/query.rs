@@ -0,0 +1,283 @@
+use std::{
+    borrow::Borrow,
+    sync::Mutex,
+    time::{
+        Duration,
+        Instant,
+    },
+};
+
+use anyhow::anyhow;
+use fnv::FnvHashMap;
+use itertools::Itertools;
+use lazy_static::lazy_static;
+use log::{
+    debug,
+    info,
+};
+use regex::Regex;
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    model::{
+        channel::{
+            Message,
+            Reaction,
+            ReactionType,
+        },
+        id::{
+            MessageId,
+            UserId,
+        },
+    },
+    prelude::*,
+};
+
+use crate::{
+    CONFIG,
+    Result,
+    commands::meme::{
+        history::CLEAN_DATE_FORMAT,
+        tags::normalize_tag,
+    },
+    db::{
+        MemeQuery,
+        query_meme,
+        QueryPage,
+    },
+    game::get_user_id,
+    util::CtxExt,
+};
+
+const PAGE_SIZE: usize = 10;
+
+const PREV_PAGE: &str = "◀";
+const NEXT_PAGE: &str = "▶";
+
+/// How long the page buttons on a query response keep working.
+const PAGE_TTL: Duration = Duration::from_secs(60 * 60);
+
+struct QueryState {
+    query: MemeQuery,
+    page: usize,
+    pages: usize,
+    created: Instant,
+}
+
+lazy_static! {
+    static ref QUERY_PAGES: Mutex<FnvHashMap<MessageId, QueryState>> = Mutex::new(FnvHashMap::default());
+
+    static ref CREATOR_REGEX: Regex = Regex::new(r"(?i)(?:by|creator)=(.*)").unwrap();
+    static ref TAG_REGEX: Regex = Regex::new(r"(?i)tag=#?(.*)").unwrap();
+    static ref AGE_REGEX: Regex = Regex::new(r"(?i)(?:age|order)=(.*)").unwrap();
+    static ref PAGE_REGEX: Regex = Regex::new(r"(?i)(?:^|\s)page=(\d+)").unwrap();
+}
+
+#[inline]
+fn page_count(total: usize) -> usize {
+    (total + PAGE_SIZE - 1) / PAGE_SIZE
+}
+
+/// Pull `page=N` out of a search, returning the remaining search and the 0-based page.
+fn split_page(s: &str) -> (String, usize) {
+    let page = PAGE_REGEX.captures(s)
+        .and_then(|c| c.get(1))
+        .and_then(|p| p.as_str().parse::<usize>().ok())
+        .map_or(0, |p| p.saturating_sub(1));
+
+    (PAGE_REGEX.replace(s, "").trim().to_owned(), page)
+}
+
+fn render(ctx: &Context, page: &QueryPage, page_idx: usize) -> Result<String> {
+    let footer = format!("page {}/{} ({} memes)", page_idx + 1, page_count(page.total), page.total);
+
+    let body = page.memes.iter()
+        .map(|(meme, metadata, images)| {
+            let user = UserId(metadata.created_by as u64).to_user(&ctx)?;
+            let username = user.nick_in(&ctx, CONFIG.discord.guild()).unwrap_or(user.name);
+
+            Ok(format!("*{}* by **{}** ({}). text length: **{}**, images: **{}**, audio: **{}**",
+                       meme.title,
+                       username,
+                       metadata.created.date().format(CLEAN_DATE_FORMAT),
+                       meme.content.as_ref().map_or(0, |s| s.len()),
+                       images,
+                       meme.audio_id.map_or("NO", |_s| "YES"),
+            ))
+        })
+        .collect::<Result<Vec<_>>>()?
+        .into_iter()
+        .scan(footer.len() + 1, |state, line| {
+            *state = *state + line.len() + 1;
+
+            if *state < 2000 {
+                Some(line)
+            } else {
+                None
+            }
+        })
+        .join("\n");
+
+    Ok(format!("{}\n{}", body, footer))
+}
+
+#[command]
+pub fn query(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let guild = msg.channel_id.to_channel(&ctx)?
+        .guild()
+        .ok_or(anyhow!("couldn't find guild"))?;
+
+    let guild = guild.read()
+        .guild(&ctx)
+        .ok_or(anyhow!("couldn't find guild"))?;
+
+    let guild = guild
+        .read();
+
+    let creator: Option<u64> = {
+        let creator = args.quoted().current().map(|s| CREATOR_REGEX.is_match(s)).unwrap_or(false);
+        if creator {
+            args.single_quoted::<String>()
+                .ok()
+                .and_then(|s| CREATOR_REGEX.captures(&s).and_then(|c| c.get(1)).map(|x| x.as_str().to_owned()))
+                .and_then(|s| get_user_id(guild.borrow(), s).ok().map(|s| s.0))
+        } else {
+            None
+        }
+    };
+
+    let tag: Option<String> = {
+        let tag = args.quoted().current().map(|s| TAG_REGEX.is_match(s)).unwrap_or(false);
+        if tag {
+            args.single_quoted::<String>()
+                .ok()
+                .and_then(|s| TAG_REGEX.captures(&s).and_then(|c| c.get(1)).map(|x| x.as_str().to_owned()))
+                .and_then(|s| normalize_tag(&s))
+        } else {
+            None
+        }
+    };
+
+    let order = {
+        let order = args.quoted().current().map(|s| AGE_REGEX.is_match(s)).unwrap_or(false);
+
+        if order {
+            args.single_quoted::<String>().ok()
+                .and_then(|s| AGE_REGEX.captures(&s).and_then(|c| c.get(1)).map(|x| x.as_str().to_owned()))
+                .map(|s: String| s.contains("new"))
+                .unwrap_or(true)
+        } else {
+            true
+        }
+    };
+
+    let (search, page) = split_page(args.rest());
+
+    let query = MemeQuery {
+        search,
+        creator,
+        tag,
+        age_desc: order,
+    };
+
+    let result = query_meme(&query, page, PAGE_SIZE)?;
+
+    if result.memes.is_empty() {
+        let total = if page == 0 { 0 } else { query_meme(&query, 0, PAGE_SIZE)?.total };
+
+        if total == 0 {
+            info!("no memes matched query");
+            return ctx.send(msg.channel_id, "no match".to_owned(), msg.tts);
+        }
+
+        return ctx.send(msg.channel_id, &format!("there are only {} pages", page_count(total)), msg.tts);
+    }
+
+    let pages = page_count(result.total);
+    let resp = render(ctx, &result, page)?;
+    let resp_id = ctx.send_result(msg.channel_id, &resp, msg.tts)?;
+
+    if pages <= 1 {
+        return Ok(());
+    }
+
+    {
+        let mut query_pages = QUERY_PAGES.lock().unwrap();
+        query_pages.retain(|_, state| state.created.elapsed() < PAGE_TTL);
+
+        query_pages.insert(resp_id, QueryState {
+            query,
+            page,
+            pages,
+            created: Instant::now(),
+        });
+    }
+
+    msg.channel_id.create_reaction(&ctx, resp_id, PREV_PAGE)?;
+    msg.channel_id.create_reaction(&ctx, resp_id, NEXT_PAGE)?;
+
+    Ok(())
+}
+
+/// Flip the page of a `query` response when someone clicks its page buttons.
+pub fn handle_query_reaction(ctx: &Context, reaction: &Reaction) -> Result<()> {
+    let delta: isize = match reaction.emoji {
+        ReactionType::Unicode(ref s) if s == PREV_PAGE => -1,
+        ReactionType::Unicode(ref s) if s == NEXT_PAGE => 1,
+        _ => return Ok(()),
+    };
+
+    if reaction.user_id == ctx.cache.read().user.id {
+        return Ok(());
+    }
+
+    let (query, page) = {
+        let mut query_pages = QUERY_PAGES.lock().unwrap();
+
+        let state = match query_pages.get_mut(&reaction.message_id) {
+            Some(state) if state.created.elapsed() < PAGE_TTL => state,
+            _ => return Ok(()),
+        };
+
+        let page = (state.page as isize + delta).max(0).min(state.pages as isize - 1) as usize;
+        state.page = page;
+
+        (state.query.clone(), page)
+    };
+
+    if let Err(e) = reaction.delete(ctx) {
+        debug!("removing page reaction: {}", e);
+    }
+
+    let result = query_meme(&query, page, PAGE_SIZE)?;
+    let resp = render(ctx, &result, page)?;
+
+    reaction.channel_id.edit_message(ctx, reaction.message_id, |m| m.content(resp))?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_split_page() {
+        assert_eq!(("".to_owned(), 0), split_page(""));
+        assert_eq!(("cat".to_owned(), 0), split_page("cat"));
+        assert_eq!(("cat".to_owned(), 1), split_page("cat page=2"));
+        assert_eq!(("big cat".to_owned(), 2), split_page("PAGE=3 big cat"));
+        assert_eq!(("".to_owned(), 0), split_page("page=0"));
+        assert_eq!(("homepage=2".to_owned(), 0), split_page("homepage=2"));
+    }
+
+    #[test]
+    fn test_page_count() {
+        assert_eq!(0, page_count(0));
+        assert_eq!(1, page_count(1));
+        assert_eq!(1, page_count(PAGE_SIZE));
+        assert_eq!(2, page_count(PAGE_SIZE + 1));
+    }
+}
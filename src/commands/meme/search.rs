@@ -0,0 +1,67 @@
+use itertools::Itertools;
+use log::info;
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{
+    Result,
+    db::search_memes,
+    util::CtxExt,
+};
+
+const SEARCH_RESULTS: usize = 10;
+
+/// "did you mean A, B or C?"
+pub(super) fn did_you_mean<S: AsRef<str>>(titles: &[S]) -> String {
+    let quoted = titles.iter().map(|t| format!("\"{}\"", t.as_ref())).collect::<Vec<_>>();
+
+    let options = match quoted.split_last() {
+        None => return "did you mean... something?".to_owned(),
+        Some((last, [])) => last.to_owned(),
+        Some((last, rest)) => format!("{} or {}", rest.join(", "), last),
+    };
+
+    format!("did you mean {}?", options)
+}
+
+#[command]
+#[aliases("memesearch", "searchmeme")]
+pub fn meme_search(ctx: &mut Context, msg: &Message, args: Args) -> Result<()> {
+    let search = args.rest().trim();
+
+    if search.is_empty() {
+        return ctx.send(msg.channel_id, "search for what", msg.tts);
+    }
+
+    let results = search_memes(search, SEARCH_RESULTS)?;
+
+    if results.is_empty() {
+        info!("no memes matched search '{}'", search);
+        return ctx.send(msg.channel_id, "no match", msg.tts);
+    }
+
+    let resp = results.into_iter()
+        .enumerate()
+        .map(|(i, (meme, score))| format!("{}. *{}* ({:.0}%)", i + 1, meme.title, score * 100.))
+        .join("\n");
+
+    ctx.send(msg.channel_id, &resp, msg.tts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_did_you_mean() {
+        assert_eq!("did you mean \"a\"?", did_you_mean(&["a"]));
+        assert_eq!("did you mean \"a\" or \"b\"?", did_you_mean(&["a", "b"]));
+        assert_eq!("did you mean \"a\", \"b\" or \"c\"?", did_you_mean(&["a", "b", "c"]));
+    }
+}
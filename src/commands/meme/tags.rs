@@ -0,0 +1,167 @@
+use diesel::{
+    NotFound,
+    PgConnection,
+    result::Error as DieselError,
+};
+use itertools::Itertools;
+use log::info;
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{
+    Result,
+    db::{
+        self,
+        connection,
+        Meme,
+    },
+    util::CtxExt,
+};
+
+const MAX_TAG_LENGTH: usize = 32;
+
+/// Normalize a user-supplied tag (with or without a leading `#`) to its stored form. Tags are
+/// lowercase and made of letters, digits, `-` and `_`.
+pub(super) fn normalize_tag(s: &str) -> Option<String> {
+    let tag = s.trim().trim_start_matches('#').to_lowercase();
+
+    let valid = !tag.is_empty() &&
+        tag.chars().count() <= MAX_TAG_LENGTH &&
+        tag.chars().all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+
+    if valid { Some(tag) } else { None }
+}
+
+/// Parse `<title> <tags...>`, replying to the user if it doesn't work out.
+fn meme_and_tags(ctx: &Context, msg: &Message, conn: &PgConnection, args: &mut Args) -> Result<Option<(Meme, Vec<String>)>> {
+    let title = args.single_quoted::<String>()?;
+
+    let tags = args.iter::<String>()
+        .filter_map(|s| s.ok())
+        .map(|s| normalize_tag(&s).ok_or(s))
+        .collect::<std::result::Result<Vec<_>, _>>();
+
+    let tags = match tags {
+        Ok(ref tags) if tags.is_empty() => {
+            ctx.send(msg.channel_id, "which tags", msg.tts)?;
+            return Ok(None);
+        },
+        Ok(tags) => tags,
+        Err(bad) => {
+            info!("rejected tag {:?}", bad);
+            ctx.send(msg.channel_id, &format!("\"{}\" is not a tag", bad), msg.tts)?;
+            return Ok(None);
+        },
+    };
+
+    match Meme::by_title(conn, &title) {
+        Ok(meme) => Ok(Some((meme, tags))),
+        Err(e) => {
+            if let Some(NotFound) = e.downcast_ref::<DieselError>() {
+                info!("attempted to tag nonexistent meme: '{}'", title);
+                ctx.send(msg.channel_id, "never heard of it", msg.tts)?;
+                return Ok(None);
+            }
+
+            Err(e)
+        },
+    }
+}
+
+#[command]
+pub fn tag(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let conn = connection()?;
+
+    let (meme, tags) = match meme_and_tags(ctx, msg, &conn, &mut args)? {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+
+    let added = meme.add_tags(&conn, &tags)?;
+    info!("added {} tags to '{}'", added, meme.title);
+
+    msg.react(ctx, "🏷")
+}
+
+#[command]
+pub fn untag(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let conn = connection()?;
+
+    let (meme, tags) = match meme_and_tags(ctx, msg, &conn, &mut args)? {
+        Some(x) => x,
+        None => return Ok(()),
+    };
+
+    let removed = meme.remove_tags(&conn, &tags)?;
+    info!("removed {} tags from '{}'", removed, meme.title);
+
+    if removed == 0 {
+        return ctx.send(msg.channel_id, "it wasn't tagged like that", msg.tts);
+    }
+
+    msg.react(ctx, "👌")
+}
+
+#[command]
+pub fn tags(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    if args.is_empty() {
+        let counts = db::tag_counts()?;
+
+        if counts.is_empty() {
+            return ctx.send(msg.channel_id, "nothing is tagged", msg.tts);
+        }
+
+        let resp = counts.into_iter()
+            .map(|(tag, count)| format!("#{} ({})", tag, count))
+            .join(", ");
+
+        return ctx.send(msg.channel_id, &resp, msg.tts);
+    }
+
+    let title = args.single_quoted::<String>()?;
+    let conn = connection()?;
+
+    let meme = match Meme::by_title(&conn, &title) {
+        Ok(meme) => meme,
+        Err(e) => {
+            if let Some(NotFound) = e.downcast_ref::<DieselError>() {
+                return ctx.send(msg.channel_id, "never heard of it", msg.tts);
+            }
+
+            return Err(e);
+        },
+    };
+
+    let tags = meme.tags(&conn)?;
+
+    if tags.is_empty() {
+        return ctx.send(msg.channel_id, &format!("{} has no tags", meme.title), msg.tts);
+    }
+
+    let resp = tags.into_iter().map(|t| format!("#{}", t)).join(" ");
+    ctx.send(msg.channel_id, &resp, msg.tts)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_normalize_tag() {
+        assert_eq!(Some("cursed".to_owned()), normalize_tag("cursed"));
+        assert_eq!(Some("cursed".to_owned()), normalize_tag("#Cursed"));
+        assert_eq!(Some("big_loud-noise2".to_owned()), normalize_tag(" #big_loud-noise2 "));
+
+        assert_eq!(None, normalize_tag(""));
+        assert_eq!(None, normalize_tag("#"));
+        assert_eq!(None, normalize_tag("two words"));
+        assert_eq!(None, normalize_tag("what?"));
+        assert_eq!(None, normalize_tag(&"a".repeat(MAX_TAG_LENGTH + 1)));
+    }
+}
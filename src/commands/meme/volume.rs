@@ -0,0 +1,67 @@
+use diesel::{
+    NotFound,
+    result::Error as DieselError,
+};
+use log::{
+    error,
+    info,
+};
+use serenity::{
+    framework::standard::{
+        Args,
+        macros::command,
+    },
+    model::channel::Message,
+    prelude::*,
+};
+
+use crate::{
+    Result,
+    commands::sound_levels::{
+        MAX_VOLUME,
+        percent,
+    },
+    db::{
+        connection,
+        Meme,
+    },
+    util::CtxExt,
+};
+
+#[command]
+#[aliases("memevol", "memvol")]
+pub fn memevolume(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let title = args.single_quoted::<String>()?;
+
+    let conn = connection()?;
+
+    let meme = match Meme::by_title(&conn, &title) {
+        Ok(meme) => meme,
+        Err(e) => {
+            if let Some(NotFound) = e.downcast_ref::<DieselError>() {
+                info!("attempted to adjust volume of nonexistent meme: '{}'", title);
+                return ctx.send(msg.channel_id, "never heard of it", msg.tts);
+            }
+
+            return Err(e);
+        },
+    };
+
+    if args.is_empty() {
+        return ctx.send(msg.channel_id, &format!("{}: {}%", meme.title, percent(meme.volume)), msg.tts);
+    }
+
+    let vol = match args.single::<f32>() {
+        Ok(vol) if !vol.is_nan() => (vol / 100.0).clamp(0.0, MAX_VOLUME),
+        Ok(_) => return ctx.send(msg.channel_id, "you're a fuck", msg.tts),
+        Err(e) => {
+            error!("parsing meme volume arg: {}", e);
+            return ctx.send(msg.channel_id, "???????", msg.tts);
+        },
+    };
+
+    let meme = meme.set_volume(&conn, vol)?;
+    info!("volume for meme '{}' updated to {}", meme.title, meme.volume);
+
+    msg.react(&ctx, "👌")
+}
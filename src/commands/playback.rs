@@ -16,7 +16,8 @@ use serenity::{
 
 use crate::{
     audio::{
-        parse_times,
+        parse_url_times,
+        synthesize,
         PlayArgs,
         PlayQueue,
         VoiceManager,
@@ -42,9 +43,13 @@ group!({
         unmute,
         play,
         volume,
+        duck,
+        say,
     ],
 });
 
+const MAX_SAY_LENGTH: usize = 400;
+
 pub fn _play(ctx: &Context, msg: &Message, url: &str) -> Result<()> {
     use url::{Url, Host};
 
@@ -55,6 +60,8 @@ pub fn _play(ctx: &Context, msg: &Message, url: &str) -> Result<()> {
         return Ok(());
     }
 
+    let options = msg.content.replacen(url, "", 1);
+
     let url = match Url::parse(url) {
         Err(e) => {
             error!("bad url: {}", e);
@@ -80,7 +87,15 @@ pub fn _play(ctx: &Context, msg: &Message, url: &str) -> Result<()> {
         return Ok(());
     }
 
-    let (start, end) = parse_times(&msg.content);
+    let (start, end) = match parse_url_times(&url, &options) {
+        Ok(times) => times,
+        Err(e) => {
+            info!("bad times in play command: {}", e);
+            return ctx.send(msg.channel_id, &format!("bad time: {}", e), msg.tts);
+        },
+    };
+
+    let volume = parse_item_volume(&options).unwrap_or(1.0);
 
     let queue_lock = ctx.data.write().get::<PlayQueue>().cloned().unwrap();
     let mut play_queue = queue_lock.write().unwrap();
@@ -91,6 +106,8 @@ pub fn _play(ctx: &Context, msg: &Message, url: &str) -> Result<()> {
         sender_channel: msg.channel_id,
         start,
         end,
+        volume,
+        meme_volume: 1.0,
     });
 
     Ok(())
@@ -114,33 +131,51 @@ pub fn play(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
 }
 
 #[command]
-pub fn pause(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+#[aliases("speak")]
+pub fn say(ctx: &mut Context, msg: &Message, args: Args) -> Result<()> {
+    let text = args.rest().trim();
+
+    if text.is_empty() {
+        return ctx.send(msg.channel_id, "say what", msg.tts);
+    }
+
+    if text.chars().count() > MAX_SAY_LENGTH {
+        info!("refusing to say {} characters", text.chars().count());
+        return ctx.send(msg.channel_id, "i'm not reading all that", msg.tts);
+    }
+
+    let data = synthesize(text)?;
+
     let queue_lock = ctx.data.write().get::<PlayQueue>().cloned().unwrap();
+    let mut play_queue = queue_lock.write().unwrap();
 
-    let done = || ctx.send(msg.channel_id, "r u srs", msg.tts);
-    let playing = {
-        let play_queue = queue_lock.read().unwrap();
+    play_queue.meme_queue.push_back(PlayArgs{
+        initiator: msg.author.name.clone(),
+        data: Right(data),
+        sender_channel: msg.channel_id,
+        start: None,
+        end: None,
+        volume: 1.0,
+        meme_volume: 1.0,
+    });
 
-        let current_item = match play_queue.playing {
-            Some(ref x) => x,
-            None => return done(),
-        };
+    debug!("queued speech: {:?}", text);
 
-        let audio = current_item.audio.lock();
-        audio.playing
-    };
+    Ok(())
+}
 
-    if !playing {
-        return done();
-    }
+#[command]
+pub fn pause(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
+    let queue_lock = ctx.data.write().get::<PlayQueue>().cloned().unwrap();
+    let play_queue = queue_lock.read().unwrap();
 
-    {
-        let queue = queue_lock.write().unwrap();
-        let ref audio = queue.playing.clone().unwrap().audio;
-        audio.lock().pause();
+    let current_item = match play_queue.playing {
+        Some(ref x) if !x.gain.paused() && x.audio.lock().playing => x,
+        _ => return ctx.send(msg.channel_id, "r u srs", msg.tts),
+    };
 
-        info!("paused playback");
-    }
+    current_item.gain.pause();
+    info!("pausing playback");
 
     Ok(())
 }
@@ -153,35 +188,23 @@ pub fn resume(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
 
 fn _resume(ctx: &mut Context, msg: &Message) -> Result<()> {
     let queue_lock = ctx.data.write().get::<PlayQueue>().cloned().unwrap();
+    let play_queue = queue_lock.read().unwrap();
 
-    let done = || ctx.send(msg.channel_id, "r u srs", msg.tts);
-    let playing = {
-        let play_queue = queue_lock.read().unwrap();
-
-        let current_item = match play_queue.playing {
-            Some(ref x) => x,
-            None => {
-                done()?;
-                return Ok(());
-            },
-        };
-
-        let audio = current_item.audio.lock();
-        audio.playing
+    let current_item = match play_queue.playing {
+        Some(ref x) => x,
+        None => return ctx.send(msg.channel_id, "r u srs", msg.tts),
     };
 
-    if playing {
-        done()?;
+    let mut audio = current_item.audio.lock();
+
+    if !current_item.gain.paused() && audio.playing {
         debug!("attempted to resume playback while sound was already playing");
-        return Ok(());
+        return ctx.send(msg.channel_id, "r u srs", msg.tts);
     }
 
-    {
-        let queue = queue_lock.write().unwrap();
-        let ref audio = queue.playing.clone().unwrap().audio;
-        audio.lock().play();
-        info!("playback resumed");
-    }
+    current_item.gain.resume();
+    audio.play();
+    info!("playback resumed");
 
     Ok(())
 }
@@ -195,10 +218,20 @@ pub fn skip(ctx: &mut Context, _msg: &Message, _args: Args) -> Result<()> {
     let mut manager = mgr_lock.lock();
 
     let queue_lock = data.get::<PlayQueue>().cloned().unwrap();
+    let mut play_queue = queue_lock.write().unwrap();
 
-    if let Some(handler) = manager.get_mut(CONFIG.discord.guild()) {
+    let fading = match play_queue.playing {
+        Some(ref current_item) if current_item.audio.lock().playing => {
+            current_item.gain.stop();
+            true
+        },
+        _ => false,
+    };
+
+    if fading {
+        info!("fading out currently-playing audio");
+    } else if let Some(handler) = manager.get_mut(CONFIG.discord.guild()) {
         handler.stop();
-        let mut play_queue = queue_lock.write().unwrap();
         play_queue.playing = None;
         info!("skipped currently-playing audio");
     } else {
@@ -1,3 +1,5 @@
+use std::time::Duration;
+
 use log::{
     error,
     info,
@@ -13,15 +15,38 @@ use serenity::{
     prelude::*,
 };
 
+use lazy_static::lazy_static;
+use regex::Regex;
+
 use crate::{
     Result,
     CONFIG,
-    audio::{PlayQueue, VoiceManager},
+    audio::{Duck, PlayQueue, VoiceManager},
     util::CtxExt,
 };
 
 pub const DEFAULT_VOLUME: f32 = 0.20;
-const MAX_VOLUME: f32 = 5.0;
+pub const MAX_VOLUME: f32 = 5.0;
+
+const DEFAULT_DUCK_LEVEL: f32 = 0.25;
+const MAX_DUCK_SECONDS: f32 = 600.0;
+
+lazy_static! {
+    static ref ITEM_VOLUME_REGEX: Regex = Regex::new(r"(?i)\bvol(?:ume)?\s*=\s*(\d+(?:\.\d+)?)%?").unwrap();
+}
+
+/// Parse a per-item volume (e.g. `vol=50`) out of a command, as a fraction of the global volume.
+pub fn parse_item_volume<A: AsRef<str>>(s: A) -> Option<f32> {
+    ITEM_VOLUME_REGEX.captures(s.as_ref())
+        .and_then(|c| c.get(1))
+        .and_then(|m| m.as_str().parse::<f32>().ok())
+        .map(|vol| (vol / 100.0).clamp(0.0, MAX_VOLUME))
+}
+
+#[inline]
+pub(crate) fn percent(vol: f32) -> usize {
+    (vol * 100.0).round() as usize
+}
 
 #[command]
 pub fn mute(ctx: &mut Context, _: &Message, _: Args) -> Result<()> {
@@ -63,15 +88,26 @@ pub fn unmute(ctx: &mut Context, msg: &Message, _: Args) -> Result<()> {
 #[command]
 pub fn volume(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
     if args.len() == 0 {
-        let vol = {
+        let report = {
             let queue_lock = ctx.data.write().get::<PlayQueue>().cloned().unwrap();
             let play_queue = queue_lock.read().unwrap();
-            (play_queue.volume / DEFAULT_VOLUME * 100.0) as usize
+            let global = play_queue.volume / DEFAULT_VOLUME;
+
+            match play_queue.playing {
+                Some(ref current) => format!(
+                    "volume: {}% (global: {}%, item: {}%, meme: {}%)",
+                    percent(global * current.init_args.volume_scale()),
+                    percent(global),
+                    percent(current.init_args.volume),
+                    percent(current.init_args.meme_volume),
+                ),
+                None => format!("volume: {}%", percent(global)),
+            }
         };
 
-        trace!("reporting volume {}", vol);
+        trace!("reporting {}", report);
 
-        return ctx.send(msg.channel_id, &format!("volume: {}%", vol), msg.tts);
+        return ctx.send(msg.channel_id, &report, msg.tts);
     }
 
     let vol: usize = match args.single::<f32>() {
@@ -111,9 +147,51 @@ pub fn volume(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
             None => return Ok(()),
         };
 
-        let mut audio = current_item.audio.lock();
-        audio.volume(play_queue.volume);
+        current_item.gain.set_volume(play_queue.volume * current_item.init_args.volume_scale());
+    }
+
+    Ok(())
+}
+
+#[command]
+pub fn duck(ctx: &mut Context, msg: &Message, mut args: Args) -> Result<()> {
+    let seconds = match args.single::<f32>() {
+        Ok(secs) if secs.is_finite() && secs > 0.0 => secs.min(MAX_DUCK_SECONDS),
+        _ => return ctx.send(msg.channel_id, "for how long, dumbass", msg.tts),
+    };
+
+    let level = match args.single::<f32>() {
+        Ok(level) if !level.is_nan() => (level / 100.0).clamp(0.0, 1.0),
+        _ => DEFAULT_DUCK_LEVEL,
+    };
+
+    let duck = Duck::new(level, Duration::from_secs_f32(seconds));
+
+    let queue_lock = ctx.data.write().get::<PlayQueue>().cloned().unwrap();
+    let mut play_queue = queue_lock.write().unwrap();
+
+    play_queue.duck = Some(duck);
+
+    if let Some(ref current_item) = play_queue.playing {
+        current_item.gain.duck(Some(duck));
     }
 
+    info!("ducking to {}% for {}s", percent(level), seconds);
+
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse_item_volume() {
+        assert_eq!(Some(0.5), parse_item_volume("https://youtu.be/xyz vol=50"));
+        assert_eq!(Some(1.25), parse_item_volume("volume = 125%"));
+        assert_eq!(Some(MAX_VOLUME), parse_item_volume("vol=100000"));
+
+        assert_eq!(None, parse_item_volume("https://youtu.be/xyz"));
+        assert_eq!(None, parse_item_volume("vol=loud"));
+    }
+}
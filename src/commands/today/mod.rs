@@ -61,6 +61,8 @@ impl TodayArgs {
             sender_channel: msg.channel_id,
             start: self.start,
             end: self.end,
+            volume: 1.0,
+            meme_volume: 1.0,
         }
     }
 }
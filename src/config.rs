@@ -32,9 +32,39 @@ pub struct Config {
     #[envconfig(from = "STEAM_API_KEY")]
     pub steam_api_key: String,
 
+    /// Command that reads text on stdin and writes a WAV to stdout.
+    #[envconfig(from = "TTS_COMMAND", default = "espeak-ng --stdout --stdin")]
+    pub tts_command: String,
+
+    /// Speak text-only memes into the voice channel when people are listening.
+    #[envconfig(from = "SPEAK_TEXT_MEMES", default = "false")]
+    pub speak_text_memes: bool,
+
+    /// How long deleted memes can be restored before their tombstones (and blobs) are purged.
+    #[envconfig(from = "TOMBSTONE_RETENTION_DAYS", default = "30")]
+    pub tombstone_retention_days: i64,
+
+    /// Largest video meme that will be stored, in bytes. Discord won't post anything bigger than
+    /// 8MB from a bot without boosts.
+    #[envconfig(from = "VIDEO_MAX_BYTES", default = "8388608")]
+    pub video_max_bytes: usize,
+
+    /// Re-encode uploaded videos (but not GIFs) as mp4 with ffmpeg, shrinking them to fit
+    /// `VIDEO_MAX_BYTES`.
+    #[envconfig(from = "TRANSCODE_VIDEOS", default = "false")]
+    pub transcode_videos: bool,
+
+    /// Where `exportmemes` writes its archives.
+    #[envconfig(from = "EXPORT_DIR", default = "exports")]
+    pub export_dir: String,
+
     pub discord: DiscordConfig,
 
     pub sheets: SheetsConfig,
+
+    pub blobs: BlobConfig,
+
+    pub random: RandomConfig,
 }
 
 #[derive(Envconfig)]
@@ -87,4 +117,50 @@ pub struct SheetsConfig {
 
     #[envconfig(from = "MAX_SHEET_COLUMN")]
     pub max_column: String,
-}
\ No newline at end of file
+}
+
+#[derive(Envconfig)]
+pub struct BlobConfig {
+    /// Where meme media lives: `fs` or `s3`.
+    #[envconfig(from = "BLOB_STORE", default = "fs")]
+    pub store: String,
+
+    #[envconfig(from = "BLOB_DIR", default = "blobs")]
+    pub dir: String,
+
+    /// Base URL of an S3-compatible service. Buckets are addressed by path, so local stand-ins work.
+    #[envconfig(from = "S3_ENDPOINT", default = "")]
+    pub s3_endpoint: String,
+
+    #[envconfig(from = "S3_BUCKET", default = "")]
+    pub s3_bucket: String,
+
+    #[envconfig(from = "S3_REGION", default = "us-east-1")]
+    pub s3_region: String,
+
+    #[envconfig(from = "S3_ACCESS_KEY", default = "")]
+    pub s3_access_key: String,
+
+    #[envconfig(from = "S3_SECRET_KEY", default = "")]
+    pub s3_secret_key: String,
+}
+
+#[derive(Envconfig)]
+pub struct RandomConfig {
+    /// How random memes avoid repeats: `exclude` recent memes, `downweight` them, `shufflebag`
+    /// through every meme before repeating any, or `off`.
+    #[envconfig(from = "RANDOM_REPEATS", default = "exclude")]
+    pub repeats: String,
+
+    /// A meme is recent if it's among this many of the latest invocations...
+    #[envconfig(from = "RECENT_MEMES", default = "10")]
+    pub recent_memes: usize,
+
+    /// ...or was invoked within this many hours.
+    #[envconfig(from = "RECENT_HOURS", default = "0")]
+    pub recent_hours: i64,
+
+    /// How likely a recent meme is next to any other under `downweight`, from 0 to 1.
+    #[envconfig(from = "RECENT_WEIGHT", default = "0.1")]
+    pub recent_weight: f64,
+}
@@ -0,0 +1,564 @@
+use std::{
+    collections::BTreeMap,
+    io::{
+        Read,
+        Write,
+    },
+};
+
+use anyhow::anyhow;
+use chrono::{
+    NaiveDateTime,
+    Utc,
+};
+use diesel::prelude::*;
+use fnv::{
+    FnvHashMap,
+    FnvHashSet,
+};
+use log::{
+    debug,
+    info,
+};
+use serde::{
+    Deserialize,
+    Serialize,
+};
+use tar::{
+    Archive,
+    Builder,
+    Header,
+};
+
+use crate::{
+    Error,
+    Result,
+};
+
+use super::{
+    blob,
+    models::*,
+    schema::*,
+};
+
+const MANIFEST_PATH: &str = "manifest.json";
+const MEDIA_DIR: &str = "media/";
+
+const VERSION: u32 = 1;
+
+/// Everything about the live memes except their media, which sits alongside the manifest in the
+/// archive under `media/<sha256>`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct Manifest {
+    pub version: u32,
+    pub exported: NaiveDateTime,
+    pub memes: Vec<MemeEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MemeEntry {
+    pub title: String,
+    pub content: Option<String>,
+    pub created_by: u64,
+    pub created: NaiveDateTime,
+    pub volume: f32,
+    pub tags: Vec<String>,
+    pub aliases: Vec<String>,
+    pub images: Vec<ImageEntry>,
+    /// sha256 of the audio, if any.
+    pub audio: Option<String>,
+    pub invocation_count: usize,
+    pub invocations: Vec<InvocationEntry>,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ImageEntry {
+    pub filename: String,
+    pub sha256: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct InvocationEntry {
+    pub user_id: u64,
+    pub message_id: u64,
+    pub time: NaiveDateTime,
+    pub random: bool,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ExportSummary {
+    pub memes: usize,
+    pub files: usize,
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct ImportSummary {
+    pub imported: usize,
+    /// Memes left alone because their title is already taken, as a title or an alias.
+    pub skipped: usize,
+    pub files: usize,
+}
+
+/// An image or audio row to be written out, by the sha256 of its content.
+enum Media {
+    Image(Image),
+    Audio(Audio),
+}
+
+impl Media {
+    fn contents(&self) -> Result<Vec<u8>> {
+        match self {
+            Media::Image(image) => image.contents(),
+            Media::Audio(audio) => audio.contents(),
+        }
+    }
+}
+
+/// The hex sha256 of a row's content, hashing it if the row predates sha256.
+fn content_hash<F: FnOnce() -> Result<Vec<u8>>>(sha256_hash: &Option<Vec<u8>>, contents: F) -> Result<String> {
+    match sha256_hash {
+        Some(hash) => Ok(blob::key(hash)),
+        None => Ok(blob::key(&sha256(&contents()?))),
+    }
+}
+
+/// Write every live meme to `out` as a tar archive. Deleted memes (and their tombstones) aren't
+/// included.
+pub fn export_memes<W: Write>(conn: &PgConnection, out: W) -> Result<ExportSummary> {
+    let rows = memes::table
+        .inner_join(metadata::table)
+        .order(memes::id)
+        .load::<(Meme, Metadata)>(conn)?;
+
+    let mut media = BTreeMap::new();
+    let mut entries = Vec::with_capacity(rows.len());
+
+    for (meme, metadata) in rows {
+        let aliases = meme_aliases::table
+            .select(meme_aliases::alias)
+            .filter(meme_aliases::meme_id.eq(meme.id))
+            .order(meme_aliases::alias)
+            .load::<String>(conn)?;
+
+        let invocations = invocation_records::table
+            .filter(invocation_records::meme_id.eq(meme.id))
+            .order(invocation_records::time)
+            .load::<InvocationRecord>(conn)?
+            .into_iter()
+            .map(|record| InvocationEntry {
+                user_id: record.user_id as u64,
+                message_id: record.message_id as u64,
+                time: record.time,
+                random: record.random,
+            })
+            .collect::<Vec<_>>();
+
+        let mut images = Vec::new();
+        for image in meme.images(conn)? {
+            let hash = content_hash(&image.sha256, || image.contents())?;
+
+            images.push(ImageEntry {
+                filename: image.filename.clone(),
+                sha256: hash.clone(),
+            });
+
+            media.entry(hash).or_insert(Media::Image(image));
+        }
+
+        let audio = match meme.audio(conn) {
+            Some(audio) => {
+                let audio = audio?;
+                let hash = content_hash(&audio.sha256, || audio.contents())?;
+
+                media.entry(hash.clone()).or_insert(Media::Audio(audio));
+                Some(hash)
+            },
+            None => None,
+        };
+
+        entries.push(MemeEntry {
+            tags: meme.tags(conn)?,
+            title: meme.title,
+            content: meme.content,
+            created_by: metadata.created_by as u64,
+            created: metadata.created,
+            volume: meme.volume,
+            aliases,
+            images,
+            audio,
+            invocation_count: invocations.len(),
+            invocations,
+        });
+    }
+
+    let manifest = Manifest {
+        version: VERSION,
+        exported: Utc::now().naive_utc(),
+        memes: entries,
+    };
+
+    write_archive(out, &manifest, media.iter().map(|(hash, media)| (hash.as_str(), media.contents())))?;
+
+    Ok(ExportSummary {
+        memes: manifest.memes.len(),
+        files: media.len(),
+    })
+}
+
+fn write_archive<'a, W: Write, I: IntoIterator<Item = (&'a str, Result<Vec<u8>>)>>(
+    out: W,
+    manifest: &Manifest,
+    media: I,
+) -> Result<()> {
+    let mtime = manifest.exported.timestamp().max(0) as u64;
+    let mut builder = Builder::new(out);
+
+    let mut append = |path: &str, data: &[u8]| {
+        let mut header = Header::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(mtime);
+
+        builder.append_data(&mut header, path, data)
+    };
+
+    // first, so importing can stream the media without holding it all
+    append(MANIFEST_PATH, &serde_json::to_vec_pretty(manifest)?)?;
+
+    for (hash, data) in media {
+        append(&format!("{}{}", MEDIA_DIR, hash), &data?)?;
+    }
+
+    builder.into_inner()?.flush()?;
+
+    Ok(())
+}
+
+/// Read an archive written by [`export_memes`]: the manifest, then each media file (checked against
+/// its name) in turn.
+fn read_archive<R: Read, F: FnMut(&Manifest, String, Vec<u8>) -> Result<()>>(input: R, mut media: F) -> Result<Manifest> {
+    let mut archive = Archive::new(input);
+    let mut manifest = None;
+
+    for entry in archive.entries()? {
+        let mut entry = entry?;
+        let path = entry.path()?.to_string_lossy().into_owned();
+
+        let mut data = Vec::with_capacity(entry.header().size()? as usize);
+        entry.read_to_end(&mut data)?;
+
+        if path == MANIFEST_PATH {
+            let parsed = serde_json::from_slice::<Manifest>(&data)?;
+
+            if parsed.version != VERSION {
+                return Err(anyhow!("can't read version {} archives", parsed.version));
+            }
+
+            manifest = Some(parsed);
+            continue;
+        }
+
+        if !path.starts_with(MEDIA_DIR) {
+            debug!("ignoring {} in meme archive", path);
+            continue;
+        }
+
+        let hash = path[MEDIA_DIR.len()..].to_owned();
+
+        let manifest = manifest.as_ref().ok_or_else(|| anyhow!("{} must come first in the archive", MANIFEST_PATH))?;
+
+        if blob::key(&sha256(&data)) != hash {
+            return Err(anyhow!("{} doesn't match its hash", path));
+        }
+
+        media(manifest, hash, data)?;
+    }
+
+    manifest.ok_or_else(|| anyhow!("no {} in the archive", MANIFEST_PATH))
+}
+
+/// Who first used a file, as an image (and under what filename) and as audio, so that its rows can
+/// be attributed to them.
+#[derive(Default)]
+struct FirstUse {
+    image: Option<(String, u64)>,
+    audio: Option<u64>,
+}
+
+fn first_uses(manifest: &Manifest, imports: &[Import]) -> FnvHashMap<String, FirstUse> {
+    let mut uses = FnvHashMap::<String, FirstUse>::default();
+
+    for meme in imports.iter().map(|import| &manifest.memes[import.index]) {
+        for image in &meme.images {
+            uses.entry(image.sha256.clone())
+                .or_default()
+                .image
+                .get_or_insert_with(|| (image.filename.clone(), meme.created_by));
+        }
+
+        if let Some(ref audio) = meme.audio {
+            uses.entry(audio.clone()).or_default().audio.get_or_insert(meme.created_by);
+        }
+    }
+
+    uses
+}
+
+/// A meme from the manifest that's going to be imported, with the aliases it can keep.
+#[derive(Clone, Debug, PartialEq)]
+struct Import {
+    index: usize,
+    aliases: Vec<String>,
+}
+
+/// Work out which of the manifest's memes to import, given the titles and aliases already `taken`.
+/// Titles and aliases share a namespace, so a meme whose title is taken is skipped, and aliases that
+/// are taken (by the database or by a meme imported before them) are dropped.
+fn plan_imports(manifest: &Manifest, mut taken: FnvHashSet<String>) -> Vec<Import> {
+    let mut imports = Vec::new();
+
+    for (index, meme) in manifest.memes.iter().enumerate() {
+        if !taken.insert(meme.title.clone()) {
+            debug!("skipping import of '{}': title is taken", meme.title);
+            continue;
+        }
+
+        let aliases = meme.aliases.iter()
+            .filter(|alias| {
+                let free = taken.insert((*alias).clone());
+
+                if !free {
+                    debug!("dropping alias '{}' of '{}' on import: it's taken", alias, meme.title);
+                }
+
+                free
+            })
+            .cloned()
+            .collect();
+
+        imports.push(Import { index, aliases });
+    }
+
+    imports
+}
+
+/// Restore memes from an archive written by [`export_memes`]. Memes whose title is already taken
+/// are skipped, along with any media only they use, and media is deduplicated by hash, so importing
+/// the same archive twice is harmless.
+pub fn import_memes<R: Read>(conn: &PgConnection, input: R) -> Result<ImportSummary> {
+    let mut taken = memes::table
+        .select(memes::title)
+        .load::<String>(conn)?
+        .into_iter()
+        .collect::<FnvHashSet<_>>();
+
+    // including those of deleted memes, which come back with them if they're restored
+    taken.extend(meme_aliases::table.select(meme_aliases::alias).load::<String>(conn)?);
+
+    let mut image_ids = FnvHashMap::default();
+    let mut audio_ids = FnvHashMap::default();
+
+    let mut planned = None;
+
+    let manifest = read_archive(input, |manifest, hash, data| {
+        let (_, uses) = planned.get_or_insert_with(|| {
+            let imports = plan_imports(manifest, std::mem::take(&mut taken));
+            let uses = first_uses(manifest, &imports);
+
+            (imports, uses)
+        });
+
+        match uses.get(&hash) {
+            Some(FirstUse { image: Some((filename, by_user)), audio }) => {
+                if let Some(audio_by) = audio {
+                    audio_ids.insert(hash.clone(), Audio::create(conn, data.clone(), *audio_by)?);
+                }
+
+                image_ids.insert(hash, Image::create(conn, filename, data, *by_user)?);
+            },
+            Some(FirstUse { image: None, audio: Some(by_user) }) => {
+                audio_ids.insert(hash, Audio::create(conn, data, *by_user)?);
+            },
+            _ => debug!("{} isn't used by any meme being imported", hash),
+        }
+
+        Ok(())
+    })?;
+
+    let imports = match planned {
+        Some((imports, _)) => imports,
+        None => plan_imports(&manifest, taken),
+    };
+
+    let mut summary = ImportSummary {
+        skipped: manifest.memes.len() - imports.len(),
+        files: image_ids.len() + audio_ids.len(),
+        ..Default::default()
+    };
+
+    for import in &imports {
+        let meme = &manifest.memes[import.index];
+
+        let missing = |hash: &String| anyhow!("'{}' needs {}, which isn't in the archive", meme.title, hash);
+
+        let images = meme.images.iter()
+            .map(|image| image_ids.get(&image.sha256).copied().ok_or_else(|| missing(&image.sha256)))
+            .collect::<Result<Vec<_>>>()?;
+
+        let audio_id = meme.audio.as_ref()
+            .map(|hash| audio_ids.get(hash).copied().ok_or_else(|| missing(hash)))
+            .transpose()?;
+
+        conn.transaction::<_, Error, _>(|| import_meme(conn, meme, &import.aliases, &images, audio_id))?;
+        summary.imported += 1;
+    }
+
+    info!("imported {} memes ({} skipped, {} files)", summary.imported, summary.skipped, summary.files);
+
+    Ok(summary)
+}
+
+fn import_meme(
+    conn: &PgConnection,
+    entry: &MemeEntry,
+    aliases: &[String],
+    image_ids: &[i32],
+    audio_id: Option<i32>,
+) -> Result<()> {
+    let meme = NewMeme {
+        title: entry.title.clone(),
+        content: entry.content.clone(),
+        audio_id,
+        metadata_id: 0,
+    }.save(conn, image_ids, entry.created_by)?;
+
+    ::diesel::update(metadata::table.find(meme.metadata_id))
+        .set(metadata::created.eq(entry.created))
+        .execute(conn)?;
+
+    meme.set_volume(conn, entry.volume)?;
+    meme.add_tags(conn, &entry.tags)?;
+
+    let aliases = aliases.iter()
+        .map(|alias| (
+            meme_aliases::alias.eq(alias),
+            meme_aliases::meme_id.eq(meme.id),
+            meme_aliases::created_by.eq(entry.created_by as i64),
+        ))
+        .collect::<Vec<_>>();
+
+    ::diesel::insert_into(meme_aliases::table)
+        .values(&aliases)
+        .on_conflict_do_nothing()
+        .execute(conn)?;
+
+    let invocations = entry.invocations.iter()
+        .map(|invocation| (
+            invocation_records::user_id.eq(invocation.user_id as i64),
+            invocation_records::message_id.eq(invocation.message_id as i64),
+            invocation_records::meme_id.eq(meme.id),
+            invocation_records::time.eq(invocation.time),
+            invocation_records::random.eq(invocation.random),
+        ))
+        .collect::<Vec<_>>();
+
+    ::diesel::insert_into(invocation_records::table)
+        .values(&invocations)
+        .execute(conn)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use chrono::NaiveDate;
+
+    use super::*;
+
+    fn manifest() -> Manifest {
+        let time = NaiveDate::from_ymd(2019, 3, 3).and_hms(4, 55, 3);
+
+        Manifest {
+            version: VERSION,
+            exported: time,
+            memes: vec![MemeEntry {
+                title: "meme".to_owned(),
+                content: Some("text".to_owned()),
+                created_by: 1,
+                created: time,
+                volume: 1.,
+                tags: vec!["tag".to_owned()],
+                aliases: vec![],
+                images: vec![ImageEntry {
+                    filename: "meme.png".to_owned(),
+                    sha256: blob::key(&sha256(b"image")),
+                }],
+                audio: None,
+                invocation_count: 1,
+                invocations: vec![InvocationEntry {
+                    user_id: 2,
+                    message_id: 3,
+                    time,
+                    random: true,
+                }],
+            }],
+        }
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let manifest = manifest();
+        let hash = blob::key(&sha256(b"image"));
+
+        let mut archive = Vec::new();
+        write_archive(&mut archive, &manifest, vec![(hash.as_str(), Ok(b"image".to_vec()))]).unwrap();
+
+        let mut media = Vec::new();
+        let read = read_archive(archive.as_slice(), |_, hash, data| {
+            media.push((hash, data));
+            Ok(())
+        }).unwrap();
+
+        assert_eq!(manifest, read);
+        assert_eq!(vec![(hash, b"image".to_vec())], media);
+    }
+
+    #[test]
+    fn test_bad_hash() {
+        let mut archive = Vec::new();
+        write_archive(&mut archive, &manifest(), vec![("00ff", Ok(b"image".to_vec()))]).unwrap();
+
+        assert!(read_archive(archive.as_slice(), |_, _, _| Ok(())).is_err());
+    }
+
+    #[test]
+    fn test_plan_imports() {
+        let entry = |title: &str, aliases: &[&str]| MemeEntry {
+            title: title.to_owned(),
+            aliases: aliases.iter().map(|&alias| alias.to_owned()).collect(),
+            ..manifest().memes.remove(0)
+        };
+
+        let mut manifest = manifest();
+        manifest.memes = vec![
+            entry("title", &[]),
+            entry("alias", &[]),
+            entry("new", &["title", "alias", "fresh", "new"]),
+            entry("fresh", &["other"]),
+            entry("other", &[]),
+        ];
+
+        let taken = vec!["title".to_owned(), "alias".to_owned()].into_iter().collect();
+
+        let imports = plan_imports(&manifest, taken);
+
+        assert_eq!(vec![
+            Import { index: 2, aliases: vec!["fresh".to_owned()] },
+            Import { index: 4, aliases: vec![] },
+        ], imports);
+
+        // only media the imported memes use gets created
+        assert_eq!(1, first_uses(&manifest, &imports).len());
+        assert!(first_uses(&manifest, &[]).is_empty());
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    fs::{
+        self,
+        File,
+    },
+    io::{
+        ErrorKind,
+        Write,
+    },
+    path::{
+        Path,
+        PathBuf,
+    },
+};
+
+use anyhow::anyhow;
+
+use crate::Result;
+
+use super::{
+    BlobStore,
+    valid_key,
+};
+
+/// Blobs as files under a root directory, fanned out by the first byte of the key.
+#[derive(Clone, Debug)]
+pub struct FsStore {
+    root: PathBuf,
+}
+
+impl FsStore {
+    pub fn new<P: AsRef<Path>>(root: P) -> Result<Self> {
+        let root = root.as_ref().to_owned();
+        fs::create_dir_all(&root)?;
+
+        Ok(FsStore {
+            root,
+        })
+    }
+
+    fn path(&self, key: &str) -> Result<PathBuf> {
+        if !valid_key(key) {
+            return Err(anyhow!("invalid blob key '{}'", key));
+        }
+
+        Ok(self.root.join(&key[..2]).join(key))
+    }
+}
+
+impl BlobStore for FsStore {
+    fn put(&self, key: &str, data: &[u8]) -> Result<()> {
+        let path = self.path(key)?;
+
+        if path.exists() {
+            return Ok(());
+        }
+
+        let dir = path.parent().unwrap();
+        fs::create_dir_all(dir)?;
+
+        // write then rename, so a crash never leaves a truncated blob under the real key
+        let tmp = dir.join(format!(".{}.tmp", key));
+
+        {
+            let mut f = File::create(&tmp)?;
+            f.write_all(data)?;
+            f.sync_all()?;
+        }
+
+        fs::rename(&tmp, &path)?;
+
+        Ok(())
+    }
+
+    fn get(&self, key: &str) -> Result<Vec<u8>> {
+        let path = self.path(key)?;
+        fs::read(&path).map_err(|e| anyhow!("reading blob {}: {}", key, e))
+    }
+
+    fn contains(&self, key: &str) -> Result<bool> {
+        Ok(self.path(key)?.exists())
+    }
+
+    fn delete(&self, key: &str) -> Result<()> {
+        match fs::remove_file(self.path(key)?) {
+            Err(ref e) if e.kind() == ErrorKind::NotFound => Ok(()),
+            result => result.map_err(Into::into),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::{
+        env,
+        process,
+    };
+
+    use super::*;
+
+    #[test]
+    fn test_round_trip() {
+        let root = env::temp_dir().join(format!("thulani-blobs-{}", process::id()));
+        let store = FsStore::new(&root).unwrap();
+
+        let key = "0123abcd";
+        assert!(!store.contains(key).unwrap());
+
+        store.put(key, b"meme").unwrap();
+        assert!(store.contains(key).unwrap());
+        assert_eq!(b"meme".to_vec(), store.get(key).unwrap());
+        assert!(root.join("01").join(key).exists());
+
+        store.put(key, b"meme").unwrap();
+
+        store.delete(key).unwrap();
+        assert!(!store.contains(key).unwrap());
+        assert!(store.get(key).is_err());
+        store.delete(key).unwrap();
+
+        assert!(store.put("../escape", b"no").is_err());
+
+        fs::remove_dir_all(&root).unwrap();
+    }
+}
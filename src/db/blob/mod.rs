@@ -0,0 +1,81 @@
+use std::fmt::Write;
+
+use anyhow::anyhow;
+use lazy_static::lazy_static;
+use log::info;
+
+use crate::{
+    CONFIG,
+    Result,
+};
+
+pub use self::{
+    fs::FsStore,
+    s3::S3Store,
+};
+
+mod fs;
+mod s3;
+
+/// Content-addressed storage for meme media. Keys are derived from the content's hash, so putting
+/// the same key twice is a no-op.
+pub trait BlobStore: Send + Sync {
+    fn put(&self, key: &str, data: &[u8]) -> Result<()>;
+    fn get(&self, key: &str) -> Result<Vec<u8>>;
+    fn contains(&self, key: &str) -> Result<bool>;
+    fn delete(&self, key: &str) -> Result<()>;
+}
+
+lazy_static! {
+    pub static ref BLOBS: Box<dyn BlobStore> = {
+        let store = from_config().expect("couldn't set up blob store");
+        info!("storing blobs in {}", CONFIG.blobs.store);
+
+        store
+    };
+}
+
+fn from_config() -> Result<Box<dyn BlobStore>> {
+    let config = &CONFIG.blobs;
+
+    match config.store.as_str() {
+        "fs" => Ok(Box::new(FsStore::new(&config.dir)?)),
+        "s3" => Ok(Box::new(S3Store::new(
+            &config.s3_endpoint,
+            &config.s3_bucket,
+            &config.s3_region,
+            &config.s3_access_key,
+            &config.s3_secret_key,
+        )?)),
+        other => Err(anyhow!("unknown blob store '{}'", other)),
+    }
+}
+
+/// The blob key for content with the given hash.
+pub fn key(hash: &[u8]) -> String {
+    hex(hash)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().fold(String::with_capacity(bytes.len() * 2), |mut s, b| {
+        write!(s, "{:02x}", b).unwrap();
+        s
+    })
+}
+
+fn valid_key(key: &str) -> bool {
+    key.len() > 2 && key.chars().all(|c| c.is_ascii_hexdigit())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_key() {
+        assert_eq!("00ff10", key(&[0, 255, 16]));
+        assert!(valid_key(&key(&[0xde, 0xad, 0xbe, 0xef])));
+        assert!(!valid_key("../etc/passwd"));
+        assert!(!valid_key("ab"));
+    }
+}
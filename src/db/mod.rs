@@ -1,15 +1,19 @@
 use std::{
     convert::AsRef,
     env,
+    thread,
+    time::Duration,
 };
 
 use chrono::{
     Date,
     DateTime,
+    NaiveDateTime,
     Utc,
 };
 use diesel::{
     NotFound,
+    pg::Pg,
     prelude::*,
     r2d2::{ConnectionManager, ManageConnection},
 };
@@ -21,14 +25,51 @@ use r2d2_postgres::{
 
 use anyhow::anyhow;
 use lazy_static::lazy_static;
+use log::{
+    error,
+    info,
+};
 
-use crate::{Error, Result};
+use crate::{
+    CONFIG,
+    Error,
+    Result,
+};
 
+pub use self::archive::{
+    export_memes,
+    ExportSummary,
+    import_memes,
+    ImportSummary,
+};
 pub use self::models::*;
-use self::schema::*;
+pub use self::rarity::{
+    Rarity,
+    UnknownRarity,
+};
+pub use self::repeats::check_repeats;
+pub use self::search::{
+    Ambiguous,
+    search_memes,
+};
+use self::{
+    blob::BLOBS,
+    rarity::{
+        pick,
+        Usage,
+    },
+    repeats::choose_fresh,
+    schema::*,
+};
 
+mod archive;
+mod blob;
+mod perceptual;
+mod rarity;
+mod repeats;
 mod schema;
 mod models;
+mod search;
 
 lazy_static! {
     static ref DB_URL: String = env::var("DATABASE_URL").expect("no database url in environment").into();
@@ -46,10 +87,12 @@ fn raw_connection() -> Result<RawPgConn> {
     RAW_CONN_MGR.connect().map_err(Error::from)
 }
 
-pub fn find_meme<T: AsRef<str>>(conn: &PgConnection, search: T) -> Result<Meme> {
-    use diesel::dsl::sql;
-    use diesel::sql_types::Text;
+/// Candidates considered when falling back to a ranked search.
+const FIND_CANDIDATES: usize = 10;
 
+/// Find a meme by exact title, then by alias, then by ranked fuzzy search. Fails with
+/// [`Ambiguous`] if the best fuzzy matches are too close to call.
+pub fn find_meme<T: AsRef<str>>(conn: &PgConnection, search: T) -> Result<Meme> {
     let search = search.as_ref();
 
     let mut meme = memes::table
@@ -58,63 +101,104 @@ pub fn find_meme<T: AsRef<str>>(conn: &PgConnection, search: T) -> Result<Meme>
         .first::<Meme>(conn);
 
     if let Err(NotFound) = meme {
-        let format_search = format!("%{}%", search);
-
         meme = memes::table
-            .filter(memes::title.ilike(&format_search).or(sql("content ILIKE ").bind::<Text, _>(&format_search)))
-            .limit(1)
+            .inner_join(meme_aliases::table.on(meme_aliases::meme_id.eq(memes::id)))
+            .filter(meme_aliases::alias.eq(search))
+            .select(memes::all_columns)
             .first::<Meme>(conn);
     }
 
+    if let Err(NotFound) = meme {
+        return match search::choose(search_memes(search, FIND_CANDIDATES)?) {
+            search::Choice::Nothing => Err(NotFound.into()),
+            search::Choice::One(meme) => Ok(meme),
+            search::Choice::Ambiguous(memes) => Err(Ambiguous(memes.into_iter().map(|m| m.title).collect()).into()),
+        };
+    }
+
     meme
         .map_err(Error::from)
 }
 
-pub fn query_meme<T: AsRef<str>>(search: T, user_id: Option<u64>, age_desc: bool) -> Result<Vec<(Meme, Metadata)>> {
+/// Filters for [`query_meme`].
+#[derive(Clone, Debug, Default, PartialEq, Eq, Hash)]
+pub struct MemeQuery {
+    pub search: String,
+    pub creator: Option<u64>,
+    pub tag: Option<String>,
+    pub age_desc: bool,
+}
+
+/// One page of [`query_meme`] results (each with its metadata and number of images), along with
+/// how many memes matched overall.
+#[derive(Clone, Debug)]
+pub struct QueryPage {
+    pub memes: Vec<(Meme, Metadata, usize)>,
+    pub total: usize,
+}
+
+/// Find memes whose title or text matches `query.search`, either as words (through the full-text
+/// index) or as a substring. `page` counts from 0.
+pub fn query_meme(query: &MemeQuery, page: usize, page_size: usize) -> Result<QueryPage> {
     let raw_conn = raw_connection()?;
 
-    let search = format!("%{}%", search.as_ref());
+    let pattern = format!("%{}%", query.search);
 
     let rows = raw_conn.query(&format!(r#"
-    SELECT memes.id, title, content, image_id, audio_id, metadata_id, created, created_by
+    SELECT memes.id, title, content, audio_id, metadata_id, created, created_by, volume,
+           COUNT(*) OVER () AS total,
+           (SELECT COUNT(*) FROM meme_images WHERE meme_images.meme_id = memes.id) AS images
     FROM memes
     INNER JOIN metadata ON memes.metadata_id = metadata.id
-    WHERE (memes.title ILIKE $1 OR memes.content ILIKE $1)
-              AND (metadata.created_by = $2 OR $3)
+    WHERE ($1 = '' OR memes.search_vector @@ plainto_tsquery('english', $1)
+                   OR memes.title ILIKE $2 OR memes.content ILIKE $2)
+              AND (metadata.created_by = $3 OR $4)
+              AND ($5::VARCHAR IS NULL OR EXISTS (SELECT 1 FROM meme_tags WHERE meme_tags.meme_id = memes.id AND meme_tags.tag = $5))
     ORDER BY metadata.created {}
-    LIMIT 100
+    LIMIT $6 OFFSET $7
     "#,
-        if age_desc { "DESC" } else { "ASC" },
+        if query.age_desc { "DESC" } else { "ASC" },
     ), &[
-        &search,
-        &(user_id.unwrap_or(0) as i64),
-        &user_id.is_none(),
+        &query.search,
+        &pattern,
+        &(query.creator.unwrap_or(0) as i64),
+        &query.creator.is_none(),
+        &query.tag,
+        &(page_size as i64),
+        &((page * page_size) as i64),
     ])?;
 
-    let result = rows.iter()
+    let total = rows.iter().next().map_or(0, |row| row.get::<_, i64>(8) as usize);
+
+    let memes = rows.iter()
         .map(|row| {
             let meme = Meme {
                 id: row.get(0),
                 title: row.get(1),
                 content: row.get(2),
-                image_id: row.get(3),
-                audio_id: row.get(4),
-                metadata_id: row.get(5),
+                audio_id: row.get(3),
+                metadata_id: row.get(4),
+                volume: row.get(7),
             };
 
             let metadata = Metadata {
-                id: row.get(5),
-                created: row.get(6),
-                created_by: row.get(7),
+                id: row.get(4),
+                created: row.get(5),
+                created_by: row.get(6),
             };
 
-            (meme, metadata)
+            (meme, metadata, row.get::<_, i64>(9) as usize)
         })
         .collect();
 
-    Ok(result)
+    Ok(QueryPage {
+        memes,
+        total,
+    })
 }
 
+/// Soft-delete a meme: the row is replaced by a tombstone holding its contents, and its images and
+/// audio are kept until the tombstone is purged.
 pub fn delete_meme<T: AsRef<str>>(conn: &PgConnection, search: T, deleted_by: u64) -> Result<()> {
     conn.transaction::<(), Error, _>(|| {
         let deleted = memes::table
@@ -125,126 +209,646 @@ pub fn delete_meme<T: AsRef<str>>(conn: &PgConnection, search: T, deleted_by: u6
             .filter(memes::id.eq(deleted.id))
             .execute(conn)?;
 
-        if let Some(image_id) = deleted.image_id {
-            let count = memes::table
-                .filter(memes::image_id.eq(image_id))
+        let _ = ::diesel::insert_into(tombstones::table)
+            .values(&NewTombstone::for_meme(deleted, deleted_by))
+            .execute(conn)?;
+
+        Ok(())
+    })
+}
+
+/// Restore the most recently deleted meme with this title from its tombstone. Fails with a unique
+/// violation if a live meme has since taken the title.
+pub fn undelete_meme<T: AsRef<str>>(conn: &PgConnection, title: T, restored_by: u64) -> Result<Meme> {
+    conn.transaction::<_, Error, _>(|| {
+        let tombstone = tombstones::table
+            .filter(tombstones::title.eq(title.as_ref()))
+            .order(tombstones::deleted_at.desc())
+            .first::<Tombstone>(conn)?;
+
+        let metadata_id = tombstone.metadata_id
+            .ok_or_else(|| anyhow!("tombstone {} has no metadata", tombstone.id))?;
+
+        let meme = ::diesel::insert_into(memes::table)
+            .values((
+                memes::id.eq(tombstone.meme_id),
+                memes::title.eq(title.as_ref()),
+                memes::content.eq(&tombstone.content),
+                memes::audio_id.eq(tombstone.audio_id),
+                memes::metadata_id.eq(metadata_id),
+                memes::volume.eq(tombstone.volume),
+            ))
+            .get_result::<Meme>(conn)?;
+
+        ::diesel::delete(tombstones::table.find(tombstone.id))
+            .execute(conn)?;
+
+        AuditRecord::create(conn, metadata_id, restored_by, Some("undeleted"))?;
+
+        Ok(meme)
+    })
+}
+
+/// How many images and audio clips have content with this hash, sha256 or legacy sha1. The two
+/// differ in length, so a hash can only ever match one column.
+fn blob_refs(conn: &PgConnection, hash: &[u8]) -> Result<i64> {
+    let images = images::table
+        .filter(images::sha256.eq(hash).or(images::data_hash.eq(hash)))
+        .count()
+        .get_result::<i64>(conn)?;
+
+    let audio = audio::table
+        .filter(audio::sha256.eq(hash).or(audio::data_hash.eq(hash)))
+        .count()
+        .get_result::<i64>(conn)?;
+
+    Ok(images + audio)
+}
+
+/// Drop tombstones older than `retention`, along with their albums and any image or audio nothing
+/// else refers to. Legacy tombstones, which have no title, are kept.
+/// Returns the number of tombstones purged.
+pub fn purge_tombstones(conn: &PgConnection, retention: chrono::Duration) -> Result<usize> {
+    use fnv::FnvHashSet;
+
+    let cutoff = (Utc::now() - retention).naive_utc();
+
+    let (purged, hashes) = conn.transaction::<_, Error, _>(|| {
+        let purged = ::diesel::delete(tombstones::table)
+            .filter(tombstones::deleted_at.lt(cutoff))
+            .filter(tombstones::title.is_not_null())
+            .get_results::<Tombstone>(conn)?;
+
+        let meme_ids = purged.iter().map(|t| t.meme_id).collect::<Vec<_>>();
+        ::diesel::delete(meme_tags::table)
+            .filter(meme_tags::meme_id.eq_any(&meme_ids))
+            .execute(conn)?;
+
+        ::diesel::delete(meme_aliases::table)
+            .filter(meme_aliases::meme_id.eq_any(&meme_ids))
+            .execute(conn)?;
+
+        let image_ids = ::diesel::delete(meme_images::table)
+            .filter(meme_images::meme_id.eq_any(&meme_ids))
+            .returning(meme_images::image_id)
+            .get_results::<i32>(conn)?;
+
+        let mut hashes = Vec::new();
+
+        // albums of live and deleted memes alike are in meme_images
+        for image_id in image_ids.into_iter().collect::<FnvHashSet<_>>() {
+            let refs = meme_images::table
+                .filter(meme_images::image_id.eq(image_id))
                 .count()
-                .execute(conn)?;
+                .get_result::<i64>(conn)?;
 
-            if count == 0 {
-                ::diesel::delete(images::table)
-                    .filter(images::id.eq(image_id))
-                    .execute(conn)?;
+            if refs == 0 {
+                hashes.push(::diesel::delete(images::table.find(image_id))
+                    .returning((images::sha256, images::data_hash))
+                    .get_result::<(Option<Vec<u8>>, Option<Vec<u8>>)>(conn)?);
             }
         }
 
-        if let Some(audio_id) = deleted.audio_id {
-            let count = memes::table
-                .select(::diesel::dsl::count_star())
-                .filter(memes::audio_id.eq(audio_id))
-                .execute(conn)?;
+        let audio_ids = purged.iter().filter_map(|t| t.audio_id).collect::<FnvHashSet<_>>();
+        for audio_id in audio_ids {
+            let refs = memes::table.filter(memes::audio_id.eq(audio_id)).count().get_result::<i64>(conn)? +
+                tombstones::table.filter(tombstones::audio_id.eq(audio_id)).count().get_result::<i64>(conn)?;
 
-            if count == 0 {
-                ::diesel::delete(audio::table)
-                    .filter(audio::id.eq(audio_id))
-                    .execute(conn)?;
+            if refs == 0 {
+                hashes.push(::diesel::delete(audio::table.find(audio_id))
+                    .returning((audio::sha256, audio::data_hash))
+                    .get_result::<(Option<Vec<u8>>, Option<Vec<u8>>)>(conn)?);
             }
         }
 
-        let tombstone = NewTombstone {
-            deleted_by: deleted_by as i64,
-            metadata_id: deleted.metadata_id,
-            meme_id: deleted.id,
+        Ok((purged.len(), hashes))
+    })?;
+
+    // only once the rows are definitely gone. images and audio share the key space, so check
+    // that nothing else still has the same content.
+    for hash in hashes.into_iter().filter_map(|(sha256, sha1)| sha256.or(sha1)) {
+        if blob_refs(conn, &hash)? == 0 {
+            BLOBS.delete(&blob::key(&hash))?;
+        }
+    }
+
+    Ok(purged)
+}
+
+/// Move images and audio still stored inline in the database out to the blob store, one row at a
+/// time. Each blob is read back before its column is cleared. Returns how many images and audio
+/// clips were moved.
+pub fn migrate_blobs(conn: &PgConnection) -> Result<(usize, usize)> {
+    fn store(hash: &[u8], data: &[u8]) -> Result<()> {
+        let key = blob::key(hash);
+        BLOBS.put(&key, data)?;
+
+        if BLOBS.get(&key)? != data {
+            return Err(anyhow!("blob {} didn't survive the round trip", key));
+        }
+
+        Ok(())
+    }
+
+    let image_ids = images::table
+        .select(images::id)
+        .filter(images::data.is_not_null())
+        .load::<i32>(conn)?;
+
+    for &id in &image_ids {
+        let (data, hash) = images::table.find(id)
+            .select((images::data, images::sha256))
+            .first::<(Option<Vec<u8>>, Option<Vec<u8>>)>(conn)?;
+
+        if let Some(data) = data {
+            let hash = hash.unwrap_or_else(|| sha256(&data));
+            store(&hash, &data)?;
+
+            ::diesel::update(images::table.find(id))
+                .set((images::data.eq(None::<Vec<u8>>), images::sha256.eq(Some(hash))))
+                .execute(conn)?;
+        }
+    }
+
+    let audio_ids = audio::table
+        .select(audio::id)
+        .filter(audio::data.is_not_null())
+        .load::<i32>(conn)?;
+
+    for &id in &audio_ids {
+        let (data, hash) = audio::table.find(id)
+            .select((audio::data, audio::sha256))
+            .first::<(Option<Vec<u8>>, Option<Vec<u8>>)>(conn)?;
+
+        if let Some(data) = data {
+            let hash = hash.unwrap_or_else(|| sha256(&data));
+            store(&hash, &data)?;
+
+            ::diesel::update(audio::table.find(id))
+                .set((audio::data.eq(None::<Vec<u8>>), audio::sha256.eq(Some(hash))))
+                .execute(conn)?;
+        }
+    }
+
+    Ok((image_ids.len(), audio_ids.len()))
+}
+
+#[derive(Clone, Debug, Default)]
+pub struct HashReport {
+    pub checked: usize,
+    /// Rows given a sha256 (and, if their blob was keyed by sha1, re-keyed).
+    pub backfilled: usize,
+    /// `("image" | "audio", id)` of rows whose content couldn't be loaded.
+    pub missing: Vec<(&'static str, i32)>,
+    /// `("image" | "audio", id)` of rows whose content doesn't match a stored hash.
+    pub mismatched: Vec<(&'static str, i32)>,
+}
+
+enum HashCheck {
+    Ok,
+    Missing,
+    Mismatch,
+    /// The row has no sha256 yet; this is it, along with the content.
+    Backfill(Vec<u8>, Vec<u8>),
+}
+
+fn check_hash(contents: Result<Vec<u8>>, sha256_hash: &Option<Vec<u8>>, sha1_hash: &Option<Vec<u8>>) -> HashCheck {
+    let data = match contents {
+        Ok(data) => data,
+        Err(_) => return HashCheck::Missing,
+    };
+
+    let actual = sha256(&data);
+
+    if sha256_hash.as_ref().map_or(false, |h| *h != actual) ||
+        sha1_hash.as_ref().map_or(false, |h| *h != sha1(&data)) {
+        return HashCheck::Mismatch;
+    }
+
+    match sha256_hash {
+        Some(_) => HashCheck::Ok,
+        None => HashCheck::Backfill(actual, data),
+    }
+}
+
+/// Record a row's sha256 with `set_hash`. Blobs stored under the row's sha1 are copied to the
+/// new key first, and the old key only dropped once no other row still depends on it.
+fn backfill_hash<F: FnOnce() -> Result<()>>(
+    conn: &PgConnection,
+    inline: bool,
+    sha1_hash: &Option<Vec<u8>>,
+    sha256_hash: &[u8],
+    data: &[u8],
+    set_hash: F,
+) -> Result<()> {
+    if inline {
+        return set_hash();
+    }
+
+    BLOBS.put(&blob::key(sha256_hash), data)?;
+    set_hash()?;
+
+    if let Some(sha1_hash) = sha1_hash {
+        let refs = images::table
+            .filter(images::data_hash.eq(sha1_hash).and(images::sha256.is_null()))
+            .count()
+            .get_result::<i64>(conn)? +
+            audio::table
+                .filter(audio::data_hash.eq(sha1_hash).and(audio::sha256.is_null()))
+                .count()
+                .get_result::<i64>(conn)?;
+
+        if refs == 0 {
+            BLOBS.delete(&blob::key(sha1_hash))?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Verify every image and audio clip against its stored hashes. Rows missing a sha256 are
+/// backfilled and their blobs re-keyed; rows that are missing or don't match are only reported.
+pub fn check_hashes(conn: &PgConnection) -> Result<HashReport> {
+    let mut report = HashReport::default();
+
+    for id in images::table.select(images::id).order(images::id).load::<i32>(conn)? {
+        let image = images::table.find(id).first::<Image>(conn)?;
+        report.checked += 1;
+
+        match check_hash(image.contents(), &image.sha256, &image.data_hash) {
+            HashCheck::Ok => {},
+            HashCheck::Missing => report.missing.push(("image", id)),
+            HashCheck::Mismatch => report.mismatched.push(("image", id)),
+            HashCheck::Backfill(hash, data) => {
+                backfill_hash(conn, image.data.is_some(), &image.data_hash, &hash, &data, || {
+                    ::diesel::update(images::table.find(id))
+                        .set(images::sha256.eq(Some(&hash)))
+                        .execute(conn)?;
+
+                    Ok(())
+                })?;
+
+                report.backfilled += 1;
+            },
+        }
+    }
+
+    for id in audio::table.select(audio::id).order(audio::id).load::<i32>(conn)? {
+        let audio = audio::table.find(id).first::<Audio>(conn)?;
+        report.checked += 1;
+
+        match check_hash(audio.contents(), &audio.sha256, &audio.data_hash) {
+            HashCheck::Ok => {},
+            HashCheck::Missing => report.missing.push(("audio", id)),
+            HashCheck::Mismatch => report.mismatched.push(("audio", id)),
+            HashCheck::Backfill(hash, data) => {
+                backfill_hash(conn, audio.data.is_some(), &audio.data_hash, &hash, &data, || {
+                    ::diesel::update(audio::table.find(id))
+                        .set(audio::sha256.eq(Some(&hash)))
+                        .execute(conn)?;
+
+                    Ok(())
+                })?;
+
+                report.backfilled += 1;
+            },
+        }
+    }
+
+    Ok(report)
+}
+
+/// Compute perceptual hashes for images and audio that haven't been tried yet. Returns how many
+/// rows got one; rows that can't be hashed are marked so they aren't fetched again, except where
+/// they couldn't be read at all. Audio is left alone entirely without `fpcalc`.
+pub fn backfill_perceptual(conn: &PgConnection) -> Result<usize> {
+    use crate::audio::{
+        fpcalc_available,
+        VIDEO_EXTENSIONS,
+    };
+
+    let mut updated = 0;
+
+    let mut query = images::table
+        .select(images::id)
+        .filter(images::perceptual_checked.eq(false))
+        .into_boxed();
+
+    // videos share the table but have nothing to hash, bar gifs' first frame
+    for ext in VIDEO_EXTENSIONS.iter().filter(|&&ext| ext != "gif") {
+        query = query.filter(images::filename.not_ilike(format!("%.{}", ext)));
+    }
+
+    let image_ids = query.load::<i32>(conn)?;
+
+    for id in image_ids {
+        let image = images::table.find(id).first::<Image>(conn)?;
+
+        let contents = match image.contents() {
+            Ok(contents) => contents,
+            Err(e) => {
+                error!("reading image {} to hash it: {}", id, e);
+                continue;
+            },
         };
 
-        let _ = ::diesel::insert_into(tombstones::table)
-            .values(&tombstone)
+        let hash = image_phash(&contents);
+
+        ::diesel::update(images::table.find(id))
+            .set((images::phash.eq(hash), images::perceptual_checked.eq(true)))
             .execute(conn)?;
 
-        Ok(())
-    })
+        if hash.is_some() {
+            updated += 1;
+        }
+    }
+
+    if !fpcalc_available() {
+        info!("fpcalc isn't installed, not fingerprinting audio");
+        return Ok(updated);
+    }
+
+    let audio_ids = audio::table
+        .select(audio::id)
+        .filter(audio::perceptual_checked.eq(false))
+        .load::<i32>(conn)?;
+
+    for id in audio_ids {
+        let audio = audio::table.find(id).first::<Audio>(conn)?;
+
+        let contents = match audio.contents() {
+            Ok(contents) => contents,
+            Err(e) => {
+                error!("reading audio {} to fingerprint it: {}", id, e);
+                continue;
+            },
+        };
+
+        let fingerprint = audio_fingerprint(&contents);
+        let fingerprinted = fingerprint.is_some();
+
+        ::diesel::update(audio::table.find(id))
+            .set((audio::fingerprint.eq(fingerprint), audio::perceptual_checked.eq(true)))
+            .execute(conn)?;
+
+        if fingerprinted {
+            updated += 1;
+        }
+    }
+
+    Ok(updated)
+}
+
+fn image_hashes(conn: &PgConnection) -> Result<Vec<(i32, u64)>> {
+    let hashes = images::table
+        .select((images::id, images::phash))
+        .filter(images::phash.is_not_null())
+        .load::<(i32, Option<i64>)>(conn)?;
+
+    Ok(hashes.into_iter().filter_map(|(id, hash)| hash.map(|h| (id, h as u64))).collect())
+}
+
+fn audio_fingerprints(conn: &PgConnection) -> Result<Vec<(i32, Vec<u32>)>> {
+    let fingerprints = audio::table
+        .select((audio::id, audio::fingerprint))
+        .filter(audio::fingerprint.is_not_null())
+        .load::<(i32, Option<Vec<i32>>)>(conn)?;
+
+    Ok(fingerprints.into_iter()
+        .filter_map(|(id, fp)| fp.map(|fp| (id, fp.into_iter().map(|x| x as u32).collect())))
+        .collect())
+}
+
+/// Other memes with an image or audio that's a near-duplicate of one of `meme`'s.
+pub fn near_duplicates(conn: &PgConnection, meme: &Meme) -> Result<Vec<Meme>> {
+    use self::perceptual::*;
+
+    let hashes = meme.images(conn)?.into_iter()
+        .filter_map(|image| image.phash)
+        .map(|hash| hash as u64)
+        .collect::<Vec<_>>();
+
+    let mut image_ids = Vec::new();
+    if !hashes.is_empty() {
+        image_ids = image_hashes(conn)?.into_iter()
+            .filter(|&(_, other)| hashes.iter().any(|&hash| hamming(hash, other) <= IMAGE_THRESHOLD))
+            .map(|(id, _)| id)
+            .collect();
+    }
+
+    let mut audio_ids = Vec::new();
+    if let Some(fp) = meme.audio(conn).transpose()?.and_then(|audio| audio.fingerprint) {
+        let fp = fp.into_iter().map(|x| x as u32).collect::<Vec<_>>();
+
+        audio_ids = audio_fingerprints(conn)?.into_iter()
+            .filter(|(_, other)| fingerprint_similarity(&fp, other) >= AUDIO_THRESHOLD)
+            .map(|(id, _)| id)
+            .collect();
+    }
+
+    memes::table
+        .filter(memes::id.ne(meme.id))
+        .filter(memes::id.eq_any(
+            meme_images::table
+                .select(meme_images::meme_id)
+                .filter(meme_images::image_id.eq_any(image_ids))
+        ).or(memes::audio_id.eq_any(audio_ids)))
+        .order(memes::title)
+        .load::<Meme>(conn)
+        .map_err(Error::from)
+}
+
+/// Groups of memes sharing near-duplicate images or audio. Only considers media that already has
+/// perceptual hashes; see [`backfill_perceptual`].
+pub fn duplicate_clusters(conn: &PgConnection) -> Result<Vec<Vec<Meme>>> {
+    use fnv::FnvHashMap;
+    use self::perceptual::*;
+
+    /// Link every meme using either side of each similar pair into one cluster.
+    fn link(by_media: &FnvHashMap<i32, Vec<usize>>, pairs: Vec<(i32, i32)>, edges: &mut Vec<(usize, usize)>) {
+        for (a, b) in pairs {
+            if let (Some(a), Some(b)) = (by_media.get(&a), by_media.get(&b)) {
+                edges.extend(a.iter().chain(b).map(|&m| (a[0], m)));
+            }
+        }
+    }
+
+    let memes = memes::table.order(memes::id).load::<Meme>(conn)?;
+
+    let index = memes.iter().enumerate().map(|(i, meme)| (meme.id, i)).collect::<FnvHashMap<_, _>>();
+
+    let mut by_image = FnvHashMap::default();
+    let mut by_audio = FnvHashMap::default();
+
+    // tombstoned memes' albums are in here too; they aren't in the index
+    let albums = meme_images::table
+        .select((meme_images::meme_id, meme_images::image_id))
+        .load::<(i32, i32)>(conn)?;
+
+    for (meme_id, image_id) in albums {
+        if let Some(&i) = index.get(&meme_id) {
+            by_image.entry(image_id).or_insert_with(Vec::new).push(i);
+        }
+    }
+
+    for (i, meme) in memes.iter().enumerate() {
+        if let Some(id) = meme.audio_id {
+            by_audio.entry(id).or_insert_with(Vec::new).push(i);
+        }
+    }
+
+    let images = image_hashes(conn)?;
+    let image_pairs = images.iter()
+        .enumerate()
+        .flat_map(|(i, &(a, ha))| images[i..].iter()
+            .filter(move |&&(_, hb)| hamming(ha, hb) <= IMAGE_THRESHOLD)
+            .map(move |&(b, _)| (a, b)))
+        .collect();
+
+    let audio = audio_fingerprints(conn)?;
+    let audio_pairs = audio.iter()
+        .enumerate()
+        .flat_map(|(i, (a, fa))| audio[i..].iter()
+            .filter(move |(_, fb)| fingerprint_similarity(fa, fb) >= AUDIO_THRESHOLD)
+            .map(move |(b, _)| (*a, *b)))
+        .collect();
+
+    let mut edges = Vec::new();
+    link(&by_image, image_pairs, &mut edges);
+    link(&by_audio, audio_pairs, &mut edges);
+
+    Ok(clusters(memes.len(), edges).into_iter()
+        .map(|cluster| cluster.into_iter().map(|i| memes[i].clone()).collect())
+        .collect())
 }
 
-pub fn rare_meme(conn: &PgConnection, audio: bool) -> Result<Meme> {
+/// Periodically purge tombstones older than `TOMBSTONE_RETENTION_DAYS`.
+pub fn spawn_tombstone_purge() {
+    const PURGE_INTERVAL: Duration = Duration::from_secs(60 * 60);
+
+    thread::spawn(|| loop {
+        let retention = chrono::Duration::days(CONFIG.tombstone_retention_days);
+
+        match connection().and_then(|conn| purge_tombstones(&conn, retention)) {
+            Ok(0) => {},
+            Ok(n) => info!("purged {} expired tombstones", n),
+            Err(e) => error!("purging tombstones: {}", e),
+        }
+
+        thread::sleep(PURGE_INTERVAL);
+    });
+}
+
+/// A rare meme and how it came to be picked.
+#[derive(Clone, Debug)]
+pub struct RareMeme {
+    pub meme: Meme,
+    pub weight: f64,
+    /// Its chance of having been picked, out of 1.
+    pub chance: f64,
+    /// Where its weight ranks among the candidates', from 1.
+    pub rank: usize,
+    pub candidates: usize,
+}
+
+/// Pick a meme at random, favoring rare ones as judged by `rarity`. Audio memes are only
+/// candidates if `audio` is set.
+pub fn rare_meme(conn: &PgConnection, audio: bool, rarity: Rarity) -> Result<RareMeme> {
+    use fnv::FnvHashMap;
     use rand::prelude::*;
 
     let raw_conn = raw_connection()?;
 
     let rows = raw_conn.query(r#"
-    WITH
-    meme_count AS (
-        SELECT
-               meme_id,
-               COUNT(*) AS ct
-        FROM invocation_records
-        GROUP BY meme_id
-    ),
-    aggregate AS (
-        SELECT
-               memes.id AS meme_id,
-               COALESCE(meme_count.ct, 0) AS ct,
-               EXTRACT(EPOCH FROM (now() - metadata.created)) AS time_diff
-        FROM meme_count
-            RIGHT JOIN memes ON memes.id = meme_count.meme_id
-            INNER JOIN metadata ON metadata.id = memes.metadata_id
-        WHERE (memes.audio_id IS NULL) = $1 OR $2
-    ),
-    least_used AS (
-        SELECT
-               meme_id,
-               TRUNC(time_diff / (ct + 1)) as play_prop
-        FROM aggregate
-    )
     SELECT
-           meme_id,
-           sum(play_prop) OVER (ORDER BY play_prop DESC) as play_prop
-    FROM least_used
-    LIMIT 100;
+           memes.id,
+           EXTRACT(EPOCH FROM (now() - metadata.created))::FLOAT8,
+           COUNT(invocation_records.id),
+           EXTRACT(EPOCH FROM (now() - MAX(invocation_records.time)))::FLOAT8
+    FROM memes
+        INNER JOIN metadata ON metadata.id = memes.metadata_id
+        LEFT JOIN invocation_records ON invocation_records.meme_id = memes.id
+    WHERE (memes.audio_id IS NULL) = $1 OR $2
+    GROUP BY memes.id, metadata.created
     "#, &[&!audio, &audio])?;
 
-    let elems = rows.iter()
-        .map(|row| (row.get::<_, i32>(0), row.get::<_, f64>(1) as i64))
-        .collect::<Vec<_>>();
+    let mut play_ages = FnvHashMap::<i32, Vec<f64>>::default();
 
-    if elems.len() == 0 {
-        return Err(anyhow!("no rare memes found"));
+    if rarity.needs_play_ages() {
+        let plays = raw_conn.query(r#"
+        SELECT invocation_records.meme_id, EXTRACT(EPOCH FROM (now() - invocation_records.time))::FLOAT8
+        FROM invocation_records
+            INNER JOIN memes ON memes.id = invocation_records.meme_id
+        WHERE (memes.audio_id IS NULL) = $1 OR $2
+        "#, &[&!audio, &audio])?;
+
+        for row in plays.iter() {
+            play_ages.entry(row.get(0)).or_default().push(row.get(1));
+        }
     }
 
-    let mut rng = thread_rng();
-    let target_prob = rng.gen_range(0, elems.last().unwrap().1);
+    let usages = rows.iter()
+        .map(|row| Usage {
+            meme_id: row.get(0),
+            age: row.get(1),
+            plays: row.get::<_, i64>(2) as usize,
+            last_played: row.get(3),
+            play_ages: play_ages.remove(&row.get::<_, i32>(0)).unwrap_or_default(),
+        })
+        .collect::<Vec<_>>();
 
-    let meme_id = elems.into_iter()
-        .find(|(_, x)| target_prob < *x)
-        .ok_or(anyhow!("couldn't locate meme satisfying target probability"))?
-        .0;
+    let ranked = rarity.rank(&usages);
 
-    Meme::find(conn, meme_id)
+    let i = pick(&ranked, thread_rng().gen())
+        .ok_or_else(|| anyhow!("no rare memes found"))?;
+
+    let (meme_id, weight) = ranked[i];
+    let total = ranked.iter().map(|&(_, weight)| weight).sum::<f64>();
+
+    Ok(RareMeme {
+        meme: Meme::find(conn, meme_id)?,
+        weight,
+        chance: weight / total,
+        rank: i + 1,
+        candidates: ranked.len(),
+    })
 }
 
-pub fn rand_meme(conn: &PgConnection, audio: bool) -> Result<Meme> {
-    use rand::{thread_rng, seq::SliceRandom};
-    use std::ops::Try;
+/// Restrict a meme query to memes carrying `tag`, if given.
+fn tagged<'a>(query: memes::BoxedQuery<'a, Pg>, tag: Option<&'a str>) -> memes::BoxedQuery<'a, Pg> {
+    match tag {
+        Some(tag) => query.filter(memes::id.eq_any(
+            meme_tags::table
+                .select(meme_tags::meme_id)
+                .filter(meme_tags::tag.eq(tag))
+        )),
+        None => query,
+    }
+}
 
-    let ids: Vec<i32> = if audio {
+pub fn rand_meme(conn: &PgConnection, audio: bool, tag: Option<&str>) -> Result<Meme> {
+    let has_images = memes::id.eq_any(meme_images::table.select(meme_images::meme_id));
+
+    let query = if audio {
         memes::table
-            .select(memes::id)
             .filter(memes::content.is_not_null()
-                .or(memes::image_id.is_not_null())
+                .or(has_images)
                 .or(memes::audio_id.is_not_null()))
-            .load(conn)
-            .map_err(Error::from)?
+            .into_boxed()
     } else {
         memes::table
-            .select(memes::id)
             .filter(memes::content.is_not_null()
-                .or(memes::image_id.is_not_null()))
-            .load(conn)
-            .map_err(Error::from)?
+                .or(has_images))
+            .into_boxed()
     };
 
-    let id = ids.choose(&mut thread_rng())
-        .into_result()
-        .map_err( |_| anyhow!("couldn't load meme"))?;
+    let ids: Vec<i32> = tagged(query, tag)
+        .select(memes::id)
+        .load(conn)
+        .map_err(Error::from)?;
+
+    let id = choose_fresh(conn, if audio { "any" } else { "no audio" }, tag, &ids)?
+        .ok_or_else(|| anyhow!("couldn't load meme"))?;
 
     memes::table
         .find(id)
@@ -252,19 +856,18 @@ pub fn rand_meme(conn: &PgConnection, audio: bool) -> Result<Meme> {
         .map_err(Error::from)
 }
 
-pub fn rand_audio_meme(conn: &PgConnection) -> Result<Meme> {
-    use rand::{thread_rng, seq::SliceRandom};
-    use std::ops::Try;
+pub fn rand_audio_meme(conn: &PgConnection, tag: Option<&str>) -> Result<Meme> {
+    let query = memes::table
+        .filter(memes::audio_id.is_not_null())
+        .into_boxed();
 
-    let ids: Vec<i32> = memes::table
+    let ids: Vec<i32> = tagged(query, tag)
         .select(memes::id)
-        .filter(memes::audio_id.is_not_null())
         .load(conn)
         .map_err(Error::from)?;
 
-    let id = ids.choose(&mut thread_rng())
-        .into_result()
-        .map_err(|_| anyhow!("couldn't load audio meme"))?;
+    let id = choose_fresh(conn, "audio", tag, &ids)?
+        .ok_or_else(|| anyhow!("couldn't load audio meme"))?;
 
     memes::table
         .find(id)
@@ -272,19 +875,18 @@ pub fn rand_audio_meme(conn: &PgConnection) -> Result<Meme> {
         .map_err(Error::from)
 }
 
-pub fn rand_silent_meme(conn: &PgConnection) -> Result<Meme> {
-    use rand::{thread_rng, seq::SliceRandom};
-    use std::ops::Try;
+pub fn rand_silent_meme(conn: &PgConnection, tag: Option<&str>) -> Result<Meme> {
+    let query = memes::table
+        .filter(memes::audio_id.is_null())
+        .into_boxed();
 
-    let ids: Vec<i32> = memes::table
+    let ids: Vec<i32> = tagged(query, tag)
         .select(memes::id)
-        .filter(memes::audio_id.is_null())
         .load(conn)
         .map_err(Error::from)?;
 
-    let id = ids.choose(&mut thread_rng())
-        .into_result()
-        .map_err(|_| anyhow!("couldn't load audio meme"))?;
+    let id = choose_fresh(conn, "silent", tag, &ids)?
+        .ok_or_else(|| anyhow!("couldn't load silent meme"))?;
 
     memes::table
         .find(id)
@@ -292,43 +894,76 @@ pub fn rand_silent_meme(conn: &PgConnection) -> Result<Meme> {
         .map_err(Error::from)
 }
 
+/// A window of time for stats, by invocation (or creation) time. Either end may be open.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+pub struct TimeRange {
+    pub since: Option<NaiveDateTime>,
+    /// Exclusive.
+    pub until: Option<NaiveDateTime>,
+}
+
+impl TimeRange {
+    #[inline]
+    pub fn is_all_time(&self) -> bool {
+        self.since.is_none() && self.until.is_none()
+    }
+}
+
+/// Raw SQL condition restricting `invocation_records` to a [`TimeRange`] given as `$1` and `$2`.
+const INVOCATIONS_IN_RANGE: &str = "($1::TIMESTAMP IS NULL OR invocation_records.time >= $1) \
+                                    AND ($2::TIMESTAMP IS NULL OR invocation_records.time < $2)";
+
+/// Invocations within `range`.
+fn invocations_in<'a>(range: &TimeRange) -> invocation_records::BoxedQuery<'a, Pg> {
+    let mut query = invocation_records::table.into_boxed();
+
+    if let Some(since) = range.since {
+        query = query.filter(invocation_records::time.ge(since));
+    }
+
+    if let Some(until) = range.until {
+        query = query.filter(invocation_records::time.lt(until));
+    }
+
+    query
+}
+
+/// The first row of a `SELECT value, COUNT(*) ... LIMIT 1` query, if there was one.
+fn top_row<T: postgres::types::FromSql>(rows: &postgres::rows::Rows) -> Option<(T, usize)> {
+    rows.iter().next().map(|row| (row.get(0), row.get::<_, i64>(1) as usize))
+}
+
 #[derive(Debug, Clone)]
 pub struct Stats {
+    pub range: TimeRange,
+
     pub memes_overall: usize,
     pub audio_memes: usize,
     pub image_memes: usize,
-    pub started_recording: DateTime<Utc>,
+    /// Memes created within the range.
+    pub new_memes: usize,
+
+    /// The first invocation within the range, if there were any.
+    pub started_recording: Option<DateTime<Utc>>,
     pub total_meme_invocations: usize,
     pub audio_meme_invocations: usize,
     pub random_meme_invocations: usize,
 
-    pub most_active_day: Date<Utc>,
-    pub most_active_day_count: usize,
-
-    pub most_audio_active_day: Date<Utc>,
-    pub most_audio_active_count: usize,
-
-    pub most_random_meme_user: u64,
-    pub most_random_meme_user_count: usize,
-    pub most_directly_named_meme_user: u64,
-    pub most_directly_named_meme_count: usize,
+    /// Records are `(what, count)`, and missing if nothing qualified within the range.
+    pub most_active_day: Option<(Date<Utc>, usize)>,
+    pub most_audio_active_day: Option<(Date<Utc>, usize)>,
 
-    pub most_popular_named_meme: String,
-    pub most_popular_named_meme_count: usize,
+    pub most_random_meme_user: Option<(u64, usize)>,
+    pub most_directly_named_meme_user: Option<(u64, usize)>,
 
-    pub most_popular_random_meme: String,
-    pub most_popular_random_meme_count: usize,
-
-    pub most_popular_meme_overall: String,
-    pub most_popular_meme_overall_count: usize,
+    pub most_popular_named_meme: Option<(String, usize)>,
+    pub most_popular_random_meme: Option<(String, usize)>,
+    pub most_popular_meme_overall: Option<(String, usize)>,
 }
 
-pub fn stats(conn: &PgConnection) -> Result<Stats> {
+pub fn stats(conn: &PgConnection, range: &TimeRange) -> Result<Stats> {
     use diesel::dsl::{count_star, count};
-    use chrono::{
-        NaiveDateTime,
-        NaiveDate,
-    };
+    use chrono::NaiveDate;
 
     #[inline]
     fn to_utc(ndt: NaiveDateTime) -> DateTime<Utc> {
@@ -346,8 +981,8 @@ pub fn stats(conn: &PgConnection) -> Result<Stats> {
         .map_err(Error::from)?;
 
     let image_count: i64 = memes::table
-        .select(count(memes::image_id))
-        .filter(memes::image_id.is_not_null())
+        .select(count_star())
+        .filter(memes::id.eq_any(meme_images::table.select(meme_images::meme_id)))
         .first(conn)
         .map_err(Error::from)?;
 
@@ -357,27 +992,29 @@ pub fn stats(conn: &PgConnection) -> Result<Stats> {
         .first(conn)
         .map_err(Error::from)?;
 
-    let started_recording: NaiveDateTime = invocation_records::table
+    let started_recording: Option<NaiveDateTime> = invocations_in(range)
         .select(invocation_records::time)
         .order(invocation_records::time)
         .first(conn)
+        .optional()
         .map_err(Error::from)?;
 
-    let started_recording = to_utc(started_recording);
-
-    let total_meme_invocations: i64 = invocation_records::table
+    let total_meme_invocations: i64 = invocations_in(range)
         .select(count_star())
         .first(conn)
         .map_err(Error::from)?;
 
-    let audio_meme_invocations: i64 = invocation_records::table
-        .inner_join(memes::table)
+    let audio_meme_invocations: i64 = invocations_in(range)
         .select(count_star())
-        .filter(memes::audio_id.is_not_null())
+        .filter(invocation_records::meme_id.eq_any(
+            memes::table
+                .select(memes::id)
+                .filter(memes::audio_id.is_not_null())
+        ))
         .first(conn)
         .map_err(Error::from)?;
 
-    let random_meme_invocations: i64 = invocation_records::table
+    let random_meme_invocations: i64 = invocations_in(range)
         .select(count_star())
         .filter(invocation_records::random.eq(true))
         .first(conn)
@@ -385,126 +1022,101 @@ pub fn stats(conn: &PgConnection) -> Result<Stats> {
 
     let raw_conn = raw_connection()?;
 
-    let rows = raw_conn.query(r#"
+    // every raw query below is restricted to the range by these
+    let params: &[&dyn postgres::types::ToSql] = &[&range.since, &range.until];
+    let in_range = INVOCATIONS_IN_RANGE;
+
+    let new_memes = raw_conn.query(r#"
+    SELECT COUNT(*) FROM memes
+    INNER JOIN metadata ON memes.metadata_id = metadata.id
+    WHERE ($1::TIMESTAMP IS NULL OR metadata.created >= $1)
+      AND ($2::TIMESTAMP IS NULL OR metadata.created < $2)
+    "#, params)?.get(0).get::<_, i64>(0);
+
+    let most_active_day = top_row::<NaiveDate>(&raw_conn.query(&format!(r#"
     SELECT DATE(time) as dt, COUNT(*) FROM invocation_records
+    WHERE {}
     GROUP BY dt
     ORDER BY COUNT(*) DESC
     LIMIT 1;
-    "#, &[])?;
-
-    let row = rows.get(0);
-
-    let most_active_day = to_utc_date(row.get(0));
-    let most_active_day_count: i64 = row.get(1);
+    "#, in_range), params)?);
 
-    let rows = raw_conn.query(r#"
+    let most_audio_active_day = top_row::<NaiveDate>(&raw_conn.query(&format!(r#"
     SELECT DATE(time) as dt, COUNT(*) FROM invocation_records
     INNER JOIN memes ON invocation_records.meme_id = memes.id
-    WHERE memes.audio_id IS NOT NULL
+    WHERE memes.audio_id IS NOT NULL AND {}
     GROUP BY dt
     ORDER BY COUNT(*) DESC
     LIMIT 1;
-    "#, &[])?;
-
-    let row = rows.get(0);
+    "#, in_range), params)?);
 
-    let most_active_audio_day = to_utc_date(row.get(0));
-    let most_active_audio_day_count: i64 = row.get(1);
-
-    let rows = raw_conn.query(r#"
+    let most_random_invoker = top_row::<i64>(&raw_conn.query(&format!(r#"
     SELECT user_id, COUNT(*) FROM invocation_records
-    WHERE random IS TRUE
+    WHERE random IS TRUE AND {}
     GROUP BY user_id
     ORDER BY COUNT(*) DESC
     LIMIT 1;
-    "#, &[])?;
-
-    let row = rows.get(0);
-
-    let most_random_invoker: i64 = row.get(0);
-    let most_random_invoker_count: i64 = row.get(1);
+    "#, in_range), params)?);
 
-    let rows = raw_conn.query(r#"
+    let most_specific_invoker = top_row::<i64>(&raw_conn.query(&format!(r#"
     SELECT user_id, COUNT(*) FROM invocation_records
-    WHERE random IS FALSE
+    WHERE random IS FALSE AND {}
     GROUP BY user_id
     ORDER BY COUNT(*) DESC
     LIMIT 1;
-    "#, &[])?;
+    "#, in_range), params)?);
 
-    let row = rows.get(0);
-
-    let most_specific_invoker: i64 = row.get(0);
-    let most_specific_invoker_count: i64 = row.get(1);
-
-    let rows = raw_conn.query(r#"
+    let most_requested_meme = top_row::<String>(&raw_conn.query(&format!(r#"
     SELECT memes.title, COUNT(*) FROM invocation_records
     INNER JOIN memes ON meme_id = memes.id
-    WHERE random IS FALSE
+    WHERE random IS FALSE AND {}
     GROUP BY memes.title
     ORDER BY COUNT(*) DESC
     LIMIT 1;
-    "#, &[])?;
-
-    let row = rows.get(0);
-
-    let most_requested_meme = row.get(0);
-    let most_requested_meme_count: i64 = row.get(1);
+    "#, in_range), params)?);
 
-    let rows = raw_conn.query(r#"
+    let most_random_meme = top_row::<String>(&raw_conn.query(&format!(r#"
     SELECT memes.title, COUNT(*) FROM invocation_records
     INNER JOIN memes ON meme_id = memes.id
-    WHERE random IS TRUE
+    WHERE random IS TRUE AND {}
     GROUP BY memes.title
     ORDER BY COUNT(*) DESC
     LIMIT 1;
-    "#, &[])?;
-
-    let row = rows.get(0);
+    "#, in_range), params)?);
 
-    let most_random_meme = row.get(0);
-    let most_random_meme_count: i64 = row.get(1);
-
-    let rows = raw_conn.query(r#"
+    let most_invoked_meme = top_row::<String>(&raw_conn.query(&format!(r#"
     SELECT memes.title, COUNT(*) FROM invocation_records
     INNER JOIN memes ON meme_id = memes.id
+    WHERE {}
     GROUP BY memes.title
     ORDER BY COUNT(*) DESC
     LIMIT 1;
-    "#, &[])?;
-
-    let row = rows.get(0);
+    "#, in_range), params)?);
 
-    let most_invoked_meme = row.get(0);
-    let most_invoked_meme_count: i64 = row.get(1);
+    let to_user = |(user_id, count): (i64, usize)| (user_id as u64, count);
 
     Ok(Stats {
+        range: *range,
+
         memes_overall: total_count as usize,
         image_memes: image_count as usize,
         audio_memes: audio_count as usize,
-        started_recording,
+        new_memes: new_memes as usize,
+
+        started_recording: started_recording.map(to_utc),
         total_meme_invocations: total_meme_invocations as usize,
         audio_meme_invocations: audio_meme_invocations as usize,
         random_meme_invocations: random_meme_invocations as usize,
 
-        most_active_day,
-        most_active_day_count: most_active_day_count as usize,
-        most_audio_active_day: most_active_audio_day,
-        most_audio_active_count: most_active_audio_day_count as usize,
+        most_active_day: most_active_day.map(|(day, count)| (to_utc_date(day), count)),
+        most_audio_active_day: most_audio_active_day.map(|(day, count)| (to_utc_date(day), count)),
 
-        most_random_meme_user: most_random_invoker as u64,
-        most_random_meme_user_count: most_random_invoker_count as usize,
-        most_directly_named_meme_user: most_specific_invoker as u64,
-        most_directly_named_meme_count: most_specific_invoker_count as usize,
+        most_random_meme_user: most_random_invoker.map(to_user),
+        most_directly_named_meme_user: most_specific_invoker.map(to_user),
 
         most_popular_named_meme: most_requested_meme,
-        most_popular_named_meme_count: most_requested_meme_count as usize,
-
         most_popular_random_meme: most_random_meme,
-        most_popular_random_meme_count: most_random_meme_count as usize,
-
         most_popular_meme_overall: most_invoked_meme,
-        most_popular_meme_overall_count: most_invoked_meme_count as usize,
     })
 }
 
@@ -517,26 +1129,46 @@ pub struct MemerInfo {
     pub most_used_meme_count: usize,
 }
 
-pub fn memers() -> Result<Vec<MemerInfo>> {
+/// Every tag in use on a live meme, with how many memes carry it, most popular first.
+pub fn tag_counts() -> Result<Vec<(String, i64)>> {
     let raw_conn = raw_connection()?;
 
     let rows = raw_conn.query(r#"
-    WITH random_count AS (
-        SELECT user_id, COUNT(*) as count
-        FROM invocation_records
-        WHERE random = TRUE
-        GROUP BY user_id
+    SELECT tag, COUNT(*) AS ct
+    FROM meme_tags
+    INNER JOIN memes ON memes.id = meme_tags.meme_id
+    GROUP BY tag
+    ORDER BY ct DESC, tag ASC
+    "#, &[])?;
+
+    Ok(rows.iter().map(|row| (row.get(0), row.get(1))).collect())
+}
+
+pub fn memers(range: &TimeRange) -> Result<Vec<MemerInfo>> {
+    let raw_conn = raw_connection()?;
+
+    let rows = raw_conn.query(r#"
+    WITH in_range AS (
+        SELECT * FROM invocation_records
+        WHERE ($1::TIMESTAMP IS NULL OR time >= $1)
+          AND ($2::TIMESTAMP IS NULL OR time < $2)
     ),
+         random_count AS (
+             SELECT user_id, COUNT(*) as count
+             FROM in_range
+             WHERE random = TRUE
+             GROUP BY user_id
+         ),
          specific_count AS (
              SELECT user_id, COUNT(*) as count
-             FROM invocation_records
+             FROM in_range
              WHERE random = FALSE
              GROUP BY user_id
          ),
          user_meme_counts AS (
              SELECT user_id, meme_id, COUNT(meme_id) as meme_count
-             FROM invocation_records
-             WHERE EXISTS (SELECT id FROM memes WHERE id = invocation_records.meme_id)
+             FROM in_range
+             WHERE EXISTS (SELECT id FROM memes WHERE id = in_range.meme_id)
              GROUP BY user_id, meme_id
              ORDER BY user_id, meme_count DESC
          ),
@@ -551,13 +1183,13 @@ pub fn memers() -> Result<Vec<MemerInfo>> {
              INNER JOIN most_memed_per_user ON user_meme_counts.user_id = most_memed_per_user.user_id
              WHERE user_meme_counts.meme_count = most_memed_per_user.max_count
          )
-    SELECT random_count.user_id, random_count.count, specific_count.count, memes.title, most_memed.meme_count
-    FROM random_count
-    INNER JOIN most_memed ON most_memed.user_id = random_count.user_id
-    INNER JOIN specific_count ON specific_count.user_id = random_count.user_id
+    SELECT most_memed.user_id, COALESCE(random_count.count, 0), COALESCE(specific_count.count, 0), memes.title, most_memed.meme_count
+    FROM most_memed
+    LEFT JOIN random_count ON random_count.user_id = most_memed.user_id
+    LEFT JOIN specific_count ON specific_count.user_id = most_memed.user_id
     INNER JOIN memes ON memes.id = most_memed.meme_id
-    ORDER BY (random_count.count + specific_count.count) DESC
-    "#, &[])?;
+    ORDER BY (COALESCE(random_count.count, 0) + COALESCE(specific_count.count, 0)) DESC
+    "#, &[&range.since, &range.until])?;
 
     let result = rows.iter().map(|row| {
         let user_id: i64 = row.get(0);
@@ -578,3 +1210,171 @@ pub fn memers() -> Result<Vec<MemerInfo>> {
 
     Ok(result)
 }
+
+/// Who has created the most live memes within `range`: `(user_id, memes)`, most first.
+pub fn creators(range: &TimeRange) -> Result<Vec<(u64, usize)>> {
+    let raw_conn = raw_connection()?;
+
+    let rows = raw_conn.query(r#"
+    SELECT metadata.created_by, COUNT(*) AS ct
+    FROM memes
+    INNER JOIN metadata ON memes.metadata_id = metadata.id
+    WHERE ($1::TIMESTAMP IS NULL OR metadata.created >= $1)
+      AND ($2::TIMESTAMP IS NULL OR metadata.created < $2)
+    GROUP BY metadata.created_by
+    ORDER BY ct DESC, metadata.created_by ASC
+    "#, &[&range.since, &range.until])?;
+
+    Ok(rows.iter().map(|row| (row.get::<_, i64>(0) as u64, row.get::<_, i64>(1) as usize)).collect())
+}
+
+/// The buckets [`invocation_counts`] groups by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Bucket {
+    Day,
+    Week,
+}
+
+impl Bucket {
+    fn unit(self) -> &'static str {
+        match self {
+            Bucket::Day => "day",
+            Bucket::Week => "week",
+        }
+    }
+}
+
+/// Invocations within `range` per `bucket`, from the first invocation's through the last's,
+/// quiet ones included: `(start of bucket, total, audio)`.
+pub fn invocation_counts(range: &TimeRange, bucket: Bucket) -> Result<Vec<(NaiveDateTime, usize, usize)>> {
+    let raw_conn = raw_connection()?;
+
+    let rows = raw_conn.query(&format!(r#"
+    WITH in_range AS (
+        SELECT invocation_records.id, invocation_records.time, memes.audio_id IS NOT NULL AS audio
+        FROM invocation_records
+        LEFT JOIN memes ON memes.id = invocation_records.meme_id
+        WHERE {in_range}
+    ),
+         buckets AS (
+             SELECT generate_series(
+                 date_trunc('{unit}', MIN(time)),
+                 date_trunc('{unit}', MAX(time)),
+                 '1 {unit}'
+             ) AS bucket
+             FROM in_range
+         )
+    SELECT buckets.bucket, COUNT(in_range.id), COUNT(in_range.id) FILTER (WHERE in_range.audio)
+    FROM buckets
+    LEFT JOIN in_range ON date_trunc('{unit}', in_range.time) = buckets.bucket
+    GROUP BY buckets.bucket
+    ORDER BY buckets.bucket
+    "#, in_range = INVOCATIONS_IN_RANGE, unit = bucket.unit()), &[&range.since, &range.until])?;
+
+    Ok(rows.iter()
+        .map(|row| (row.get(0), row.get::<_, i64>(1) as usize, row.get::<_, i64>(2) as usize))
+        .collect())
+}
+
+/// The `limit` users with the most invocations within `range`: `(user_id, invocations)`, most
+/// first.
+pub fn user_invocations(range: &TimeRange, limit: usize) -> Result<Vec<(u64, usize)>> {
+    let raw_conn = raw_connection()?;
+
+    let rows = raw_conn.query(&format!(r#"
+    SELECT user_id, COUNT(*) AS ct
+    FROM invocation_records
+    WHERE {}
+    GROUP BY user_id
+    ORDER BY ct DESC, user_id ASC
+    LIMIT $3
+    "#, INVOCATIONS_IN_RANGE), &[&range.since, &range.until, &(limit as i64)])?;
+
+    Ok(rows.iter().map(|row| (row.get::<_, i64>(0) as u64, row.get::<_, i64>(1) as usize)).collect())
+}
+
+/// The most invoked meme within `range` and how many times it was invoked, if anything was.
+pub fn top_meme(range: &TimeRange) -> Result<Option<(String, usize)>> {
+    let raw_conn = raw_connection()?;
+
+    Ok(top_row::<String>(&raw_conn.query(&format!(r#"
+    SELECT memes.title, COUNT(*) FROM invocation_records
+    INNER JOIN memes ON meme_id = memes.id
+    WHERE {}
+    GROUP BY memes.title
+    ORDER BY COUNT(*) DESC
+    LIMIT 1;
+    "#, INVOCATIONS_IN_RANGE), &[&range.since, &range.until])?))
+}
+
+/// How one meme has been used, from `invocation_records`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct MemeStats {
+    pub total: usize,
+    pub random: usize,
+    pub named: usize,
+    pub first: Option<NaiveDateTime>,
+    pub last: Option<NaiveDateTime>,
+    /// `(user_id, invocations)`, most invocations first.
+    pub top_invokers: Vec<(u64, usize)>,
+    /// Invocations in each month from the first invocation's through the current one, oldest first.
+    pub monthly: Vec<(NaiveDateTime, usize)>,
+}
+
+pub fn meme_stats(meme: &Meme, top_invokers: usize) -> Result<MemeStats> {
+    let raw_conn = raw_connection()?;
+
+    let totals = raw_conn.query(r#"
+    SELECT COUNT(*), COUNT(*) FILTER (WHERE random), MIN(time), MAX(time)
+    FROM invocation_records
+    WHERE meme_id = $1
+    "#, &[&meme.id])?;
+
+    let totals = totals.get(0);
+    let total = totals.get::<_, i64>(0) as usize;
+    let random = totals.get::<_, i64>(1) as usize;
+
+    let top_invokers = raw_conn.query(r#"
+    SELECT user_id, COUNT(*) AS ct
+    FROM invocation_records
+    WHERE meme_id = $1
+    GROUP BY user_id
+    ORDER BY ct DESC, user_id ASC
+    LIMIT $2
+    "#, &[&meme.id, &(top_invokers as i64)])?
+        .iter()
+        .map(|row| (row.get::<_, i64>(0) as u64, row.get::<_, i64>(1) as usize))
+        .collect();
+
+    // every month, including the quiet ones
+    let monthly = raw_conn.query(r#"
+    WITH months AS (
+        SELECT generate_series(
+            date_trunc('month', MIN(time)),
+            date_trunc('month', NOW() AT TIME ZONE 'UTC'),
+            '1 month'
+        ) AS month
+        FROM invocation_records
+        WHERE meme_id = $1
+    )
+    SELECT months.month, COUNT(invocation_records.id)
+    FROM months
+    LEFT JOIN invocation_records ON invocation_records.meme_id = $1
+        AND date_trunc('month', invocation_records.time) = months.month
+    GROUP BY months.month
+    ORDER BY months.month
+    "#, &[&meme.id])?
+        .iter()
+        .map(|row| (row.get(0), row.get::<_, i64>(1) as usize))
+        .collect();
+
+    Ok(MemeStats {
+        total,
+        random,
+        named: total - random,
+        first: totals.get(2),
+        last: totals.get(3),
+        top_invokers,
+        monthly,
+    })
+}
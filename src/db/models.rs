@@ -1,13 +1,22 @@
 use chrono::naive::NaiveDateTime;
+use fnv::FnvHashSet;
 use diesel::{
     Identifiable,
     Insertable,
     prelude::*,
     Queryable,
 };
+use log::warn;
 
 use crate::{
-    db::schema::*,
+    db::{
+        blob::{
+            self,
+            BLOBS,
+        },
+        perceptual,
+        schema::*,
+    },
     Error,
     Result,
 };
@@ -18,14 +27,52 @@ pub struct Meme {
     pub id: i32,
     pub title: String,
     pub content: Option<String>,
-    pub image_id: Option<i32>,
     pub audio_id: Option<i32>,
     pub metadata_id: i32,
+    pub volume: f32,
 }
 
 impl Meme {
-    pub fn image(&self, conn: &PgConnection) -> Option<Result<Image>> {
-        self.image_id.map(|x: i32| images::table.filter(images::id.eq(x)).first(conn).map_err(Error::from))
+    /// The meme's album, in order.
+    pub fn images(&self, conn: &PgConnection) -> Result<Vec<Image>> {
+        meme_images::table
+            .inner_join(images::table)
+            .filter(meme_images::meme_id.eq(self.id))
+            .order(meme_images::position)
+            .select(images::all_columns)
+            .load(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn image_ids(&self, conn: &PgConnection) -> Result<Vec<i32>> {
+        meme_images::table
+            .select(meme_images::image_id)
+            .filter(meme_images::meme_id.eq(self.id))
+            .order(meme_images::position)
+            .load(conn)
+            .map_err(Error::from)
+    }
+
+    /// Replace the meme's album with `image_ids`, in that order.
+    fn set_images(&self, conn: &PgConnection, image_ids: &[i32]) -> Result<()> {
+        ::diesel::delete(meme_images::table)
+            .filter(meme_images::meme_id.eq(self.id))
+            .execute(conn)?;
+
+        let rows = image_ids.iter()
+            .enumerate()
+            .map(|(i, &image_id)| (
+                meme_images::meme_id.eq(self.id),
+                meme_images::position.eq(i as i32),
+                meme_images::image_id.eq(image_id),
+            ))
+            .collect::<Vec<_>>();
+
+        ::diesel::insert_into(meme_images::table)
+            .values(&rows)
+            .execute(conn)?;
+
+        Ok(())
     }
 
     pub fn audio(&self, conn: &PgConnection) -> Option<Result<Audio>> {
@@ -35,6 +82,94 @@ impl Meme {
     pub fn find(conn: &PgConnection, id: i32) -> Result<Meme> {
         memes::table.find(id).get_result(conn).map_err(Error::from)
     }
+
+    pub fn by_title<T: AsRef<str>>(conn: &PgConnection, title: T) -> Result<Meme> {
+        memes::table
+            .filter(memes::title.eq(title.as_ref()))
+            .first(conn)
+            .map_err(Error::from)
+    }
+
+    /// Apply `changes` in place, leaving one audit record per changed field.
+    pub fn update(&self, conn: &PgConnection, changes: &MemeChanges, by_user: u64) -> Result<Meme> {
+        conn.transaction::<_, Error, _>(|| {
+            let descriptions = changes.describe(self, &self.image_ids(conn)?);
+
+            let updated = if changes.title.is_some() || changes.content.is_some() || changes.audio_id.is_some() {
+                ::diesel::update(memes::table.find(self.id))
+                    .set((
+                        changes.title.as_ref().map(|title| memes::title.eq(title)),
+                        changes.content.as_ref().map(|content| memes::content.eq(content)),
+                        changes.audio_id.map(|audio_id| memes::audio_id.eq(audio_id)),
+                    ))
+                    .get_result::<Meme>(conn)?
+            } else {
+                self.clone()
+            };
+
+            if let Some(ref image_ids) = changes.images {
+                self.set_images(conn, image_ids)?;
+            }
+
+            for description in descriptions {
+                AuditRecord::create(conn, self.metadata_id, by_user, Some(description))?;
+            }
+
+            Ok(updated)
+        })
+    }
+
+    pub fn tags(&self, conn: &PgConnection) -> Result<Vec<String>> {
+        meme_tags::table
+            .select(meme_tags::tag)
+            .filter(meme_tags::meme_id.eq(self.id))
+            .order(meme_tags::tag.asc())
+            .load(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn add_tags(&self, conn: &PgConnection, tags: &[String]) -> Result<usize> {
+        let rows = tags.iter()
+            .map(|tag| (meme_tags::meme_id.eq(self.id), meme_tags::tag.eq(tag)))
+            .collect::<Vec<_>>();
+
+        ::diesel::insert_into(meme_tags::table)
+            .values(&rows)
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn remove_tags(&self, conn: &PgConnection, tags: &[String]) -> Result<usize> {
+        ::diesel::delete(meme_tags::table)
+            .filter(meme_tags::meme_id.eq(self.id))
+            .filter(meme_tags::tag.eq_any(tags))
+            .execute(conn)
+            .map_err(Error::from)
+    }
+
+    pub fn add_alias(&self, conn: &PgConnection, alias: &str, by_user: u64) -> Result<MemeAlias> {
+        conn.transaction::<_, Error, _>(|| {
+            let alias = ::diesel::insert_into(meme_aliases::table)
+                .values((
+                    meme_aliases::alias.eq(alias),
+                    meme_aliases::meme_id.eq(self.id),
+                    meme_aliases::created_by.eq(by_user as i64),
+                ))
+                .get_result::<MemeAlias>(conn)?;
+
+            AuditRecord::create(conn, self.metadata_id, by_user, Some(format!("alias added: {:?}", alias.alias)))?;
+
+            Ok(alias)
+        })
+    }
+
+    pub fn set_volume(&self, conn: &PgConnection, volume: f32) -> Result<Meme> {
+        ::diesel::update(memes::table.find(self.id))
+            .set(memes::volume.eq(volume))
+            .get_result(conn)
+            .map_err(Error::from)
+    }
 }
 
 #[derive(Insertable, PartialEq, Debug, Clone)]
@@ -42,54 +177,126 @@ impl Meme {
 pub struct NewMeme {
     pub title: String,
     pub content: Option<String>,
-    pub image_id: Option<i32>,
     pub audio_id: Option<i32>,
     pub metadata_id: i32,
 }
 
 impl NewMeme {
-    pub fn save(mut self, conn: &PgConnection, by_user: u64) -> Result<Meme> {
-        let metadata = Metadata::create(conn, by_user)?;
+    /// Save the meme with `image_ids` as its album.
+    pub fn save(mut self, conn: &PgConnection, image_ids: &[i32], by_user: u64) -> Result<Meme> {
+        conn.transaction::<_, Error, _>(|| {
+            let metadata = Metadata::create(conn, by_user)?;
 
-        self.metadata_id = metadata.id;
+            self.metadata_id = metadata.id;
 
-        ::diesel::insert_into(memes::table)
-            .values(&self)
-            .get_result::<Meme>(conn)
-            .map_err(Error::from)
+            let meme = ::diesel::insert_into(memes::table)
+                .values(&self)
+                .get_result::<Meme>(conn)?;
+
+            meme.set_images(conn, image_ids)?;
+
+            Ok(meme)
+        })
     }
 }
 
+/// A partial update to a meme. `None` leaves a field alone; for nullable fields, `Some(None)`
+/// clears it. `images` replaces the whole album.
+#[derive(PartialEq, Debug, Clone, Default)]
+pub struct MemeChanges {
+    pub title: Option<String>,
+    pub content: Option<Option<String>>,
+    pub images: Option<Vec<i32>>,
+    pub audio_id: Option<Option<i32>>,
+}
+
+impl MemeChanges {
+    pub fn is_empty(&self) -> bool {
+        self.title.is_none() && self.content.is_none() && self.images.is_none() && self.audio_id.is_none()
+    }
+
+    /// Human-readable descriptions of what these changes do to `meme`, whose album is currently
+    /// `image_ids`, skipping no-ops.
+    pub fn describe(&self, meme: &Meme, image_ids: &[i32]) -> Vec<String> {
+        fn id(id: &Option<i32>) -> String {
+            id.map_or_else(|| "none".to_owned(), |id| id.to_string())
+        }
+
+        let mut result = Vec::new();
+
+        if let Some(title) = self.title.as_ref().filter(|&t| t != &meme.title) {
+            result.push(format!("title: {:?} -> {:?}", meme.title, title));
+        }
+
+        if let Some(content) = self.content.as_ref().filter(|&c| c != &meme.content) {
+            result.push(format!("text: {:?} -> {:?}", meme.content, content));
+        }
+
+        if let Some(images) = self.images.as_ref().filter(|&i| i.as_slice() != image_ids) {
+            result.push(format!("images: {:?} -> {:?}", image_ids, images));
+        }
+
+        if let Some(audio_id) = self.audio_id.as_ref().filter(|&a| a != &meme.audio_id) {
+            result.push(format!("audio: {} -> {}", id(&meme.audio_id), id(audio_id)));
+        }
+
+        result
+    }
+}
+
+pub(crate) fn sha1(data: &[u8]) -> Vec<u8> {
+    let mut hash = ::sha1::Sha1::new();
+    hash.update(data);
+    hash.digest().bytes().to_vec()
+}
+
+pub(crate) fn sha256(data: &[u8]) -> Vec<u8> {
+    use sha2::Digest;
+    ::sha2::Sha256::digest(data).to_vec()
+}
 
 #[derive(Queryable, Identifiable, PartialEq, Debug)]
 #[table_name = "audio"]
 pub struct Audio {
     pub id: i32,
-    pub data: Vec<u8>,
+    /// `None` once the blob lives in the blob store; see [`Audio::contents`].
+    pub data: Option<Vec<u8>>,
     pub metadata_id: i32,
-    pub data_hash: Vec<u8>,
+    /// Legacy SHA-1 hash, only present on rows that predate `sha256`.
+    pub data_hash: Option<Vec<u8>>,
+    pub sha256: Option<Vec<u8>>,
+    /// Chromaprint fingerprint, for spotting near-duplicates.
+    pub fingerprint: Option<Vec<i32>>,
+    /// Whether fingerprinting has been tried, whether or not it worked.
+    pub perceptual_checked: bool,
 }
 
 impl Audio {
     pub fn create(conn: &PgConnection, data: Vec<u8>, by_user: u64) -> Result<i32> {
-        let mut data_hash = ::sha1::Sha1::new();
-        data_hash.update(&data);
-        let data_hash = data_hash.digest().bytes().to_vec();
+        let sha256 = sha256(&data);
 
+        // rows not yet backfilled with a sha256 can still only be matched by their sha1
         let id = audio::table
             .select(audio::id)
-            .filter(audio::data_hash.eq(&data_hash))
+            .filter(audio::sha256.eq(&sha256).or(audio::data_hash.eq(sha1(&data))))
             .get_results::<i32>(conn)?;
 
         if let Some(id) = id.first() {
             return Ok(*id);
         }
 
+        BLOBS.put(&blob::key(&sha256), &data)?;
+
         let metadata = Metadata::create(conn, by_user)?;
 
+        let fingerprint = audio_fingerprint(&data);
+
         let new_audio = NewAudio {
-            data,
-            data_hash,
+            data: None,
+            sha256: Some(sha256),
+            // without fpcalc, leave it for a backfill once there is one
+            perceptual_checked: fingerprint.is_some() || crate::audio::fpcalc_available(),
+            fingerprint,
             metadata_id: metadata.id,
         };
 
@@ -99,14 +306,28 @@ impl Audio {
             .get_result(conn)
             .map_err(Error::from)
     }
+
+    /// The blob itself, from wherever it's stored.
+    pub fn contents(&self) -> Result<Vec<u8>> {
+        match self.data {
+            Some(ref data) => Ok(data.clone()),
+            None => BLOBS.get(&self.blob_key()?),
+        }
+    }
+
+    pub fn blob_key(&self) -> Result<String> {
+        blob_key(self.id, &self.sha256, &self.data_hash)
+    }
 }
 
 #[derive(Insertable, PartialEq, Debug)]
 #[table_name = "audio"]
 pub struct NewAudio {
-    pub data: Vec<u8>,
+    pub data: Option<Vec<u8>>,
     pub metadata_id: i32,
-    pub data_hash: Vec<u8>,
+    pub sha256: Option<Vec<u8>>,
+    pub fingerprint: Option<Vec<i32>>,
+    pub perceptual_checked: bool,
 }
 
 
@@ -114,32 +335,42 @@ pub struct NewAudio {
 #[table_name="images"]
 pub struct Image {
     pub id: i32,
-    pub data: Vec<u8>,
+    /// `None` once the blob lives in the blob store; see [`Image::contents`].
+    pub data: Option<Vec<u8>>,
     pub metadata_id: i32,
-    pub data_hash: Vec<u8>,
+    /// Legacy SHA-1 hash, only present on rows that predate `sha256`.
+    pub data_hash: Option<Vec<u8>>,
     pub filename: String,
+    pub sha256: Option<Vec<u8>>,
+    /// dHash, for spotting near-duplicates.
+    pub phash: Option<i64>,
+    /// Whether hashing has been tried, whether or not it worked.
+    pub perceptual_checked: bool,
 }
 
 impl Image {
     pub fn create(conn: &PgConnection, filename: &str, data: Vec<u8>, by_user: u64) -> Result<i32> {
-        let mut data_hash = ::sha1::Sha1::new();
-        data_hash.update(&data);
-        let data_hash = data_hash.digest().bytes().to_vec();
+        let sha256 = sha256(&data);
 
+        // rows not yet backfilled with a sha256 can still only be matched by their sha1
         let id = images::table
             .select(images::id)
-            .filter(images::data_hash.eq(&data_hash))
+            .filter(images::sha256.eq(&sha256).or(images::data_hash.eq(sha1(&data))))
             .get_results::<i32>(conn)?;
 
         if let Some(id) = id.first() {
             return Ok(*id);
         }
 
+        BLOBS.put(&blob::key(&sha256), &data)?;
+
         let metadata = Metadata::create(conn, by_user)?;
 
         let new_image = NewImage {
-            data,
-            data_hash,
+            data: None,
+            sha256: Some(sha256),
+            phash: image_phash(&data),
+            perceptual_checked: true,
             filename: filename.to_owned(),
             metadata_id: metadata.id,
         };
@@ -150,15 +381,58 @@ impl Image {
             .get_result(conn)
             .map_err(Error::from)
     }
+
+    /// The blob itself, from wherever it's stored.
+    pub fn contents(&self) -> Result<Vec<u8>> {
+        match self.data {
+            Some(ref data) => Ok(data.clone()),
+            None => BLOBS.get(&self.blob_key()?),
+        }
+    }
+
+    pub fn blob_key(&self) -> Result<String> {
+        blob_key(self.id, &self.sha256, &self.data_hash)
+    }
 }
 
 #[derive(Insertable, PartialEq, Debug)]
 #[table_name="images"]
 pub struct NewImage {
-    pub data: Vec<u8>,
+    pub data: Option<Vec<u8>>,
     pub metadata_id: i32,
-    pub data_hash: Vec<u8>,
+    pub sha256: Option<Vec<u8>>,
     pub filename: String,
+    pub phash: Option<i64>,
+    pub perceptual_checked: bool,
+}
+
+/// The dHash of an image as stored, or `None` if it can't be decoded.
+pub(crate) fn image_phash(data: &[u8]) -> Option<i64> {
+    // videos are stored as images too, but aren't worth complaining about
+    if image::guess_format(data).is_err() {
+        return None;
+    }
+
+    perceptual::image_dhash(data)
+        .map_err(|e| warn!("hashing image: {}", e))
+        .ok()
+        .map(|hash| hash as i64)
+}
+
+/// The fingerprint of a clip as stored, or `None` if `fpcalc` couldn't produce one.
+pub(crate) fn audio_fingerprint(data: &[u8]) -> Option<Vec<i32>> {
+    crate::audio::fingerprint(data)
+        .map_err(|e| warn!("fingerprinting audio: {}", e))
+        .ok()
+        .map(|fp| fp.into_iter().map(|x| x as i32).collect())
+}
+
+/// Blobs are keyed by sha256 where a row has one, and by sha1 until it's backfilled.
+fn blob_key(id: i32, sha256: &Option<Vec<u8>>, sha1: &Option<Vec<u8>>) -> Result<String> {
+    sha256.as_ref()
+        .or(sha1.as_ref())
+        .map(|hash| blob::key(hash))
+        .ok_or_else(|| anyhow::anyhow!("blob {} has no hash", id))
 }
 
 
@@ -201,14 +475,16 @@ pub struct AuditRecord {
     pub updated: NaiveDateTime,
     pub updated_by: i64,
     pub metadata_id: i32,
+    pub description: Option<String>,
 }
 
 impl AuditRecord {
-    pub fn create(conn: &PgConnection, metadata: i32, by_user: u64) -> Result<AuditRecord> {
+    pub fn create<T: Into<String>>(conn: &PgConnection, metadata: i32, by_user: u64, description: Option<T>) -> Result<AuditRecord> {
         ::diesel::insert_into(audit_records::table)
             .values(&NewAuditRecord {
                 updated_by: by_user as i64,
                 metadata_id: metadata,
+                description: description.map(Into::into),
             })
             .get_result::<AuditRecord>(conn)
             .map_err(Error::from)
@@ -220,18 +496,36 @@ impl AuditRecord {
 pub struct NewAuditRecord {
     pub updated_by: i64,
     pub metadata_id: i32,
+    pub description: Option<String>,
 }
 
-#[derive(Queryable, Identifiable, PartialEq, Debug)]
+/// Record of a deleted meme. Tombstones written since soft deletion was introduced carry the
+/// meme's contents, and can be restored until they're purged.
+#[derive(Queryable, Identifiable, PartialEq, Debug, Clone)]
 #[table_name="tombstones"]
 pub struct Tombstone {
     pub id: i32,
-    pub deleted: NaiveDateTime,
-    pub deleted_by: i64,
-    pub metadata_id: i32,
     pub meme_id: i32,
+    pub deleted_by: i64,
+    pub deleted_at: NaiveDateTime,
+    pub metadata_id: Option<i32>,
+    pub title: Option<String>,
+    pub content: Option<String>,
+    pub audio_id: Option<i32>,
+    pub volume: f32,
 }
 
+impl Tombstone {
+    /// The most recently deleted restorable memes.
+    pub fn recent(conn: &PgConnection, n: usize) -> Result<Vec<Tombstone>> {
+        tombstones::table
+            .filter(tombstones::title.is_not_null())
+            .order(tombstones::deleted_at.desc())
+            .limit(n as i64)
+            .load(conn)
+            .map_err(Error::from)
+    }
+}
 
 #[derive(Insertable, PartialEq, Debug)]
 #[table_name="tombstones"]
@@ -239,6 +533,24 @@ pub struct NewTombstone {
     pub deleted_by: i64,
     pub metadata_id: i32,
     pub meme_id: i32,
+    pub title: String,
+    pub content: Option<String>,
+    pub audio_id: Option<i32>,
+    pub volume: f32,
+}
+
+impl NewTombstone {
+    pub fn for_meme(meme: Meme, deleted_by: u64) -> Self {
+        NewTombstone {
+            deleted_by: deleted_by as i64,
+            metadata_id: meme.metadata_id,
+            meme_id: meme.id,
+            title: meme.title,
+            content: meme.content,
+            audio_id: meme.audio_id,
+            volume: meme.volume,
+        }
+    }
 }
 
 #[derive(Queryable, Identifiable, PartialEq, Debug)]
@@ -289,3 +601,63 @@ impl InvocationRecord {
             .map_err(Error::from)
     }
 }
+
+#[derive(Queryable, Identifiable, PartialEq, Debug, Clone)]
+#[table_name="meme_aliases"]
+#[primary_key(alias)]
+pub struct MemeAlias {
+    pub alias: String,
+    pub meme_id: i32,
+    pub created_by: i64,
+    pub created: NaiveDateTime,
+}
+
+impl MemeAlias {
+    /// Remove an alias, recording it against the meme it pointed to. Returns the removed alias.
+    pub fn remove(conn: &PgConnection, alias: &str, by_user: u64) -> Result<MemeAlias> {
+        conn.transaction::<_, Error, _>(|| {
+            let removed = ::diesel::delete(meme_aliases::table.find(alias))
+                .get_result::<MemeAlias>(conn)?;
+
+            if let Ok(meme) = Meme::find(conn, removed.meme_id) {
+                AuditRecord::create(conn, meme.metadata_id, by_user, Some(format!("alias removed: {:?}", removed.alias)))?;
+            }
+
+            Ok(removed)
+        })
+    }
+}
+
+#[derive(Queryable, Identifiable, PartialEq, Debug)]
+#[table_name="recording_opt_outs"]
+#[primary_key(user_id)]
+pub struct RecordingOptOut {
+    pub user_id: i64,
+    pub created: NaiveDateTime,
+}
+
+impl RecordingOptOut {
+    pub fn all(conn: &PgConnection) -> Result<FnvHashSet<u64>> {
+        let ids = recording_opt_outs::table
+            .select(recording_opt_outs::user_id)
+            .load::<i64>(conn)?;
+
+        Ok(ids.into_iter().map(|id| id as u64).collect())
+    }
+
+    pub fn add(conn: &PgConnection, user_id: u64) -> Result<()> {
+        ::diesel::insert_into(recording_opt_outs::table)
+            .values(recording_opt_outs::user_id.eq(user_id as i64))
+            .on_conflict_do_nothing()
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+
+    pub fn remove(conn: &PgConnection, user_id: u64) -> Result<()> {
+        ::diesel::delete(recording_opt_outs::table.find(user_id as i64))
+            .execute(conn)
+            .map(|_| ())
+            .map_err(Error::from)
+    }
+}
@@ -0,0 +1,144 @@
+use image::imageops::FilterType;
+
+use crate::Result;
+
+/// Images whose dHashes differ in at most this many of 64 bits are considered near-duplicates.
+pub const IMAGE_THRESHOLD: u32 = 10;
+
+/// Clips whose fingerprints agree on at least this fraction of bits are considered
+/// near-duplicates. Unrelated audio sits around 0.5.
+pub const AUDIO_THRESHOLD: f32 = 0.8;
+
+/// How far (in fingerprint items, ~1/8s each) one clip may be shifted against another.
+const MAX_OFFSET: isize = 24;
+
+/// The least overlap (in fingerprint items) worth comparing, unless a clip is shorter than this.
+const MIN_OVERLAP: usize = 16;
+
+const DHASH_WIDTH: u32 = 9;
+const DHASH_HEIGHT: u32 = 8;
+
+/// The 64-bit difference hash of an image: shrink to 9x8 greyscale and record whether each pixel
+/// is darker than its right-hand neighbour. Survives rescaling and recompression.
+pub fn image_dhash(data: &[u8]) -> Result<u64> {
+    let img = image::load_from_memory(data)?
+        .resize_exact(DHASH_WIDTH, DHASH_HEIGHT, FilterType::Triangle)
+        .to_luma();
+
+    Ok(dhash(img.as_raw()))
+}
+
+/// dHash of a row-major 9x8 greyscale image.
+fn dhash(pixels: &[u8]) -> u64 {
+    let width = DHASH_WIDTH as usize;
+    debug_assert_eq!(width * DHASH_HEIGHT as usize, pixels.len());
+
+    pixels.chunks(width)
+        .flat_map(|row| row.windows(2).map(|px| px[0] < px[1]))
+        .fold(0u64, |hash, bit| (hash << 1) | bit as u64)
+}
+
+#[inline]
+pub fn hamming(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// The best fraction of agreeing bits between two chromaprint fingerprints over every alignment
+/// within `MAX_OFFSET`.
+pub fn fingerprint_similarity(a: &[u32], b: &[u32]) -> f32 {
+    let min_overlap = MIN_OVERLAP.min(a.len()).min(b.len()).max(1);
+
+    (-MAX_OFFSET..=MAX_OFFSET)
+        .filter_map(|offset| {
+            let (a, b) = if offset >= 0 {
+                (a.get(offset as usize..)?, b)
+            } else {
+                (a, b.get((-offset) as usize..)?)
+            };
+
+            let overlap = a.len().min(b.len());
+            if overlap < min_overlap {
+                return None;
+            }
+
+            let errors = a.iter().zip(b).map(|(x, y)| (x ^ y).count_ones()).sum::<u32>();
+            Some(1. - errors as f32 / (32 * overlap) as f32)
+        })
+        .fold(0., f32::max)
+}
+
+/// Group `0..n` into the connected components of `edges`, dropping singletons. Each cluster is
+/// sorted, and clusters are ordered by their first member.
+pub fn clusters<I: IntoIterator<Item = (usize, usize)>>(n: usize, edges: I) -> Vec<Vec<usize>> {
+    fn find(parent: &mut [usize], mut x: usize) -> usize {
+        while parent[x] != x {
+            parent[x] = parent[parent[x]];
+            x = parent[x];
+        }
+
+        x
+    }
+
+    let mut parent = (0..n).collect::<Vec<_>>();
+
+    for (a, b) in edges {
+        let (ra, rb) = (find(&mut parent, a), find(&mut parent, b));
+        parent[ra.max(rb)] = ra.min(rb);
+    }
+
+    // roots are always the smallest member of their component
+    let mut groups = vec![Vec::new(); n];
+    for x in 0..n {
+        let root = find(&mut parent, x);
+        groups[root].push(x);
+    }
+
+    groups.into_iter()
+        .filter(|v| v.len() > 1)
+        .collect()
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_dhash() {
+        let ascending = (0..72).map(|i| (i % 9) as u8 * 10).collect::<Vec<_>>();
+        assert_eq!(u64::max_value(), dhash(&ascending));
+
+        let descending = ascending.iter().map(|p| 255 - p).collect::<Vec<_>>();
+        assert_eq!(0, dhash(&descending));
+
+        let mut brighter = ascending.iter().map(|p| p + 20).collect::<Vec<_>>();
+        assert_eq!(0, hamming(dhash(&ascending), dhash(&brighter)));
+
+        brighter[0] = 255;
+        assert_eq!(1, hamming(dhash(&ascending), dhash(&brighter)));
+    }
+
+    #[test]
+    fn test_fingerprint_similarity() {
+        let a = (0..100u32).map(|i| i.wrapping_mul(2654435761)).collect::<Vec<_>>();
+        assert_eq!(1., fingerprint_similarity(&a, &a));
+
+        // the same clip, starting a second later
+        assert_eq!(1., fingerprint_similarity(&a[8..], &a));
+        assert_eq!(1., fingerprint_similarity(&a, &a[8..]));
+
+        let inverted = a.iter().map(|x| !x).collect::<Vec<_>>();
+        assert_eq!(0., fingerprint_similarity(&a[..1], &inverted[..1]));
+
+        let noisy = a.iter().map(|x| x ^ 0b1010).collect::<Vec<_>>();
+        assert!(fingerprint_similarity(&a, &noisy) > AUDIO_THRESHOLD);
+
+        assert_eq!(0., fingerprint_similarity(&a, &[]));
+    }
+
+    #[test]
+    fn test_clusters() {
+        assert_eq!(Vec::<Vec<usize>>::new(), clusters(3, vec![]));
+        assert_eq!(vec![vec![0, 2, 4], vec![1, 3]], clusters(6, vec![(4, 2), (3, 1), (2, 0)]));
+        assert_eq!(vec![vec![0, 1]], clusters(2, vec![(0, 1), (1, 0), (1, 1)]));
+    }
+}
@@ -0,0 +1,204 @@
+use std::{
+    cmp::Ordering,
+    fmt,
+    str::FromStr,
+};
+
+use thiserror::Error;
+
+const SECS_PER_DAY: f64 = 24. * 60. * 60.;
+
+/// How long it takes an invocation to count for half as much under [`Rarity::AgeDecayed`].
+const HALF_LIFE_DAYS: f64 = 30.;
+
+/// How a rare meme is weighted. Higher weights are likelier picks.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Rarity {
+    /// Fewest invocations ever.
+    LeastPlayed,
+    /// Longest since the last invocation (or since being made, if it's never been invoked).
+    LeastRecent,
+    /// Fewest invocations for its age.
+    InverseFrequency,
+    /// Fewest invocations, with each counting for half as much every [`HALF_LIFE_DAYS`].
+    AgeDecayed,
+}
+
+impl Default for Rarity {
+    fn default() -> Self {
+        Rarity::InverseFrequency
+    }
+}
+
+#[derive(Clone, Debug, Error, PartialEq, Eq, Hash)]
+#[error("unknown rarity {0:?}")]
+pub struct UnknownRarity(pub String);
+
+impl FromStr for Rarity {
+    type Err = UnknownRarity;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().replace('_', "-").as_str() {
+            "least-played" | "played" | "plays" => Ok(Rarity::LeastPlayed),
+            "least-recent" | "recent" | "stale" => Ok(Rarity::LeastRecent),
+            "inverse-frequency" | "frequency" | "freq" => Ok(Rarity::InverseFrequency),
+            "age-decayed" | "decayed" | "decay" => Ok(Rarity::AgeDecayed),
+            _ => Err(UnknownRarity(s.to_owned())),
+        }
+    }
+}
+
+impl fmt::Display for Rarity {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(match self {
+            Rarity::LeastPlayed => "least-played",
+            Rarity::LeastRecent => "least-recent",
+            Rarity::InverseFrequency => "inverse-frequency",
+            Rarity::AgeDecayed => "age-decayed",
+        })
+    }
+}
+
+/// What a meme's rarity is judged on.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Usage {
+    pub meme_id: i32,
+    /// Seconds since the meme was made.
+    pub age: f64,
+    pub plays: usize,
+    /// Seconds since its latest invocation, if it's had any.
+    pub last_played: Option<f64>,
+    /// Seconds since each of its invocations, if [`Rarity::needs_play_ages`].
+    pub play_ages: Vec<f64>,
+}
+
+impl Rarity {
+    /// Whether weighing a meme takes the time of each of its invocations, not just how many there
+    /// were and the latest.
+    pub fn needs_play_ages(self) -> bool {
+        self == Rarity::AgeDecayed
+    }
+
+    /// How likely `usage`'s meme should be to come up, relative to others weighed the same way.
+    /// Always positive.
+    pub fn weight(self, usage: &Usage) -> f64 {
+        let plays = usage.plays as f64;
+
+        match self {
+            Rarity::LeastPlayed => 1. / (plays + 1.),
+            Rarity::LeastRecent => {
+                let since = usage.last_played.map_or(usage.age, |last| last.min(usage.age));
+                since.max(0.) / SECS_PER_DAY + 1.
+            },
+            Rarity::InverseFrequency => (usage.age.max(0.) / SECS_PER_DAY + 1.) / (plays + 1.),
+            Rarity::AgeDecayed => {
+                let decayed = usage.play_ages.iter()
+                    .map(|&ago| 0.5f64.powf(ago.max(0.) / SECS_PER_DAY / HALF_LIFE_DAYS))
+                    .sum::<f64>();
+
+                1. / (decayed + 1.)
+            },
+        }
+    }
+
+    /// Every meme with its weight, heaviest first.
+    pub fn rank(self, usages: &[Usage]) -> Vec<(i32, f64)> {
+        let mut ranked = usages.iter()
+            .map(|usage| (usage.meme_id, self.weight(usage)))
+            .collect::<Vec<_>>();
+
+        ranked.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal).then(a.0.cmp(&b.0)));
+
+        ranked
+    }
+}
+
+/// The index into `ranked` that a uniform `r` in `[0, 1)` lands on when each entry gets a slice
+/// the size of its weight.
+pub fn pick(ranked: &[(i32, f64)], r: f64) -> Option<usize> {
+    let total = ranked.iter().map(|&(_, weight)| weight).sum::<f64>();
+
+    if ranked.is_empty() || !(total > 0.) {
+        return None;
+    }
+
+    let target = r * total;
+    let mut cumulative = 0.;
+
+    for (i, &(_, weight)) in ranked.iter().enumerate() {
+        cumulative += weight;
+
+        if target < cumulative {
+            return Some(i);
+        }
+    }
+
+    // only reachable through rounding, with r just short of 1
+    Some(ranked.len() - 1)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn usage(meme_id: i32, age_days: f64, play_days: &[f64]) -> Usage {
+        let play_ages = play_days.iter().map(|d| d * SECS_PER_DAY).collect::<Vec<_>>();
+
+        Usage {
+            meme_id,
+            age: age_days * SECS_PER_DAY,
+            plays: play_ages.len(),
+            last_played: play_ages.iter().cloned().min_by(|a, b| a.partial_cmp(b).unwrap()),
+            play_ages,
+        }
+    }
+
+    fn order(rarity: Rarity, usages: &[Usage]) -> Vec<i32> {
+        rarity.rank(usages).into_iter().map(|(id, _)| id).collect()
+    }
+
+    #[test]
+    fn test_parse() {
+        assert_eq!(Ok(Rarity::LeastPlayed), "least-played".parse());
+        assert_eq!(Ok(Rarity::LeastRecent), "LEAST_RECENT".parse());
+        assert_eq!(Ok(Rarity::AgeDecayed), "decay".parse());
+        assert_eq!(Err(UnknownRarity("rarest".to_owned())), "rarest".parse::<Rarity>());
+
+        for &rarity in &[Rarity::LeastPlayed, Rarity::LeastRecent, Rarity::InverseFrequency, Rarity::AgeDecayed] {
+            assert_eq!(Ok(rarity), rarity.to_string().parse());
+        }
+    }
+
+    #[test]
+    fn test_weights() {
+        // 1: old and played a lot, long ago; 2: new and never played; 3: old, played once, recently
+        let usages = vec![
+            usage(1, 400., &[390., 380., 370., 360., 350.]),
+            usage(2, 2., &[]),
+            usage(3, 300., &[1.]),
+        ];
+
+        assert_eq!(vec![2, 3, 1], order(Rarity::LeastPlayed, &usages));
+        assert_eq!(vec![1, 2, 3], order(Rarity::LeastRecent, &usages));
+        assert_eq!(vec![3, 1, 2], order(Rarity::InverseFrequency, &usages));
+        assert_eq!(vec![2, 1, 3], order(Rarity::AgeDecayed, &usages));
+
+        for &rarity in &[Rarity::LeastPlayed, Rarity::LeastRecent, Rarity::InverseFrequency, Rarity::AgeDecayed] {
+            assert!(rarity.rank(&usages).iter().all(|&(_, weight)| weight > 0.));
+        }
+    }
+
+    #[test]
+    fn test_pick() {
+        assert_eq!(None, pick(&[], 0.5));
+        assert_eq!(None, pick(&[(1, 0.)], 0.5));
+
+        let ranked = vec![(1, 3.), (2, 1.), (3, 0.), (4, 1.)];
+
+        assert_eq!(Some(0), pick(&ranked, 0.));
+        assert_eq!(Some(0), pick(&ranked, 0.59));
+        assert_eq!(Some(1), pick(&ranked, 0.6));
+        assert_eq!(Some(3), pick(&ranked, 0.8));
+        assert_eq!(Some(3), pick(&ranked, 0.999_999));
+    }
+}
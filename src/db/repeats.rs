@@ -0,0 +1,242 @@
+use std::sync::Mutex;
+
+use anyhow::anyhow;
+use chrono::{
+    Duration,
+    Utc,
+};
+use diesel::prelude::*;
+use fnv::{
+    FnvHashMap,
+    FnvHashSet,
+};
+use lazy_static::lazy_static;
+use log::info;
+
+use crate::{
+    CONFIG,
+    Result,
+};
+
+use super::{
+    InvocationRecord,
+    invocations_in,
+    rarity::pick,
+    schema::invocation_records,
+    TimeRange,
+};
+
+/// How random picks treat memes that came up recently.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Repeats {
+    /// Anything goes.
+    Allow,
+    /// Recent memes are left out, unless nothing else is left.
+    Exclude,
+    /// Recent memes are this many times as likely as any other.
+    DownWeight(f64),
+    /// Every meme comes up once before any comes up again.
+    ShuffleBag,
+}
+
+/// Which query a shuffle bag holds memes for.
+type BagKey = (&'static str, Option<String>);
+
+lazy_static! {
+    static ref REPEATS: Result<Repeats> = {
+        let repeats = from_config();
+
+        if let Ok(ref repeats) = repeats {
+            info!("random memes avoid repeats with {:?}", repeats);
+        }
+
+        repeats
+    };
+
+    /// What's left in each shuffle bag.
+    static ref BAGS: Mutex<FnvHashMap<BagKey, Vec<i32>>> = Mutex::new(FnvHashMap::default());
+}
+
+fn from_config() -> Result<Repeats> {
+    let config = &CONFIG.random;
+
+    match config.repeats.as_str() {
+        "off" => Ok(Repeats::Allow),
+        "exclude" => Ok(Repeats::Exclude),
+        "downweight" if config.recent_weight >= 0. && config.recent_weight <= 1. =>
+            Ok(Repeats::DownWeight(config.recent_weight)),
+        "downweight" => Err(anyhow!("RECENT_WEIGHT should be between 0 and 1, not {}", config.recent_weight)),
+        "shufflebag" => Ok(Repeats::ShuffleBag),
+        other => Err(anyhow!("unknown RANDOM_REPEATS '{}'", other)),
+    }
+}
+
+/// The configured [`Repeats`], or why the settings are bad.
+fn repeats() -> Result<Repeats> {
+    match *REPEATS {
+        Ok(repeats) => Ok(repeats),
+        Err(ref e) => Err(anyhow!("bad random repeat settings: {}", e)),
+    }
+}
+
+/// Fail if the random repeat settings are bad, so that shows up at startup rather than on the first
+/// random meme.
+pub fn check_repeats() -> Result<()> {
+    repeats().map(|_| ())
+}
+
+/// Memes among the latest `RECENT_MEMES` invocations or invoked within `RECENT_HOURS`.
+fn recent_memes(conn: &PgConnection) -> Result<FnvHashSet<i32>> {
+    let config = &CONFIG.random;
+
+    let mut recent = InvocationRecord::last_n(conn, config.recent_memes)?
+        .into_iter()
+        .map(|record| record.meme_id)
+        .collect::<FnvHashSet<_>>();
+
+    if config.recent_hours > 0 {
+        let range = TimeRange {
+            since: Some((Utc::now() - Duration::hours(config.recent_hours)).naive_utc()),
+            until: None,
+        };
+
+        recent.extend(invocations_in(&range)
+            .select(invocation_records::meme_id)
+            .load::<i32>(conn)?);
+    }
+
+    Ok(recent)
+}
+
+/// Odds for each of `candidates`, in the same order, given which memes are `recent`. Not for
+/// [`Repeats::ShuffleBag`], which treats everything alike.
+fn weigh(candidates: &[i32], recent: &FnvHashSet<i32>, repeats: Repeats) -> Vec<(i32, f64)> {
+    let recent_weight = match repeats {
+        Repeats::Allow | Repeats::ShuffleBag => 1.,
+        Repeats::Exclude => 0.,
+        Repeats::DownWeight(weight) => weight,
+    };
+
+    let weighted = candidates.iter()
+        .map(|&id| (id, if recent.contains(&id) { recent_weight } else { 1. }))
+        .collect::<Vec<_>>();
+
+    // better a repeat than nothing
+    if weighted.iter().all(|&(_, weight)| weight == 0.) {
+        return candidates.iter().map(|&id| (id, 1.)).collect();
+    }
+
+    weighted
+}
+
+/// Take a meme out of `bag`, refilling it with all of `candidates` once it's empty. Memes that
+/// aren't `recent` go first where there are any, so the end of one round doesn't repeat the start
+/// of the next. `r` is uniform in `[0, 1)`.
+fn draw(bag: &mut Vec<i32>, candidates: &[i32], recent: &FnvHashSet<i32>, r: f64) -> Option<i32> {
+    let current = candidates.iter().collect::<FnvHashSet<_>>();
+
+    // memes deleted (or retagged) since the bag was filled
+    bag.retain(|id| current.contains(id));
+
+    if bag.is_empty() {
+        bag.extend(candidates);
+    }
+
+    let mut eligible = (0..bag.len())
+        .filter(|&i| !recent.contains(&bag[i]))
+        .collect::<Vec<_>>();
+
+    if eligible.is_empty() {
+        eligible = (0..bag.len()).collect();
+    }
+
+    if eligible.is_empty() {
+        return None;
+    }
+
+    let i = eligible[((r * eligible.len() as f64) as usize).min(eligible.len() - 1)];
+    Some(bag.swap_remove(i))
+}
+
+/// Pick one of `candidates` at random, steering clear of repeats as configured. `pool` and `tag`
+/// identify the query `candidates` came from, so each gets its own shuffle bag.
+pub(super) fn choose_fresh(
+    conn: &PgConnection,
+    pool: &'static str,
+    tag: Option<&str>,
+    candidates: &[i32],
+) -> Result<Option<i32>> {
+    use rand::prelude::*;
+
+    let repeats = repeats()?;
+    let r = thread_rng().gen::<f64>();
+
+    let recent = match repeats {
+        Repeats::Allow => FnvHashSet::default(),
+        _ => recent_memes(conn)?,
+    };
+
+    match repeats {
+        Repeats::ShuffleBag => {
+            let mut bags = BAGS.lock().unwrap();
+            let bag = bags.entry((pool, tag.map(str::to_owned))).or_default();
+
+            Ok(draw(bag, candidates, &recent, r))
+        },
+        repeats => Ok(pick(&weigh(candidates, &recent, repeats), r).map(|i| candidates[i])),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn set(ids: &[i32]) -> FnvHashSet<i32> {
+        ids.iter().cloned().collect()
+    }
+
+    #[test]
+    fn test_weigh() {
+        let candidates = [1, 2, 3];
+        let recent = set(&[2, 4]);
+
+        assert_eq!(vec![(1, 1.), (2, 1.), (3, 1.)], weigh(&candidates, &recent, Repeats::Allow));
+        assert_eq!(vec![(1, 1.), (2, 0.), (3, 1.)], weigh(&candidates, &recent, Repeats::Exclude));
+        assert_eq!(vec![(1, 1.), (2, 0.25), (3, 1.)], weigh(&candidates, &recent, Repeats::DownWeight(0.25)));
+
+        // nothing but repeats left
+        assert_eq!(vec![(2, 1.)], weigh(&[2], &recent, Repeats::Exclude));
+        assert_eq!(vec![(2, 1.)], weigh(&[2], &recent, Repeats::DownWeight(0.)));
+        assert_eq!(vec![(2, 0.25)], weigh(&[2], &recent, Repeats::DownWeight(0.25)));
+
+        assert_eq!(Vec::<(i32, f64)>::new(), weigh(&[], &recent, Repeats::Exclude));
+    }
+
+    #[test]
+    fn test_draw() {
+        let candidates = [1, 2, 3, 4];
+        let mut bag = Vec::new();
+
+        // a full round hits everything once, starting without the recent meme
+        let mut round = (0..4)
+            .map(|_| draw(&mut bag, &candidates, &set(&[3]), 0.))
+            .collect::<Option<Vec<_>>>()
+            .unwrap();
+
+        assert_ne!(Some(&3), round.first());
+        assert_eq!(Some(&3), round.last());
+
+        round.sort();
+        assert_eq!(vec![1, 2, 3, 4], round);
+        assert!(bag.is_empty());
+
+        // memes that are gone drop out of the bag
+        bag = vec![1, 5, 6];
+        assert_eq!(Some(1), draw(&mut bag, &candidates, &set(&[]), 0.999));
+        assert!(bag.is_empty());
+
+        // everything recent still refills
+        assert_eq!(Some(2), draw(&mut bag, &[2], &set(&[2]), 0.5));
+        assert_eq!(None, draw(&mut bag, &[], &set(&[]), 0.5));
+    }
+}
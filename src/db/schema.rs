@@ -1,9 +1,12 @@
 table! {
     audio (id) {
         id -> Int4,
-        data -> Bytea,
+        data -> Nullable<Bytea>,
         metadata_id -> Int4,
-        data_hash -> Bytea,
+        data_hash -> Nullable<Bytea>,
+        sha256 -> Nullable<Bytea>,
+        fingerprint -> Nullable<Array<Int4>>,
+        perceptual_checked -> Bool,
     }
 }
 
@@ -13,16 +16,20 @@ table! {
         updated -> Timestamp,
         updated_by -> Int8,
         metadata_id -> Int4,
+        description -> Nullable<Text>,
     }
 }
 
 table! {
     images (id) {
         id -> Int4,
-        data -> Bytea,
+        data -> Nullable<Bytea>,
         metadata_id -> Int4,
-        data_hash -> Bytea,
+        data_hash -> Nullable<Bytea>,
         filename -> Varchar,
+        sha256 -> Nullable<Bytea>,
+        phash -> Nullable<Int8>,
+        perceptual_checked -> Bool,
     }
 }
 
@@ -37,14 +44,38 @@ table! {
     }
 }
 
+table! {
+    meme_aliases (alias) {
+        alias -> Varchar,
+        meme_id -> Int4,
+        created_by -> Int8,
+        created -> Timestamp,
+    }
+}
+
+table! {
+    meme_images (meme_id, position) {
+        meme_id -> Int4,
+        position -> Int4,
+        image_id -> Int4,
+    }
+}
+
+table! {
+    meme_tags (meme_id, tag) {
+        meme_id -> Int4,
+        tag -> Varchar,
+    }
+}
+
 table! {
     memes (id) {
         id -> Int4,
         title -> Varchar,
         content -> Nullable<Text>,
-        image_id -> Nullable<Int4>,
         audio_id -> Nullable<Int4>,
         metadata_id -> Int4,
+        volume -> Float4,
     }
 }
 
@@ -56,6 +87,13 @@ table! {
     }
 }
 
+table! {
+    recording_opt_outs (user_id) {
+        user_id -> Int8,
+        created -> Timestamp,
+    }
+}
+
 table! {
     tombstones (id) {
         id -> Int4,
@@ -63,6 +101,10 @@ table! {
         deleted_by -> Int8,
         deleted_at -> Timestamp,
         metadata_id -> Nullable<Int4>,
+        title -> Nullable<Varchar>,
+        content -> Nullable<Text>,
+        audio_id -> Nullable<Int4>,
+        volume -> Float4,
     }
 }
 
@@ -70,9 +112,10 @@ joinable!(audio -> metadata (metadata_id));
 joinable!(audit_records -> metadata (metadata_id));
 joinable!(images -> metadata (metadata_id));
 joinable!(memes -> audio (audio_id));
-joinable!(memes -> images (image_id));
+joinable!(meme_images -> images (image_id));
 joinable!(memes -> metadata (metadata_id));
 joinable!(tombstones -> metadata (metadata_id));
+joinable!(tombstones -> audio (audio_id));
 joinable!(invocation_records -> memes (meme_id));
 
 allow_tables_to_appear_in_same_query!(
@@ -80,7 +123,11 @@ allow_tables_to_appear_in_same_query!(
     audit_records,
     images,
     invocation_records,
+    meme_aliases,
+    meme_images,
+    meme_tags,
     memes,
     metadata,
+    recording_opt_outs,
     tombstones,
 );
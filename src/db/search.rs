@@ -0,0 +1,124 @@
+use std::cmp::Ordering;
+
+use thiserror::Error;
+
+use crate::Result;
+
+use super::{
+    Meme,
+    raw_connection,
+};
+
+/// How close the runner-up has to score to the best match for a search to be ambiguous.
+const AMBIGUITY_MARGIN: f32 = 0.1;
+
+/// Most candidates offered back when a search is ambiguous.
+const MAX_SUGGESTIONS: usize = 3;
+
+#[derive(Clone, Debug, Error, PartialEq, Eq, Hash)]
+#[error("search could mean any of {0:?}")]
+pub struct Ambiguous(pub Vec<String>);
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Choice<T> {
+    Nothing,
+    One(T),
+    Ambiguous(Vec<T>),
+}
+
+/// Pick the best of a set of scored candidates, or all of the best if they're too close to call.
+pub fn choose<T>(mut candidates: Vec<(T, f32)>) -> Choice<T> {
+    candidates.sort_by(|a, b| b.1.partial_cmp(&a.1).unwrap_or(Ordering::Equal));
+
+    let best = match candidates.first() {
+        Some((_, score)) => *score,
+        None => return Choice::Nothing,
+    };
+
+    let mut close = candidates.into_iter()
+        .take_while(|(_, score)| best - score < AMBIGUITY_MARGIN)
+        .take(MAX_SUGGESTIONS)
+        .map(|(x, _)| x)
+        .collect::<Vec<_>>();
+
+    if close.len() == 1 {
+        Choice::One(close.remove(0))
+    } else {
+        Choice::Ambiguous(close)
+    }
+}
+
+/// Rank memes against `search` by trigram similarity, weighting matches on the title (or an alias)
+/// twice as heavily as matches on the text. Scores run from 0 to 1.
+pub fn search_memes<T: AsRef<str>>(search: T, limit: usize) -> Result<Vec<(Meme, f32)>> {
+    let raw_conn = raw_connection()?;
+
+    let search = search.as_ref();
+    let pattern = format!("%{}%", search);
+
+    let rows = raw_conn.query(r#"
+    WITH scored AS (
+        SELECT
+            memes.*,
+            GREATEST(
+                similarity(title, $1),
+                word_similarity($1, title),
+                CASE WHEN title ILIKE $2 THEN 0.9 ELSE 0 END,
+                (SELECT MAX(GREATEST(similarity(alias, $1), word_similarity($1, alias)))
+                 FROM meme_aliases WHERE meme_aliases.meme_id = memes.id)
+            ) AS title_match,
+            GREATEST(
+                COALESCE(word_similarity($1, content), 0),
+                CASE WHEN content ILIKE $2 THEN 0.9 ELSE 0 END
+            ) AS content_match
+        FROM memes
+        WHERE title % $1 OR $1 <% title OR title ILIKE $2
+           OR $1 <% content OR content ILIKE $2
+           OR id IN (SELECT meme_id FROM meme_aliases WHERE alias % $1 OR $1 <% alias)
+    )
+    SELECT id, title, content, audio_id, metadata_id, volume,
+           ((2 * title_match + content_match) / 3)::REAL AS score
+    FROM scored
+    ORDER BY score DESC, id ASC
+    LIMIT $3
+    "#, &[
+        &search,
+        &pattern,
+        &(limit as i64),
+    ])?;
+
+    let result = rows.iter()
+        .map(|row| {
+            let meme = Meme {
+                id: row.get(0),
+                title: row.get(1),
+                content: row.get(2),
+                audio_id: row.get(3),
+                metadata_id: row.get(4),
+                volume: row.get(5),
+            };
+
+            (meme, row.get(6))
+        })
+        .collect();
+
+    Ok(result)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_choose() {
+        assert_eq!(Choice::<&str>::Nothing, choose(vec![]));
+        assert_eq!(Choice::One("a"), choose(vec![("a", 0.4)]));
+        assert_eq!(Choice::One("a"), choose(vec![("b", 0.5), ("a", 0.9), ("c", 0.2)]));
+
+        assert_eq!(Choice::Ambiguous(vec!["a", "b"]), choose(vec![("b", 0.85), ("a", 0.9), ("c", 0.2)]));
+        assert_eq!(
+            Choice::Ambiguous(vec!["a", "b", "c"]),
+            choose(vec![("a", 0.9), ("b", 0.9), ("c", 0.9), ("d", 0.9)]),
+        );
+    }
+}
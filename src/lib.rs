@@ -0,0 +1,41 @@
+#![feature(try_trait)]
+#![feature(pattern)]
+#![feature(concat_idents)]
+#![feature(associated_type_defaults)]
+#![feature(clamp)]
+
+#![feature(box_syntax, box_patterns)]
+
+// trash dependencies that can't be fucked to upgrade to ed. 2018
+#[macro_use] extern crate diesel;
+#[macro_use] extern crate pest_derive;
+#[macro_use] extern crate envconfig_derive;
+
+pub use self::util::*;
+pub use self::config::*;
+
+#[cfg(feature = "diesel")]
+pub mod db;
+
+#[cfg(feature = "games")]
+mod game;
+
+#[cfg(not(feature = "games"))]
+mod game {
+    use serenity::framework::StandardFramework;
+
+    #[inline]
+    fn register(f: StandardFramework) -> StandardFramework {
+        return f
+    }
+}
+
+mod commands;
+mod util;
+pub mod audio;
+mod config;
+pub mod log_setup;
+pub mod bot;
+
+pub type Error = anyhow::Error;
+pub type Result<T> = anyhow::Result<T>;
@@ -1,16 +1,3 @@
-#![feature(try_trait)]
-#![feature(pattern)]
-#![feature(concat_idents)]
-#![feature(associated_type_defaults)]
-#![feature(clamp)]
-
-#![feature(box_syntax, box_patterns)]
-
-// trash dependencies that can't be fucked to upgrade to ed. 2018
-#[macro_use] extern crate diesel;
-#[macro_use] extern crate pest_derive;
-#[macro_use] extern crate envconfig_derive;
-
 use std::{
     thread,
     time::{
@@ -24,34 +11,10 @@ use log::{
     info,
 };
 
-pub use self::util::*;
-pub use self::config::*;
-
-#[cfg(feature = "diesel")]
-mod db;
-
-#[cfg(feature = "games")]
-mod game;
-
-#[cfg(not(feature = "games"))]
-mod game {
-    use serenity::framework::StandardFramework;
-
-    #[inline]
-    fn register(f: StandardFramework) -> StandardFramework {
-        return f
-    }
-}
-
-mod commands;
-mod util;
-mod audio;
-mod config;
-mod log_setup;
-mod bot;
-
-pub type Error = anyhow::Error;
-pub type Result<T> = anyhow::Result<T>;
+use thulani::{
+    bot,
+    log_setup,
+};
 
 const BACKOFF_FACTOR: f64 = 2.0;
 const MAX_BACKOFFS: usize = 3;